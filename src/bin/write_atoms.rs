@@ -1,59 +1,98 @@
 use chrono::Utc;
+use clap::{Parser, ValueEnum};
+use log::{error, info, warn, Level, LevelFilter, Log, Metadata, Record};
+use rust_atomizer::call_graph::try_build_call_graph;
+use rust_atomizer::config::Config;
+use rust_atomizer::error::AtomizerError;
+use rust_atomizer::manifest::{
+    clone_git_repository, detect_project_layout, git_head_commit, git_repository_name, is_git_url,
+    write_standalone_cargo_toml, ProjectLayout,
+};
 use rust_atomizer::scip_to_call_graph_json::{
-    build_call_graph, parse_scip_json, write_call_graph_as_atoms_json,
+    compile_globs, parse_scip_json, render_dot_to_image, write_atoms_json_from_iter,
+    write_atoms_json_gz_from_iter, write_atoms_json_with_meta, write_json_gz, Atom, AtomsMetadata,
+    AtomsWithMeta, BuildOptions, DotOptions, SvgLayout, DEFAULT_LAYOUT_ITERATIONS,
+    DEFAULT_LAYOUT_MAX_NODES, DEFAULT_LAYOUT_SEED,
+};
+use rust_atomizer::tools::{
+    check_tool, check_tools, ExternalTool, GIT, GRAPHVIZ, RUST_ANALYZER, SCIP, VERUS_ANALYZER,
 };
-use std::env;
+use rust_atomizer::verus_parser::DEFAULT_MATCH_TOLERANCE;
 use std::fs::{self, OpenOptions};
-use std::io::Write;
-use std::path::Path;
-use std::process::Command;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// Default wall-clock limit for the analyzer run, in seconds
+const DEFAULT_ANALYZER_TIMEOUT_SECS: u64 = 3600;
+
+/// Layout of the per-run log file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines between a session header and footer
+    Text,
+    /// One JSON object per line with `ts`, `level`, `repo_id`, `user_id` and `message`
+    Json,
+}
 
+/// `log` backend that prints through `env_logger` (filtered by `RUST_LOG`) and
+/// keeps every message at `Info` or above for the per-run log file.
 struct AtomizerLogger {
+    console: env_logger::Logger,
     repo_id: String,
     user_id: String,
-    messages: Vec<String>,
+    format: LogFormat,
+    messages: Mutex<Vec<String>>,
     log_file_path: String,
 }
 
 impl AtomizerLogger {
-    fn new(repo_id: String, user_id: String) -> Result<Self, Box<dyn std::error::Error>> {
+    fn new(
+        repo_id: String,
+        user_id: String,
+        format: LogFormat,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         // Create logs directory if it doesn't exist
         fs::create_dir_all("logs")?;
 
         // Create timestamped log filename
         let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
-        let log_file_path = format!("logs/atomizer_{repo_id}_{timestamp}.log");
+        let extension = match format {
+            LogFormat::Text => "log",
+            LogFormat::Json => "jsonl",
+        };
+        let log_file_path = format!("logs/atomizer_{repo_id}_{timestamp}.{extension}");
+
+        let console =
+            env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+                .build();
 
         Ok(AtomizerLogger {
+            console,
             repo_id,
             user_id,
-            messages: Vec::new(),
+            format,
+            messages: Mutex::new(Vec::new()),
             log_file_path,
         })
     }
 
-    fn log(&mut self, level: &str, message: &str) {
-        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S UTC");
-        let log_entry = format!("[{timestamp}] [{level}] {message}");
-        self.messages.push(log_entry.clone());
-        // Also print to console for immediate feedback
-        println!("{log_entry}");
-    }
-
-    fn info(&mut self, message: &str) {
-        self.log("INFO", message);
-    }
-
-    fn error(&mut self, message: &str) {
-        self.log("ERROR", message);
-    }
-
-    fn warn(&mut self, message: &str) {
-        self.log("WARN", message);
+    /// Install as the global logger. The logger lives for the rest of the
+    /// process so the log file can still be written at exit.
+    fn install(self) -> Result<&'static Self, log::SetLoggerError> {
+        let logger: &'static Self = Box::leak(Box::new(self));
+        log::set_logger(logger)?;
+        log::set_max_level(logger.console.filter().max(LevelFilter::Info));
+        Ok(logger)
     }
 
     fn save_logs(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.messages.is_empty() {
+        let messages = self.messages.lock().unwrap();
+        if messages.is_empty() {
             return Ok(());
         }
 
@@ -61,88 +100,623 @@ impl AtomizerLogger {
             .create(true)
             .append(true)
             .open(&self.log_file_path)?;
+        self.write_session(&mut file, &messages)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Write the collected entries; JSON logs are bare lines, so they stay valid
+    /// JSON Lines when several runs append to the same file
+    fn write_session<W: Write>(&self, out: &mut W, messages: &[String]) -> std::io::Result<()> {
+        if self.format == LogFormat::Json {
+            for message in messages {
+                writeln!(out, "{message}")?;
+            }
+            return Ok(());
+        }
 
         // Write header with metadata
-        writeln!(file, "=== Atomizer Log Session ===")?;
-        writeln!(file, "Repo ID: {}", self.repo_id)?;
-        writeln!(file, "User ID: {}", self.user_id)?;
+        writeln!(out, "=== Atomizer Log Session ===")?;
+        writeln!(out, "Repo ID: {}", self.repo_id)?;
+        writeln!(out, "User ID: {}", self.user_id)?;
         writeln!(
-            file,
+            out,
             "Session Start: {}",
             Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
         )?;
-        writeln!(file, "================================")?;
-        writeln!(file)?;
+        writeln!(out, "================================")?;
+        writeln!(out)?;
 
         // Write all log messages
-        for message in &self.messages {
-            writeln!(file, "{message}")?;
+        for message in messages {
+            writeln!(out, "{message}")?;
         }
 
-        writeln!(file)?;
-        writeln!(file, "=== End of Session ===")?;
-        writeln!(file)?;
+        writeln!(out)?;
+        writeln!(out, "=== End of Session ===")?;
+        writeln!(out)
+    }
 
-        file.flush()?;
-        Ok(())
+    /// One log file entry for `record`
+    fn format_entry(&self, record: &Record) -> String {
+        let now = Utc::now();
+        match self.format {
+            LogFormat::Text => format!(
+                "[{}] [{}] {}",
+                now.format("%Y-%m-%d %H:%M:%S UTC"),
+                record.level(),
+                record.args()
+            ),
+            LogFormat::Json => serde_json::json!({
+                "ts": now.to_rfc3339(),
+                "level": record.level().as_str(),
+                "repo_id": self.repo_id,
+                "user_id": self.user_id,
+                "message": record.args().to_string(),
+            })
+            .to_string(),
+        }
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} <path-to-folder> <repo_id> [user_id]", args[0]);
-        std::process::exit(1);
+impl Log for AtomizerLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info || self.console.enabled(metadata)
     }
 
-    let folder_path = &args[1];
-    let repo_id = &args[2];
-    let user_id = args.get(3).map(|s| s.as_str()).unwrap_or("460176");
-
-    let mut logger = AtomizerLogger::new(repo_id.clone(), user_id.to_string())?;
-    logger.info(&format!(
-        "Starting atomizer for repo_id: {repo_id}, user_id: {user_id}"
-    ));
-
-    // Check if Cargo.toml exists, if not create one for standalone Rust files
-    let cargo_toml_path = Path::new(folder_path).join("Cargo.toml");
-    if !cargo_toml_path.exists() {
-        logger.info(&format!(
-            "Creating Cargo.toml for standalone Rust files in {folder_path}..."
-        ));
-
-        // Look for .rs files in the directory
-        let entries = fs::read_dir(folder_path)?;
-        let mut rust_files = Vec::new();
-
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("rs") {
-                if let Some(file_name) = path.file_stem().and_then(|s| s.to_str()) {
-                    rust_files.push((
-                        file_name.to_string(),
-                        path.file_name().unwrap().to_str().unwrap().to_string(),
-                    ));
-                }
+    fn log(&self, record: &Record) {
+        if record.level() <= Level::Info {
+            let log_entry = self.format_entry(record);
+            self.messages.lock().unwrap().push(log_entry);
+        }
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+    }
+}
+
+/// Output format for the generated call graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Atoms JSON consumed by the database loader
+    Json,
+    /// Graphviz DOT file
+    Dot,
+    /// Standalone SVG rendering
+    Svg,
+    /// PlantUML component diagram
+    Plantuml,
+    /// Nodes and links JSON for D3's force layout
+    D3,
+    /// Cypher script that loads the graph into Neo4j
+    Cypher,
+    /// PNG image rendered from the DOT output by Graphviz
+    Png,
+    /// PDF document rendered from the DOT output by Graphviz
+    Pdf,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Dot => "dot",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Plantuml => "puml",
+            OutputFormat::D3 => "d3.json",
+            OutputFormat::Cypher => "cypher",
+            OutputFormat::Png => "png",
+            OutputFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// SCIP indexer used to analyze the input folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Tool {
+    RustAnalyzer,
+    VerusAnalyzer,
+}
+
+impl Tool {
+    fn external(self) -> ExternalTool {
+        match self {
+            Tool::RustAnalyzer => RUST_ANALYZER,
+            Tool::VerusAnalyzer => VERUS_ANALYZER,
+        }
+    }
+}
+
+/// Generate atoms from a Rust project via SCIP.
+#[derive(Debug, Parser)]
+#[command(name = "write_atoms", version, about)]
+struct Cli {
+    /// Path to the folder containing Rust source code, or a git URL to clone
+    #[arg(long, short = 'i', value_name = "FOLDER")]
+    input: Option<String>,
+
+    /// Commit, tag or branch to check out when --input is a git URL
+    #[arg(long, value_name = "REV")]
+    rev: Option<String>,
+
+    /// Keep the temporary clone of a git URL input instead of deleting it
+    #[arg(long)]
+    keep: bool,
+
+    /// Repository identifier (used for logging)
+    #[arg(long)]
+    repo_id: Option<String>,
+
+    /// Config file [default: <FOLDER>/atomizer.toml if present]
+    #[arg(long, short = 'c', value_name = "PATH")]
+    config: Option<String>,
+
+    /// User identifier (used for logging) [default: 460176]
+    #[arg(long)]
+    user_id: Option<String>,
+
+    /// Output path [default: <folder_name>.<format> in the current directory]
+    #[arg(long, short = 'o', value_name = "PATH")]
+    output: Option<String>,
+
+    /// Path of the SCIP index written by the analyzer [default: index.scip]
+    #[arg(long, value_name = "PATH")]
+    scip_file: Option<String>,
+
+    /// Output format [default: json]
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Indexer used to generate the SCIP index [default: rust-analyzer]
+    #[arg(long, value_enum)]
+    tool: Option<Tool>,
+
+    /// Line tolerance when matching SCIP definitions to parsed spans [default: 15]
+    #[arg(long, value_name = "LINES")]
+    tolerance: Option<usize>,
+
+    /// List callees defined outside the project in atom `deps` (as `external:<path>`)
+    #[arg(long)]
+    include_external: bool,
+
+    /// Wrap the JSON atoms in an object with build metadata (version, indexer, time, commit)
+    #[arg(long)]
+    with_metadata: bool,
+
+    /// Gzip-compress the JSON output (written as `<folder_name>.json.gz` by default)
+    #[arg(long)]
+    compress: bool,
+
+    /// Only build the call edges; skip reading sources for function bodies
+    #[arg(long)]
+    skip_bodies: bool,
+
+    /// Leave out test functions and everything under `#[cfg(test)]`
+    #[arg(long)]
+    exclude_tests: bool,
+
+    /// Add an edge to every implementation of a called trait method that has several
+    #[arg(long)]
+    fan_out_trait_calls: bool,
+
+    /// Kill the analyzer if it runs longer than this [default: 3600]
+    #[arg(long, value_name = "SECONDS")]
+    analyzer_timeout: Option<u64>,
+
+    /// Fail if more than this many function bodies could not be read
+    #[arg(long, value_name = "N")]
+    max_body_failures: Option<usize>,
+
+    /// Also parse the bodies of this macro as items, like `verus!` (repeatable)
+    #[arg(long = "item-macro", value_name = "NAME")]
+    item_macros: Vec<String>,
+
+    /// Only output functions defined under this directory, e.g. `src/protocol`
+    #[arg(long, value_name = "PATH")]
+    subtree: Option<String>,
+
+    /// List calls leaving --subtree in atom deps as `external:` entries instead of
+    /// dropping them (implies --include-external)
+    #[arg(long)]
+    subtree_external_deps: bool,
+
+    /// Only output functions in files matching this glob, e.g. `src/crypto/**` (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Leave out functions in files matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Seed for the node positions of SVG output; change it to try another layout
+    #[arg(long, value_name = "N")]
+    layout_seed: Option<u64>,
+
+    /// Force simulation steps for SVG output [default: 200]
+    #[arg(long, value_name = "N")]
+    layout_iterations: Option<usize>,
+
+    /// Skip the SVG force simulation for graphs with more nodes, counted after
+    /// --subtree/--include/--exclude; nodes are never dropped and other formats
+    /// ignore it [default: 1000]
+    #[arg(long, value_name = "N")]
+    layout_max_nodes: Option<usize>,
+
+    /// Link DOT nodes to their source, e.g. `https://github.com/org/repo/blob/main/{path}#L{line}`
+    #[arg(long, value_name = "TEMPLATE")]
+    url_template: Option<String>,
+
+    /// Draw calls of trait methods in DOT output dashed (resolved to one impl) or
+    /// dotted (dispatched) instead of solid
+    #[arg(long)]
+    edge_kind_styles: bool,
+
+    /// Layout of the log file written to `logs/` [default: text]
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// Deprecated positional form: <path-to-folder> <repo_id> [user_id]
+    #[arg(hide = true, num_args = 0..=3)]
+    positional: Vec<String>,
+}
+
+/// Effective options after merging CLI flags, the config file and defaults
+struct Settings {
+    folder_path: String,
+    repo_id: String,
+    user_id: String,
+    config_path: Option<PathBuf>,
+    output: Option<String>,
+    scip_file: String,
+    format: OutputFormat,
+    tool: Tool,
+    tolerance: usize,
+    analyzer_timeout: Duration,
+    include_external: bool,
+    with_metadata: bool,
+    compress: bool,
+    skip_bodies: bool,
+    exclude_tests: bool,
+    fan_out_trait_calls: bool,
+    max_body_failures: Option<usize>,
+    url_template: Option<String>,
+    edge_kind_styles: bool,
+    item_macros: Vec<String>,
+    subtree: Option<String>,
+    subtree_external_deps: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    layout: SvgLayout,
+    log_format: LogFormat,
+}
+
+/// Parse a config file value with the same names the CLI accepts
+fn parse_value<T: ValueEnum>(field: &str, value: &str) -> Result<T, String> {
+    T::from_str(value, true).map_err(|_| format!("invalid {field} in config file: {value:?}"))
+}
+
+impl Cli {
+    /// Merge flags with the config file, falling back to the deprecated positional
+    /// arguments when `--input`/`--repo-id` are absent.
+    /// Precedence: CLI flags > config file > defaults.
+    fn resolve(&self) -> Result<Settings, String> {
+        let positional = |i: usize| self.positional.get(i).cloned();
+        let folder_path = self
+            .input
+            .clone()
+            .or_else(|| positional(0))
+            .ok_or("missing --input <FOLDER>")?;
+        let repo_id = self
+            .repo_id
+            .clone()
+            .or_else(|| positional(1))
+            .ok_or("missing --repo-id <REPO_ID>")?;
+
+        let config_path = match &self.config {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Config::discover(&folder_path),
+        };
+        let config = match &config_path {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        let format = match (self.format, &config.format) {
+            (Some(format), _) => format,
+            (None, Some(value)) => parse_value("format", value)?,
+            (None, None) => OutputFormat::Json,
+        };
+        let tool = match (self.tool, &config.tool) {
+            (Some(tool), _) => tool,
+            (None, Some(value)) => parse_value("tool", value)?,
+            (None, None) => Tool::RustAnalyzer,
+        };
+        let log_format = match (self.log_format, &config.log_format) {
+            (Some(log_format), _) => log_format,
+            (None, Some(value)) => parse_value("log_format", value)?,
+            (None, None) => LogFormat::Text,
+        };
+        let subtree_external_deps =
+            self.subtree_external_deps || config.subtree_external_deps.unwrap_or(false);
+        // Check the globs now rather than after a long analyzer run
+        let patterns_or_config = |flags: &[String], configured: Option<Vec<String>>| {
+            let patterns = if flags.is_empty() {
+                configured.unwrap_or_default()
+            } else {
+                flags.to_vec()
+            };
+            compile_globs(&patterns).map(|_| patterns)
+        };
+        let include = patterns_or_config(&self.include, config.include.clone())?;
+        let exclude = patterns_or_config(&self.exclude, config.exclude.clone())?;
+
+        Ok(Settings {
+            folder_path,
+            repo_id,
+            user_id: self
+                .user_id
+                .clone()
+                .or_else(|| positional(2))
+                .or(config.user_id)
+                .unwrap_or_else(|| "460176".to_string()),
+            config_path,
+            output: self.output.clone().or(config.output),
+            scip_file: self
+                .scip_file
+                .clone()
+                .or(config.scip_file)
+                .unwrap_or_else(|| "index.scip".to_string()),
+            format,
+            tool,
+            tolerance: self
+                .tolerance
+                .or(config.tolerance)
+                .unwrap_or(DEFAULT_MATCH_TOLERANCE),
+            analyzer_timeout: Duration::from_secs(
+                self.analyzer_timeout
+                    .or(config.analyzer_timeout)
+                    .unwrap_or(DEFAULT_ANALYZER_TIMEOUT_SECS),
+            ),
+            include_external: self.include_external
+                || config.include_external.unwrap_or(false)
+                || subtree_external_deps,
+            with_metadata: self.with_metadata || config.with_metadata.unwrap_or(false),
+            compress: self.compress || config.compress.unwrap_or(false),
+            skip_bodies: self.skip_bodies || config.skip_bodies.unwrap_or(false),
+            exclude_tests: self.exclude_tests || config.exclude_tests.unwrap_or(false),
+            fan_out_trait_calls: self.fan_out_trait_calls
+                || config.fan_out_trait_calls.unwrap_or(false),
+            max_body_failures: self.max_body_failures.or(config.max_body_failures),
+            url_template: self.url_template.clone().or(config.url_template),
+            edge_kind_styles: self.edge_kind_styles || config.edge_kind_styles.unwrap_or(false),
+            item_macros: if self.item_macros.is_empty() {
+                config.item_macros.unwrap_or_default()
+            } else {
+                self.item_macros.clone()
+            },
+            subtree: self.subtree.clone().or(config.subtree),
+            subtree_external_deps,
+            include,
+            exclude,
+            layout: SvgLayout {
+                seed: self
+                    .layout_seed
+                    .or(config.layout_seed)
+                    .unwrap_or(DEFAULT_LAYOUT_SEED),
+                iterations: self
+                    .layout_iterations
+                    .or(config.layout_iterations)
+                    .unwrap_or(DEFAULT_LAYOUT_ITERATIONS),
+                max_nodes: self
+                    .layout_max_nodes
+                    .or(config.layout_max_nodes)
+                    .unwrap_or(DEFAULT_LAYOUT_MAX_NODES),
+            },
+            log_format,
+        })
+    }
+}
+
+/// Create any missing parent directories of `path` so the output can be
+/// written to a directory that does not exist yet (e.g. a CI artifacts dir).
+fn create_parent_dirs(path: &str) -> std::io::Result<()> {
+    match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+/// A child's pipe read on its own thread into a shared buffer, so the child never
+/// blocks on a full pipe while we wait for it
+struct PipeReader {
+    handle: JoinHandle<()>,
+    buf: Arc<Mutex<Vec<u8>>>,
+}
+
+impl PipeReader {
+    fn spawn<R: Read + Send + 'static>(mut pipe: R) -> Self {
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        let shared = Arc::clone(&buf);
+        let handle = thread::spawn(move || {
+            let mut chunk = [0; 8192];
+            while let Ok(n @ 1..) = pipe.read(&mut chunk) {
+                shared.lock().unwrap().extend_from_slice(&chunk[..n]);
             }
+        });
+        PipeReader { handle, buf }
+    }
+
+    /// Everything read so far; with `wait`, first read until the pipe is closed.
+    /// After a timeout, processes spawned by the child may still hold the pipe open,
+    /// so only wait once the child has exited on its own.
+    fn finish(self, wait: bool) -> Vec<u8> {
+        if wait {
+            let _ = self.handle.join();
         }
+        let captured = std::mem::take(&mut *self.buf.lock().unwrap());
+        captured
+    }
+}
 
-        if !rust_files.is_empty() {
-            // Create a basic Cargo.toml
-            let package_name = rust_files[0].0.clone();
-            let mut cargo_content = format!(
-                "[package]\nname = \"{package_name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n"
-            );
+/// Like [`Command::output`], but kill the process if it is still running after
+/// `timeout`. A timeout is returned as `ErrorKind::TimedOut`, with the stderr
+/// captured until then in the message.
+fn output_with_timeout(command: &mut Command, timeout: Duration) -> std::io::Result<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().map(PipeReader::spawn);
+    let stderr = child.stderr.take().map(PipeReader::spawn);
+    let finish = |pipe: Option<PipeReader>, wait: bool| {
+        pipe.map(|pipe| pipe.finish(wait)).unwrap_or_default()
+    };
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Output {
+                status,
+                stdout: finish(stdout, true),
+                stderr: finish(stderr, true),
+            });
+        }
+        if Instant::now() >= deadline {
+            // The process may exit on its own in the meantime; `wait` reaps it either way
+            let _ = child.kill();
+            child.wait()?;
+            let stderr = finish(stderr, false);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!(
+                    "timed out after {}s; stderr: {}",
+                    timeout.as_secs(),
+                    String::from_utf8_lossy(&stderr).trim()
+                ),
+            ));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
 
-            for (name, file_name) in rust_files {
-                cargo_content.push_str(&format!(
-                    "[[bin]]\nname = \"{name}\"\npath = \"{file_name}\"\n\n"
-                ));
+/// Clone the repository at `url` into a new temporary directory, returning the
+/// directory and the path of the clone inside it
+fn clone_input(url: &str, rev: Option<&str>) -> Result<(TempDir, PathBuf), AtomizerError> {
+    check_tool(&GIT)?;
+    let temp = tempfile::Builder::new().prefix("atomizer-").tempdir()?;
+    let dest = temp.path().join(git_repository_name(url));
+    clone_git_repository(url, rev, &dest)?;
+    Ok((temp, dest))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli = Cli::parse();
+    let mut settings = match cli.resolve() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("error: {e}\n\nFor more information, try '--help'.");
+            std::process::exit(2);
+        }
+    };
+
+    let logger = AtomizerLogger::new(
+        settings.repo_id.clone(),
+        settings.user_id.clone(),
+        settings.log_format,
+    )?
+    .install()?;
+    if !cli.positional.is_empty() {
+        warn!("Positional arguments are deprecated; use --input <FOLDER> --repo-id <REPO_ID> [--user-id <USER_ID>]");
+    }
+    info!(
+        "Starting atomizer for repo_id: {}, user_id: {}",
+        settings.repo_id, settings.user_id
+    );
+
+    // A git URL is cloned into a temporary directory, which is removed when we are
+    // done unless `--keep` is given. `process::exit` skips destructors, so failure
+    // paths below drop `clone_dir` explicitly.
+    let mut clone_dir = None;
+    if is_git_url(&settings.folder_path) {
+        let url = settings.folder_path.clone();
+        match &cli.rev {
+            Some(rev) => info!("Cloning {url} at {rev}..."),
+            None => info!("Cloning {url}..."),
+        }
+        let (temp, dest) = match clone_input(&url, cli.rev.as_deref()) {
+            Ok(cloned) => cloned,
+            Err(e) => {
+                error!("Failed to clone {url}: {e}");
+                logger.save_logs()?;
+                std::process::exit(1);
             }
+        };
+        // Resolve again so that an atomizer.toml inside the clone is picked up
+        cli.input = Some(dest.to_string_lossy().into_owned());
+        settings = match cli.resolve() {
+            Ok(settings) => settings,
+            Err(e) => {
+                error!("{e}");
+                drop(temp);
+                logger.save_logs()?;
+                std::process::exit(1);
+            }
+        };
+        if cli.keep {
+            info!("Keeping the clone in {}", temp.keep().display());
+        } else {
+            clone_dir = Some(temp);
+        }
+    } else if cli.rev.is_some() {
+        warn!("--rev only applies when --input is a git URL; ignoring it");
+    }
+    let folder_path = &settings.folder_path;
+    if let Some(config_path) = &settings.config_path {
+        info!("Using config file {}", config_path.display());
+    }
 
-            fs::write(&cargo_toml_path, cargo_content)?;
-            logger.info("Created Cargo.toml for standalone Rust files");
+    // Fail early, with install instructions, if a program we shell out to is missing
+    let mut required = vec![settings.tool.external(), SCIP];
+    if matches!(settings.format, OutputFormat::Png | OutputFormat::Pdf) {
+        required.push(GRAPHVIZ);
+    }
+    match check_tools(&required) {
+        Ok(versions) => {
+            for (tool, version) in required.iter().zip(&versions) {
+                info!("Found {}: {version}", tool.binary);
+            }
+        }
+        Err(e) => {
+            error!("{e}");
+            drop(clone_dir);
+            logger.save_logs()?;
+            std::process::exit(1);
+        }
+    }
+
+    // Use an existing Cargo.toml as-is; only synthesize one for loose sources
+    match detect_project_layout(Path::new(folder_path))? {
+        ProjectLayout::Package => {
+            info!("Using existing package manifest in {folder_path}");
+        }
+        ProjectLayout::VirtualWorkspace => {
+            info!(
+                "Found virtual workspace manifest in {folder_path}; indexing workspace root as-is"
+            );
+        }
+        ProjectLayout::Standalone => {
+            info!("Creating Cargo.toml for standalone Rust files in {folder_path}...");
+            // Register every .rs file in the tree (skipping target/ and hidden dirs)
+            if write_standalone_cargo_toml(Path::new(folder_path))? {
+                info!("Created Cargo.toml for standalone Rust files");
+            } else {
+                warn!("No Rust source files found in {folder_path}");
+            }
         }
     }
 
@@ -150,60 +724,235 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .file_name()
         .and_then(|name| name.to_str())
         .unwrap_or("output");
-    let json_output_path = format!("{folder}.json");
-    let scip_file = "index.scip";
-    let scip_json_file = "index_scip.json";
+    let compress = settings.compress && settings.format == OutputFormat::Json;
+    if settings.compress && !compress {
+        warn!("--compress only applies to JSON output; ignoring it");
+    }
+    let output_path = settings.output.clone().unwrap_or_else(|| {
+        let extension = settings.format.extension();
+        if compress {
+            format!("{folder}.{extension}.gz")
+        } else {
+            format!("{folder}.{extension}")
+        }
+    });
+    let scip_file = settings.scip_file.as_str();
+    let scip_json_file = format!(
+        "{}_scip.json",
+        Path::new(scip_file).with_extension("").display()
+    );
 
-    // Run verus-analyzer scip <path_to_folder>
-    logger.info(&format!("Running: verus-analyzer scip {folder_path}"));
-    let output = Command::new("verus-analyzer")
+    // Run <tool> scip <path_to_folder> --output <scip_file>
+    let tool = settings.tool.external().binary;
+    info!("Running: {tool} scip {folder_path} --output {scip_file}");
+    let mut analyzer = Command::new(tool);
+    analyzer
         .arg("scip")
         .arg(folder_path)
-        .output()?;
+        .arg("--output")
+        .arg(scip_file);
+    let output = match output_with_timeout(&mut analyzer, settings.analyzer_timeout) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Failed to run {tool}: {e}");
+            drop(clone_dir);
+            logger.save_logs()?;
+            std::process::exit(1);
+        }
+    };
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        logger.warn(&format!(
-            "Errors while running verus-analyzer scip: {stderr}"
-        ));
+        warn!("Errors while running {tool} scip: {stderr}");
     }
 
     // Run scip print --json > <folder>_scip.json
-    logger.info(&format!(
-        "Running: scip print --json {scip_file} > {scip_json_file}"
-    ));
-    let scip_print = Command::new("scip")
+    info!("Running: scip print --json {scip_file} > {scip_json_file}");
+    let scip_print = match Command::new("scip")
         .arg("print")
         .arg("--json")
         .arg(scip_file)
-        .output()?;
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Failed to run scip print: {e}");
+            drop(clone_dir);
+            logger.save_logs()?;
+            std::process::exit(1);
+        }
+    };
     if !scip_print.status.success() {
         let stderr = String::from_utf8_lossy(&scip_print.stderr);
-        let error_msg = format!("Failed to run scip print: {stderr}");
-        logger.error(&error_msg);
+        error!("Failed to run scip print: {stderr}");
         // Save logs before exiting
+        drop(clone_dir);
+        logger.save_logs()?;
+        std::process::exit(1);
+    }
+    if let Err(e) = std::fs::write(&scip_json_file, &scip_print.stdout) {
+        error!("Failed to write {scip_json_file}: {e}");
+        drop(clone_dir);
         logger.save_logs()?;
         std::process::exit(1);
     }
-    std::fs::write(scip_json_file, &scip_print.stdout)?;
 
-    logger.info(&format!("Parsing SCIP JSON from {scip_json_file}..."));
-    let scip_data = parse_scip_json(scip_json_file)?;
+    info!("Parsing SCIP JSON from {scip_json_file}...");
+    let scip_data = match parse_scip_json(&scip_json_file) {
+        Ok(scip_data) => scip_data,
+        Err(e) => {
+            error!("Failed to parse {scip_json_file}: {e}");
+            drop(clone_dir);
+            logger.save_logs()?;
+            std::process::exit(1);
+        }
+    };
 
-    logger.info("Building call graph...");
-    let call_graph = build_call_graph(&scip_data);
+    info!("Building call graph...");
+    let mut options = BuildOptions {
+        tolerance: settings.tolerance,
+        extract_bodies: !settings.skip_bodies,
+        exclude_tests: settings.exclude_tests,
+        fan_out_trait_calls: settings.fan_out_trait_calls,
+        ..BuildOptions::default()
+    };
+    options
+        .item_macros
+        .extend(settings.item_macros.iter().cloned());
+    let max_body_failures = settings.max_body_failures.unwrap_or(usize::MAX);
+    let mut call_graph = match try_build_call_graph(&scip_data, &options, max_body_failures) {
+        Ok((call_graph, warnings)) => {
+            for warning in &warnings {
+                warn!("{warning}");
+            }
+            call_graph
+        }
+        Err(e) => {
+            if let AtomizerError::BodyExtraction { warnings, .. } = &e {
+                for warning in warnings {
+                    warn!("{warning}");
+                }
+            }
+            error!("{e}");
+            drop(clone_dir);
+            logger.save_logs()?;
+            std::process::exit(1);
+        }
+    };
+    if let Some(subtree) = &settings.subtree {
+        call_graph = call_graph.retain_subtree(subtree, settings.subtree_external_deps);
+        info!("Kept {} functions under {subtree}", call_graph.len());
+    }
+    // The patterns were validated when the settings were resolved
+    if !settings.include.is_empty() {
+        call_graph = call_graph.retain_by_glob(&settings.include)?;
+    }
+    if !settings.exclude.is_empty() {
+        call_graph = call_graph.exclude_by_glob(&settings.exclude)?;
+    }
+    info!("Call graph summary:\n{}", call_graph.summary());
 
-    if let Err(e) = write_call_graph_as_atoms_json(&call_graph, &json_output_path) {
-        let error_msg = format!("Failed to write atoms JSON: {e}");
-        logger.error(&error_msg);
+    let dot_options = DotOptions {
+        url_template: settings.url_template.clone(),
+        edge_kind_styles: settings.edge_kind_styles,
+        ..Default::default()
+    };
+    let written = create_parent_dirs(&output_path).and_then(|()| match settings.format {
+        OutputFormat::Json => {
+            let atoms: Box<dyn Iterator<Item = Atom>> = if settings.include_external {
+                Box::new(call_graph.atoms_with_external())
+            } else {
+                Box::new(call_graph.atoms())
+            };
+            let metadata = settings.with_metadata.then(|| {
+                AtomsMetadata::new(
+                    scip_data.metadata.tool_info.clone(),
+                    git_head_commit(Path::new(folder_path)),
+                )
+            });
+            match (metadata, compress) {
+                (Some(metadata), false) => {
+                    write_atoms_json_with_meta(&atoms.collect::<Vec<_>>(), &metadata, &output_path)
+                }
+                (Some(metadata), true) => {
+                    let atoms = atoms.collect();
+                    write_json_gz(&AtomsWithMeta { metadata, atoms }, &output_path)
+                }
+                (None, false) => write_atoms_json_from_iter(atoms, &output_path),
+                (None, true) => write_atoms_json_gz_from_iter(atoms, &output_path),
+            }
+        }
+        OutputFormat::Dot => call_graph.write_dot(&output_path, false, &dot_options),
+        OutputFormat::Svg => call_graph.write_svg_with_layout(&output_path, None, &settings.layout),
+        OutputFormat::Plantuml => call_graph.write_plantuml(&output_path),
+        OutputFormat::D3 => call_graph.write_d3_json(&output_path),
+        OutputFormat::Cypher => call_graph.write_cypher(&output_path),
+        OutputFormat::Png | OutputFormat::Pdf => {
+            // Keep the DOT source next to the rendered image
+            let dot_path = Path::new(&output_path).with_extension("dot");
+            call_graph
+                .write_dot(&dot_path.to_string_lossy(), false, &dot_options)
+                .and_then(|()| {
+                    render_dot_to_image(&dot_path, &output_path, settings.format.extension())
+                })
+        }
+    });
+    if let Err(e) = written {
+        error!("Failed to write {:?} output: {e}", settings.format);
         // Save logs before exiting
+        drop(clone_dir);
         logger.save_logs()?;
         std::process::exit(1);
     }
-    logger.info(&format!("Atoms JSON written to {json_output_path}"));
+    info!("Output written to {output_path}");
 
     // Save all logs to file at the end
     logger.save_logs()?;
-    println!("Logs saved to file: {}", logger.log_file_path);
+    info!("Logs saved to file: {}", logger.log_file_path);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_log_lines() {
+        let logger = AtomizerLogger {
+            console: env_logger::Builder::new().build(),
+            repo_id: "dalek".to_string(),
+            user_id: "460176".to_string(),
+            format: LogFormat::Json,
+            messages: Mutex::new(Vec::new()),
+            log_file_path: String::new(),
+        };
+        logger.log(
+            &Record::builder()
+                .level(Level::Info)
+                .args(format_args!("Output written to \"atoms.json\""))
+                .build(),
+        );
+        logger.log(
+            &Record::builder()
+                .level(Level::Warn)
+                .args(format_args!("line one\nline two"))
+                .build(),
+        );
+
+        let mut written = Vec::new();
+        let messages = logger.messages.lock().unwrap();
+        logger.write_session(&mut written, &messages).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(written)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["message"], "Output written to \"atoms.json\"");
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["repo_id"], "dalek");
+        assert_eq!(lines[1]["user_id"], "460176");
+        assert!(chrono::DateTime::parse_from_rfc3339(lines[1]["ts"].as_str().unwrap()).is_ok());
+    }
+}
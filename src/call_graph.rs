@@ -0,0 +1,544 @@
+//! [`CallGraph`]: an owned call graph with the analysis and export functions of
+//! [`crate::scip_to_call_graph_json`] available as methods.
+//!
+//! The free functions keep working on `HashMap<String, FunctionNode>`; use
+//! [`CallGraph::as_map`] or the `From` conversions to move between the two.
+
+use crate::error::AtomizerError;
+use crate::scip_to_call_graph_json::{
+    atoms, atoms_with_external, body_size_histogram, build_call_graph,
+    build_call_graph_with_options, build_call_graph_with_report, build_call_graph_with_tolerance,
+    call_graph_summary, call_graph_to_atoms, call_graph_to_atoms_with_external,
+    call_graph_to_cypher, call_graph_to_d3, call_graph_to_dot, call_graph_to_plantuml,
+    compute_degree_metrics, compute_pagerank, diff_call_graphs, exclude_by_glob,
+    find_by_display_name, find_by_path, find_cycles, find_dead_functions, find_entry_points,
+    find_large_functions, generate_call_graph_d3_json, generate_call_graph_dot,
+    generate_call_graph_plantuml, generate_call_graph_svg, generate_call_graph_svg_with_layout,
+    generate_call_graph_svg_with_seed, generate_module_summary_dot, generate_per_crate_dot,
+    generate_per_module_dot, generate_public_api_subgraph_dot, load_call_graph, reachable_from,
+    retain_by_glob, retain_reachable, retain_subtree, save_call_graph, transitive_callees,
+    transitive_callers, update_call_graph, write_atoms_json_from_iter,
+    write_call_graph_as_atoms_json, write_call_graph_as_atoms_json_gz,
+    write_call_graph_as_atoms_json_with_meta, write_call_graph_cypher, Atom, AtomsMetadata,
+    BuildOptions, CallGraphDiff, D3Graph, DotOptions, EdgeFilter, FunctionNode, GraphStats,
+    NodeSizing, ScipIndex, SvgLayout,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// A call graph keyed by unique function keys (symbol plus signature).
+///
+/// Methods taking a `symbol` accept the base SCIP symbol; several nodes may share
+/// one symbol (e.g. multiple trait impls), so lookups by symbol return all of them.
+#[derive(Debug, Clone, Default)]
+pub struct CallGraph(HashMap<String, FunctionNode>);
+
+impl CallGraph {
+    /// Build a call graph from a parsed SCIP index, see [`build_call_graph`]
+    pub fn from_scip(scip_data: &ScipIndex) -> Self {
+        CallGraph(build_call_graph(scip_data))
+    }
+
+    /// Like [`CallGraph::from_scip`] with a custom body-matching line tolerance
+    pub fn from_scip_with_tolerance(scip_data: &ScipIndex, tolerance: usize) -> Self {
+        CallGraph(build_call_graph_with_tolerance(scip_data, tolerance))
+    }
+
+    /// Like [`CallGraph::from_scip_with_tolerance`], also returning the warnings
+    /// collected while building, see [`build_call_graph_with_report`]
+    pub fn from_scip_with_report(scip_data: &ScipIndex, tolerance: usize) -> (Self, Vec<String>) {
+        let (call_graph, warnings) = build_call_graph_with_report(scip_data, tolerance);
+        (CallGraph(call_graph), warnings)
+    }
+
+    /// Build with explicit [`BuildOptions`], see [`build_call_graph_with_options`]
+    pub fn from_scip_with_options(
+        scip_data: &ScipIndex,
+        options: &BuildOptions,
+    ) -> (Self, Vec<String>) {
+        let (call_graph, warnings) = build_call_graph_with_options(scip_data, options);
+        (CallGraph(call_graph), warnings)
+    }
+
+    /// Update in place after the documents at `changed_paths` were edited, see
+    /// [`update_call_graph`]
+    pub fn update(
+        &mut self,
+        scip_data: &ScipIndex,
+        changed_paths: &[String],
+        options: &BuildOptions,
+    ) -> Vec<String> {
+        update_call_graph(&mut self.0, scip_data, changed_paths, options)
+    }
+
+    /// Reload a graph written by [`CallGraph::save`], see [`load_call_graph`]
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        load_call_graph(path).map(CallGraph)
+    }
+
+    /// See [`save_call_graph`]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        save_call_graph(&self.0, path)
+    }
+
+    pub fn as_map(&self) -> &HashMap<String, FunctionNode> {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> HashMap<String, FunctionNode> {
+        self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// All nodes, in arbitrary order
+    pub fn nodes(&self) -> impl Iterator<Item = &FunctionNode> {
+        self.0.values()
+    }
+
+    /// Look up a node by its unique key, or else the first node (by signature) with
+    /// the given base symbol
+    pub fn get(&self, key_or_symbol: &str) -> Option<&FunctionNode> {
+        self.0
+            .get(key_or_symbol)
+            .or_else(|| self.nodes_with_symbol(key_or_symbol).into_iter().next())
+    }
+
+    /// All nodes sharing a base symbol, sorted by signature
+    pub fn nodes_with_symbol(&self, symbol: &str) -> Vec<&FunctionNode> {
+        let mut nodes: Vec<&FunctionNode> = self
+            .0
+            .values()
+            .filter(|node| node.symbol == symbol)
+            .collect();
+        nodes.sort_by(|a, b| a.signature_text.cmp(&b.signature_text));
+        nodes
+    }
+
+    /// Nodes called by any node with `symbol`, sorted by symbol and signature.
+    /// Callees outside the graph (e.g. external functions) are not included.
+    pub fn callees_of(&self, symbol: &str) -> Vec<&FunctionNode> {
+        let callees: HashSet<&str> = self
+            .nodes_with_symbol(symbol)
+            .into_iter()
+            .flat_map(|node| node.callees.iter().map(String::as_str))
+            .collect();
+        let mut nodes: Vec<&FunctionNode> = self
+            .0
+            .values()
+            .filter(|node| callees.contains(node.symbol.as_str()))
+            .collect();
+        sort_nodes(&mut nodes);
+        nodes
+    }
+
+    /// Nodes calling any node with `symbol`, sorted by symbol and signature
+    pub fn callers_of(&self, symbol: &str) -> Vec<&FunctionNode> {
+        let callers: HashSet<&str> = self
+            .nodes_with_symbol(symbol)
+            .into_iter()
+            .flat_map(|node| node.callers.iter().map(String::as_str))
+            .collect();
+        let mut nodes: Vec<&FunctionNode> = callers
+            .into_iter()
+            .filter_map(|key| self.0.get(key))
+            .collect();
+        sort_nodes(&mut nodes);
+        nodes
+    }
+
+    /// See [`find_by_display_name`]
+    pub fn find_by_display_name(&self, name: &str) -> Vec<&FunctionNode> {
+        find_by_display_name(&self.0, name)
+    }
+
+    /// See [`find_by_path`]
+    pub fn find_by_path(&self, identifier: &str) -> Option<&FunctionNode> {
+        find_by_path(&self.0, identifier)
+    }
+
+    /// See [`find_cycles`]
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        find_cycles(&self.0)
+    }
+
+    /// See [`find_dead_functions`]
+    pub fn dead_functions(&self, roots: &[&str]) -> Vec<&FunctionNode> {
+        find_dead_functions(&self.0, roots)
+    }
+
+    /// See [`find_large_functions`]
+    pub fn large_functions(&self, max_lines: usize) -> Vec<(&FunctionNode, usize)> {
+        find_large_functions(&self.0, max_lines)
+    }
+
+    /// See [`find_entry_points`]
+    pub fn entry_points(&self, main_and_tests_only: bool) -> Vec<&FunctionNode> {
+        find_entry_points(&self.0, main_and_tests_only)
+    }
+
+    /// See [`transitive_callees`]
+    pub fn transitive_callees(&self, seeds: &[String]) -> HashSet<String> {
+        transitive_callees(&self.0, seeds)
+    }
+
+    /// See [`transitive_callers`]
+    pub fn transitive_callers(&self, seeds: &[String]) -> HashSet<String> {
+        transitive_callers(&self.0, seeds)
+    }
+
+    /// See [`reachable_from`]
+    pub fn reachable_from(&self, entry_symbols: &[String]) -> HashSet<String> {
+        reachable_from(&self.0, entry_symbols)
+    }
+
+    /// Only the nodes reachable from `entry_symbols`, see [`retain_reachable`]
+    pub fn retain_reachable(&self, entry_symbols: &[String]) -> CallGraph {
+        CallGraph(retain_reachable(&self.0, entry_symbols))
+    }
+
+    /// See [`retain_by_glob`]
+    pub fn retain_by_glob(&self, patterns: &[String]) -> Result<CallGraph, String> {
+        retain_by_glob(&self.0, patterns).map(CallGraph)
+    }
+
+    /// See [`retain_subtree`]
+    pub fn retain_subtree(&self, subtree: &str, keep_outgoing: bool) -> CallGraph {
+        CallGraph(retain_subtree(&self.0, subtree, keep_outgoing))
+    }
+
+    /// See [`exclude_by_glob`]
+    pub fn exclude_by_glob(&self, patterns: &[String]) -> Result<CallGraph, String> {
+        exclude_by_glob(&self.0, patterns).map(CallGraph)
+    }
+
+    /// See [`compute_degree_metrics`]
+    pub fn degree_metrics(&self) -> HashMap<String, (usize, usize)> {
+        compute_degree_metrics(&self.0)
+    }
+
+    /// See [`body_size_histogram`]
+    pub fn body_size_histogram(&self, buckets: &[usize]) -> Vec<(usize, usize)> {
+        body_size_histogram(&self.0, buckets)
+    }
+
+    /// See [`compute_pagerank`]
+    pub fn pagerank(&self, damping: f64, iterations: usize) -> HashMap<String, f64> {
+        compute_pagerank(&self.0, damping, iterations)
+    }
+
+    /// See [`call_graph_summary`]
+    pub fn summary(&self) -> GraphStats {
+        call_graph_summary(&self.0)
+    }
+
+    /// Changes from `self` to `new`, see [`diff_call_graphs`]
+    pub fn diff(&self, new: &CallGraph) -> CallGraphDiff {
+        diff_call_graphs(&self.0, &new.0)
+    }
+
+    /// See [`call_graph_to_atoms`]
+    pub fn to_atoms(&self) -> Vec<Atom> {
+        call_graph_to_atoms(&self.0)
+    }
+
+    /// See [`call_graph_to_atoms_with_external`]
+    pub fn to_atoms_with_external(&self) -> Vec<Atom> {
+        call_graph_to_atoms_with_external(&self.0)
+    }
+
+    /// Atoms built lazily, see [`atoms`]
+    pub fn atoms(&self) -> impl Iterator<Item = Atom> + '_ {
+        atoms(&self.0)
+    }
+
+    /// See [`atoms_with_external`]
+    pub fn atoms_with_external(&self) -> impl Iterator<Item = Atom> + '_ {
+        atoms_with_external(&self.0)
+    }
+
+    /// See [`call_graph_to_dot`]
+    pub fn to_dot(&self, highlight_cycles: bool, options: &DotOptions) -> String {
+        call_graph_to_dot(&self.0, highlight_cycles, options)
+    }
+
+    /// See [`write_call_graph_as_atoms_json`]
+    pub fn write_atoms_json<P: AsRef<Path>>(&self, output_path: P) -> std::io::Result<()> {
+        write_call_graph_as_atoms_json(&self.0, output_path)
+    }
+
+    /// Like [`CallGraph::write_atoms_json`], with external callees listed in `deps`
+    pub fn write_atoms_json_with_external<P: AsRef<Path>>(
+        &self,
+        output_path: P,
+    ) -> std::io::Result<()> {
+        write_atoms_json_from_iter(self.atoms_with_external(), output_path)
+    }
+
+    /// See [`write_call_graph_as_atoms_json_gz`]
+    pub fn write_atoms_json_gz<P: AsRef<Path>>(&self, output_path: P) -> std::io::Result<()> {
+        write_call_graph_as_atoms_json_gz(&self.0, output_path)
+    }
+
+    /// See [`write_call_graph_as_atoms_json_with_meta`]
+    pub fn write_atoms_json_with_meta<P: AsRef<Path>>(
+        &self,
+        metadata: &AtomsMetadata,
+        output_path: P,
+    ) -> std::io::Result<()> {
+        write_call_graph_as_atoms_json_with_meta(&self.0, metadata, output_path)
+    }
+
+    /// See [`call_graph_to_d3`]
+    pub fn to_d3(&self) -> D3Graph {
+        call_graph_to_d3(&self.0)
+    }
+
+    /// See [`generate_call_graph_d3_json`]
+    pub fn write_d3_json<P: AsRef<Path>>(&self, output_path: P) -> std::io::Result<()> {
+        generate_call_graph_d3_json(&self.0, output_path)
+    }
+
+    /// See [`call_graph_to_cypher`]
+    pub fn to_cypher(&self) -> String {
+        call_graph_to_cypher(&self.0)
+    }
+
+    /// See [`write_call_graph_cypher`]
+    pub fn write_cypher<P: AsRef<Path>>(&self, output_path: P) -> std::io::Result<()> {
+        write_call_graph_cypher(&self.0, output_path)
+    }
+
+    /// See [`call_graph_to_plantuml`]
+    pub fn to_plantuml(&self) -> String {
+        call_graph_to_plantuml(&self.0)
+    }
+
+    /// See [`generate_call_graph_plantuml`]
+    pub fn write_plantuml<P: AsRef<Path>>(&self, output_path: P) -> std::io::Result<()> {
+        generate_call_graph_plantuml(&self.0, output_path)
+    }
+
+    /// See [`generate_call_graph_dot`]
+    pub fn write_dot(
+        &self,
+        output_path: &str,
+        highlight_cycles: bool,
+        options: &DotOptions,
+    ) -> std::io::Result<()> {
+        generate_call_graph_dot(&self.0, output_path, highlight_cycles, options)
+    }
+
+    /// See [`generate_per_module_dot`]
+    pub fn write_per_module_dot<P: AsRef<Path>>(
+        &self,
+        output_dir: P,
+        options: &DotOptions,
+    ) -> std::io::Result<Vec<PathBuf>> {
+        generate_per_module_dot(&self.0, output_dir, options)
+    }
+
+    /// See [`generate_per_crate_dot`]
+    pub fn write_per_crate_dot<P: AsRef<Path>>(
+        &self,
+        output_dir: P,
+        options: &DotOptions,
+    ) -> std::io::Result<Vec<PathBuf>> {
+        generate_per_crate_dot(&self.0, output_dir, options)
+    }
+
+    /// See [`generate_public_api_subgraph_dot`]
+    pub fn write_public_api_dot(
+        &self,
+        output_path: &str,
+        options: &DotOptions,
+    ) -> std::io::Result<usize> {
+        generate_public_api_subgraph_dot(&self.0, output_path, options)
+    }
+
+    /// See [`generate_module_summary_dot`]
+    pub fn write_module_summary_dot(
+        &self,
+        output_path: &str,
+        filter: &EdgeFilter,
+        options: &DotOptions,
+    ) -> std::io::Result<()> {
+        generate_module_summary_dot(&self.0, output_path, filter, options)
+    }
+
+    /// See [`generate_call_graph_svg`]
+    pub fn write_svg(
+        &self,
+        output_path: &str,
+        node_sizing: Option<&NodeSizing>,
+    ) -> std::io::Result<()> {
+        generate_call_graph_svg(&self.0, output_path, node_sizing)
+    }
+
+    /// See [`generate_call_graph_svg_with_seed`]
+    pub fn write_svg_with_seed(
+        &self,
+        output_path: &str,
+        node_sizing: Option<&NodeSizing>,
+        seed: u64,
+    ) -> std::io::Result<()> {
+        generate_call_graph_svg_with_seed(&self.0, output_path, node_sizing, seed)
+    }
+
+    /// See [`generate_call_graph_svg_with_layout`]
+    pub fn write_svg_with_layout(
+        &self,
+        output_path: &str,
+        node_sizing: Option<&NodeSizing>,
+        layout: &SvgLayout,
+    ) -> std::io::Result<()> {
+        generate_call_graph_svg_with_layout(&self.0, output_path, node_sizing, layout)
+    }
+}
+
+/// Build a call graph, failing if more than `max_body_failures` function bodies could
+/// not be read. Otherwise returns the graph with the build warnings, as
+/// [`CallGraph::from_scip_with_options`]. Use [`CallGraph::from_scip`] when partial
+/// bodies are acceptable.
+pub fn try_build_call_graph(
+    scip_data: &ScipIndex,
+    options: &BuildOptions,
+    max_body_failures: usize,
+) -> Result<(CallGraph, Vec<String>), AtomizerError> {
+    let (call_graph, warnings) = CallGraph::from_scip_with_options(scip_data, options);
+    if warnings.len() > max_body_failures {
+        return Err(AtomizerError::BodyExtraction {
+            failed: warnings.len(),
+            max_failures: max_body_failures,
+            warnings,
+        });
+    }
+    Ok((call_graph, warnings))
+}
+
+fn sort_nodes(nodes: &mut [&FunctionNode]) {
+    nodes.sort_by(|a, b| (&a.symbol, &a.signature_text).cmp(&(&b.symbol, &b.signature_text)));
+}
+
+impl From<HashMap<String, FunctionNode>> for CallGraph {
+    fn from(call_graph: HashMap<String, FunctionNode>) -> Self {
+        CallGraph(call_graph)
+    }
+}
+
+impl From<CallGraph> for HashMap<String, FunctionNode> {
+    fn from(call_graph: CallGraph) -> Self {
+        call_graph.0
+    }
+}
+
+impl AsRef<HashMap<String, FunctionNode>> for CallGraph {
+    fn as_ref(&self) -> &HashMap<String, FunctionNode> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(symbol: &str, callees: &[&str], callers: &[&str]) -> FunctionNode {
+        FunctionNode {
+            symbol: symbol.to_string(),
+            display_name: symbol.to_string(),
+            signature_text: format!("fn {symbol}()"),
+            file_path: "/tmp/test.rs".to_string(),
+            relative_path: "test.rs".to_string(),
+            callers: callers.iter().map(|c| c.to_string()).collect(),
+            callees: callees.iter().map(|c| c.to_string()).collect(),
+            range: vec![],
+            body: None,
+            kind: 17,
+            enclosing_range: None,
+            external_callees: HashSet::new(),
+            is_test: false,
+            visibility: Default::default(),
+            is_async: false,
+            is_unsafe: false,
+            is_const: false,
+            is_external: false,
+            enclosing: None,
+            edge_kinds: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_call_graph_queries() {
+        let map: HashMap<String, FunctionNode> = [
+            node("main", &["a", "b", "external"], &[]),
+            node("a", &["b"], &["main"]),
+            node("b", &[], &["main", "a"]),
+        ]
+        .into_iter()
+        .map(|n| (n.symbol.clone(), n))
+        .collect();
+        let graph = CallGraph::from(map);
+
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.get("a").unwrap().symbol, "a");
+        let callees: Vec<_> = graph.callees_of("main").iter().map(|n| &n.symbol).collect();
+        assert_eq!(callees, vec!["a", "b"]);
+        let callers: Vec<_> = graph.callers_of("b").iter().map(|n| &n.symbol).collect();
+        assert_eq!(callers, vec!["a", "main"]);
+
+        assert!(graph.cycles().is_empty());
+        assert!(graph.dead_functions(&["main"]).is_empty());
+        assert_eq!(graph.summary().edges, 4);
+        assert_eq!(graph.to_atoms().len(), 3);
+        assert!(graph
+            .to_dot(false, &DotOptions::default())
+            .contains("\"a\" -> \"b\""));
+        assert!(graph.diff(&graph.clone()).is_empty());
+
+        let map: HashMap<String, FunctionNode> = graph.into();
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn test_try_build_call_graph() {
+        let index: ScipIndex = serde_json::from_value(serde_json::json!({
+            "metadata": {
+                "tool_info": {"name": "rust-analyzer", "version": "0"},
+                "project_root": "file:///nonexistent",
+                "text_document_encoding": 1
+            },
+            "documents": [{
+                "language": "rust",
+                "relative_path": "src/lib.rs",
+                "position_encoding": 1,
+                "occurrences": [
+                    {"range": [0, 3, 4], "symbol": "rust-analyzer cargo c 0.1.0 a().", "symbol_roles": 1}
+                ],
+                "symbols": [{
+                    "symbol": "rust-analyzer cargo c 0.1.0 a().",
+                    "kind": 17,
+                    "display_name": "a",
+                    "signature_documentation": {"language": "rust", "text": "fn a()", "position_encoding": 1}
+                }]
+            }]
+        }))
+        .unwrap();
+        let options = BuildOptions::default();
+
+        let err = try_build_call_graph(&index, &options, 0).unwrap_err();
+        assert!(matches!(
+            err,
+            AtomizerError::BodyExtraction { failed: 1, .. }
+        ));
+        assert_eq!(
+            try_build_call_graph(&index, &options, 1).unwrap().0.len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Run configuration loaded from an `atomizer.toml` file.
+//!
+//! Every field is optional. Binaries merge values with the precedence
+//! CLI flags > config file > built-in defaults.
+//!
+//! ```toml
+//! user_id = "460176"
+//! output = "artifacts/atoms.json"
+//! format = "json"
+//! tool = "verus-analyzer"
+//! scip_file = "index.scip"
+//! tolerance = 15
+//! layout_seed = 0
+//! layout_iterations = 200
+//! layout_max_nodes = 1000
+//! analyzer_timeout = 3600
+//! include_external = false
+//! with_metadata = false
+//! compress = false
+//! skip_bodies = false
+//! exclude_tests = true
+//! fan_out_trait_calls = false
+//! edge_kind_styles = false
+//! item_macros = ["my_items"]
+//! subtree = "src/protocol"
+//! subtree_external_deps = false
+//! include = ["src/**"]
+//! exclude = ["src/bin/**"]
+//! log_format = "json"
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// File name looked up in the input folder when no `--config` is given
+pub const CONFIG_FILE_NAME: &str = "atomizer.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub user_id: Option<String>,
+    /// Output path for the generated file
+    pub output: Option<String>,
+    /// Output format (`json`, `dot`, `svg`, `plantuml`, `d3`, `cypher`, `png` or `pdf`)
+    pub format: Option<String>,
+    /// SCIP indexer (`rust-analyzer` or `verus-analyzer`)
+    pub tool: Option<String>,
+    /// Path of the SCIP index written by the indexer
+    pub scip_file: Option<String>,
+    /// Line tolerance when matching SCIP definitions to parsed spans
+    pub tolerance: Option<usize>,
+    /// Seed for the node positions of SVG output
+    pub layout_seed: Option<u64>,
+    /// Force simulation steps for SVG output
+    pub layout_iterations: Option<usize>,
+    /// Node count above which the SVG force simulation is skipped; only affects SVG
+    /// output and never drops nodes
+    pub layout_max_nodes: Option<usize>,
+    /// Seconds after which the analyzer run is killed
+    pub analyzer_timeout: Option<u64>,
+    /// List callees defined outside the project in atom `deps`
+    pub include_external: Option<bool>,
+    /// Wrap the JSON atoms in an object with build metadata
+    pub with_metadata: Option<bool>,
+    /// Gzip-compress the JSON output
+    pub compress: Option<bool>,
+    /// Only build the call edges, without function bodies
+    pub skip_bodies: Option<bool>,
+    /// Leave out test functions and `#[cfg(test)]` modules
+    pub exclude_tests: Option<bool>,
+    /// Add edges to every implementation of a called trait method with several
+    pub fan_out_trait_calls: Option<bool>,
+    /// Fail if more than this many function bodies could not be read
+    pub max_body_failures: Option<usize>,
+    /// Source link template for DOT nodes, with `{path}` and `{line}` placeholders
+    pub url_template: Option<String>,
+    /// Style DOT edges by call kind (dashed for static trait calls, dotted for dispatch)
+    pub edge_kind_styles: Option<bool>,
+    /// Macros whose bodies are parsed as items in addition to `verus!`
+    pub item_macros: Option<Vec<String>>,
+    /// Directory whose functions are output, relative to the input folder
+    pub subtree: Option<String>,
+    /// List calls leaving `subtree` as external deps instead of dropping them
+    pub subtree_external_deps: Option<bool>,
+    /// Globs over relative paths; only functions in matching files are output
+    pub include: Option<Vec<String>>,
+    /// Globs over relative paths; functions in matching files are left out
+    pub exclude: Option<Vec<String>>,
+    /// Log file layout (`text` or `json`)
+    pub log_format: Option<String>,
+}
+
+impl Config {
+    /// Parse a config file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {e}", path.display()))
+    }
+
+    /// Path of `atomizer.toml` in `folder`, if it exists
+    pub fn discover<P: AsRef<Path>>(folder: P) -> Option<PathBuf> {
+        let candidate = folder.as_ref().join(CONFIG_FILE_NAME);
+        candidate.is_file().then_some(candidate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_load_and_discover() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Config::discover(dir.path()).is_none());
+
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(&path, "tool = \"verus-analyzer\"\ntolerance = 30\n").unwrap();
+        assert_eq!(Config::discover(dir.path()), Some(path.clone()));
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.tool.as_deref(), Some("verus-analyzer"));
+        assert_eq!(config.tolerance, Some(30));
+        assert_eq!(config.output, None);
+
+        fs::write(&path, "tolerence = 30\n").unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! Error type for fallible library entry points such as
+//! [`try_build_call_graph`](crate::call_graph::try_build_call_graph).
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum AtomizerError {
+    /// More source files failed to load during body extraction than allowed
+    BodyExtraction {
+        /// Number of failures
+        failed: usize,
+        /// The allowed maximum
+        max_failures: usize,
+        /// One message per failure
+        warnings: Vec<String>,
+    },
+    /// An external program could not be run, see [`crate::tools`]
+    MissingTool {
+        tool: String,
+        install_hint: String,
+        /// Why running it failed
+        reason: String,
+    },
+    Io(std::io::Error),
+}
+
+impl fmt::Display for AtomizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtomizerError::BodyExtraction {
+                failed,
+                max_failures,
+                warnings,
+            } => {
+                write!(
+                    f,
+                    "body extraction failed for {failed} functions (allowed: {max_failures})"
+                )?;
+                if let Some(first) = warnings.first() {
+                    write!(f, "; first failure: {first}")?;
+                }
+                Ok(())
+            }
+            AtomizerError::MissingTool {
+                tool,
+                install_hint,
+                reason,
+            } => write!(
+                f,
+                "`{tool}` could not be run ({reason}); make sure it is on your PATH or {install_hint}"
+            ),
+            AtomizerError::Io(e) => write!(f, "I/O error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for AtomizerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AtomizerError::Io(e) => Some(e),
+            AtomizerError::BodyExtraction { .. } | AtomizerError::MissingTool { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AtomizerError {
+    fn from(e: std::io::Error) -> Self {
+        AtomizerError::Io(e)
+    }
+}
@@ -1,2 +1,7 @@
+pub mod call_graph;
+pub mod config;
+pub mod error;
+pub mod manifest;
 pub mod scip_to_call_graph_json;
+pub mod tools;
 pub mod verus_parser;
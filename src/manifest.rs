@@ -0,0 +1,325 @@
+//! Preparing a source folder for SCIP indexing.
+//!
+//! The analyzers only index Cargo projects, so a folder of loose `.rs` files
+//! needs a synthesized `Cargo.toml` that registers every source file. Existing
+//! manifests (including virtual workspace roots) are always left untouched.
+
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::{DirEntry, WalkDir};
+
+/// Directories that never contain project sources worth indexing.
+fn is_skipped_dir(entry: &DirEntry) -> bool {
+    entry.depth() > 0
+        && entry.file_type().is_dir()
+        && entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name == "target" || name.starts_with('.'))
+}
+
+/// Recursively find all `.rs` files under `root`, skipping `target/` and
+/// hidden directories. Paths are returned relative to `root`, sorted.
+pub fn find_rust_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| !is_skipped_dir(e))
+    {
+        let entry = entry.map_err(io::Error::other)?;
+        let path = entry.path();
+        if entry.file_type().is_file() && path.extension().and_then(|s| s.to_str()) == Some("rs") {
+            if let Ok(relative) = path.strip_prefix(root) {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Turn a relative source path into a valid Cargo target name,
+/// e.g. `src/field/mod.rs` -> `src_field_mod`. Cargo rejects names starting with a
+/// digit, so those get a leading `_` (`2d.rs` -> `_2d`).
+fn target_name(relative: &Path) -> String {
+    let name: String = relative
+        .with_extension("")
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+/// Build a `Cargo.toml` that registers every `.rs` file under `root` as a
+/// `[[bin]]` target. Paths that map to the same target name, such as `src/a_b.rs`
+/// and `src/a/b.rs`, get a numeric suffix (`src_a_b_2`). Returns `None` if the
+/// folder contains no Rust sources.
+pub fn standalone_cargo_toml(root: &Path) -> io::Result<Option<String>> {
+    let rust_files = find_rust_files(root)?;
+    let Some(first) = rust_files.first() else {
+        return Ok(None);
+    };
+
+    let package_name = target_name(Path::new(first.file_name().unwrap_or_default()));
+    let mut cargo_content = format!(
+        "[package]\nname = \"{package_name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n"
+    );
+
+    let mut taken = HashSet::new();
+    for relative in &rust_files {
+        let base_name = target_name(relative);
+        let mut name = base_name.clone();
+        let mut suffix = 1;
+        while !taken.insert(name.clone()) {
+            suffix += 1;
+            name = format!("{base_name}_{suffix}");
+        }
+        // Cargo paths always use forward slashes
+        let path = relative.to_string_lossy().replace('\\', "/");
+        cargo_content.push_str(&format!(
+            "[[bin]]\nname = \"{name}\"\npath = \"{path}\"\n\n"
+        ));
+    }
+
+    Ok(Some(cargo_content))
+}
+
+/// How a source folder will be handed to the analyzer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectLayout {
+    /// `Cargo.toml` with a `[package]` (or one we could not parse); used as-is.
+    Package,
+    /// Virtual manifest with `[workspace]` but no `[package]`; the analyzer
+    /// is run at the workspace root as-is.
+    VirtualWorkspace,
+    /// No `Cargo.toml`; one has to be synthesized.
+    Standalone,
+}
+
+/// Inspect `root/Cargo.toml` to decide how the folder should be indexed.
+pub fn detect_project_layout(root: &Path) -> io::Result<ProjectLayout> {
+    let manifest_path = root.join("Cargo.toml");
+    if !manifest_path.exists() {
+        return Ok(ProjectLayout::Standalone);
+    }
+    let content = std::fs::read_to_string(&manifest_path)?;
+    match content.parse::<toml::Table>() {
+        Ok(table) if table.contains_key("workspace") && !table.contains_key("package") => {
+            Ok(ProjectLayout::VirtualWorkspace)
+        }
+        _ => Ok(ProjectLayout::Package),
+    }
+}
+
+/// Synthesize `root/Cargo.toml` for a folder of loose sources.
+///
+/// Never overwrites an existing manifest. Returns `false` if nothing was
+/// written, either because a manifest already exists or there are no sources.
+pub fn write_standalone_cargo_toml(root: &Path) -> io::Result<bool> {
+    let Some(cargo_content) = standalone_cargo_toml(root)? else {
+        return Ok(false);
+    };
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(root.join("Cargo.toml"));
+    match file {
+        Ok(mut file) => {
+            file.write_all(cargo_content.as_bytes())?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether an input names a remote git repository rather than a local folder:
+/// an `https://`, `http://`, `ssh://`, `git://` or `file://` URL, or an scp-style
+/// `user@host:path` address.
+pub fn is_git_url(input: &str) -> bool {
+    const SCHEMES: [&str; 5] = ["https://", "http://", "ssh://", "git://", "file://"];
+    if SCHEMES.iter().any(|scheme| input.starts_with(scheme)) {
+        return true;
+    }
+    // `git@github.com:org/repo.git`; a local path with `@` and `:` would need both
+    // before the first slash
+    match (input.find('@'), input.find(':'), input.find('/')) {
+        (Some(at), Some(colon), slash) => at < colon && slash.is_none_or(|slash| colon < slash),
+        _ => false,
+    }
+}
+
+/// Directory name for a clone of `url`: its last path segment without `.git`,
+/// e.g. `https://github.com/org/repo.git` -> `repo`
+pub fn git_repository_name(url: &str) -> String {
+    let name = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches(".git");
+    if name.is_empty() {
+        "repository".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Run `git` with `args`, turning a failed exit into an error carrying its stderr
+fn run_git(args: &[&str]) -> io::Result<()> {
+    let output = Command::new("git").args(args).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(format!(
+        "git {} failed: {}",
+        args.first().unwrap_or(&""),
+        String::from_utf8_lossy(&output.stderr).trim()
+    )))
+}
+
+/// Clone `url` into `dest` (which must not exist yet) by shelling out to `git`.
+///
+/// Without `rev` only the latest commit of the default branch is fetched. With
+/// `rev` (a commit, tag or branch) the full history is cloned and `rev` is checked
+/// out as a detached `HEAD`.
+pub fn clone_git_repository(url: &str, rev: Option<&str>, dest: &Path) -> io::Result<()> {
+    let dest_str = dest.to_string_lossy();
+    match rev {
+        None => run_git(&["clone", "--depth", "1", "--", url, &dest_str]),
+        Some(rev) => {
+            run_git(&["clone", "--no-checkout", "--", url, &dest_str])?;
+            run_git(&["-C", &dest_str, "checkout", "--detach", rev])
+        }
+    }
+}
+
+/// Commit hash checked out in `root`, or `None` if it is not a git repository
+/// (or `git` is not installed)
+pub fn git_head_commit(root: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_find_rust_files_recursive() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for file in [
+            "main.rs",
+            "src/lib.rs",
+            "src/field/mod.rs",
+            "target/debug/build.rs",
+            ".git/hooks/x.rs",
+            "notes.txt",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "fn main() {}\n").unwrap();
+        }
+
+        let files = find_rust_files(root).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("main.rs"),
+                PathBuf::from("src/field/mod.rs"),
+                PathBuf::from("src/lib.rs"),
+            ]
+        );
+
+        let manifest = standalone_cargo_toml(root).unwrap().unwrap();
+        assert!(manifest.contains("name = \"main\"\nversion"));
+        assert!(manifest.contains("name = \"src_field_mod\"\npath = \"src/field/mod.rs\""));
+        assert!(!manifest.contains("target/"));
+    }
+
+    #[test]
+    fn test_standalone_cargo_toml_unique_names() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["src/a_b.rs", "src/a/b.rs", "3d.rs"] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "fn main() {}\n").unwrap();
+        }
+
+        let manifest = standalone_cargo_toml(dir.path()).unwrap().unwrap();
+        assert!(manifest.contains("[package]\nname = \"_3d\"\n"));
+        assert!(manifest.contains("name = \"_3d\"\npath = \"3d.rs\""));
+        assert!(manifest.contains("name = \"src_a_b\"\npath = \"src/a/b.rs\""));
+        assert!(manifest.contains("name = \"src_a_b_2\"\npath = \"src/a_b.rs\""));
+        assert!(manifest.parse::<toml::Table>().is_ok());
+    }
+
+    #[test]
+    fn test_standalone_cargo_toml_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(standalone_cargo_toml(dir.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_detect_project_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+        assert_eq!(
+            detect_project_layout(root).unwrap(),
+            ProjectLayout::Standalone
+        );
+
+        assert!(write_standalone_cargo_toml(root).unwrap());
+        assert_eq!(detect_project_layout(root).unwrap(), ProjectLayout::Package);
+
+        let workspace = "[workspace]\nmembers = [\"a\", \"b\"]\n";
+        fs::write(root.join("Cargo.toml"), workspace).unwrap();
+        assert_eq!(
+            detect_project_layout(root).unwrap(),
+            ProjectLayout::VirtualWorkspace
+        );
+
+        // An existing manifest is never overwritten
+        assert!(!write_standalone_cargo_toml(root).unwrap());
+        assert_eq!(
+            fs::read_to_string(root.join("Cargo.toml")).unwrap(),
+            workspace
+        );
+    }
+
+    #[test]
+    fn test_git_url_detection() {
+        assert!(is_git_url("https://github.com/org/repo.git"));
+        assert!(is_git_url("git@github.com:org/repo.git"));
+        assert!(is_git_url("ssh://git@host/org/repo"));
+        assert!(!is_git_url("../curve25519-dalek"));
+        assert!(!is_git_url("/tmp/user@host/x:y"));
+        assert!(!is_git_url("C:\\src\\repo"));
+
+        assert_eq!(
+            git_repository_name("https://github.com/org/repo.git"),
+            "repo"
+        );
+        assert_eq!(git_repository_name("git@github.com:org/tool"), "tool");
+        assert_eq!(git_repository_name("https://host/org/repo/"), "repo");
+    }
+}
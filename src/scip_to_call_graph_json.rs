@@ -1,11 +1,22 @@
-use crate::verus_parser::FileSpanCache;
-use log::{debug, warn};
+use crate::verus_parser::{
+    extract_exact_text_from_span, line_ending, FileSpanCache, FunctionSpan, Visibility,
+    DEFAULT_ITEM_MACROS, DEFAULT_MATCH_TOLERANCE,
+};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::{debug, info, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize, Serializer as _};
 use serde_json;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fs::{self};
-use std::path::Path;
+use std::io::{BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
 
 /// Expected prefix for SCIP symbols from rust-analyzer/verus-analyzer
 const SCIP_SYMBOL_PREFIX: &str = "rust-analyzer cargo ";
@@ -13,27 +24,34 @@ const SCIP_SYMBOL_PREFIX: &str = "rust-analyzer cargo ";
 /// Prefix for probe-style URIs
 const PROBE_URI_PREFIX: &str = "probe:";
 
+/// Prefix of `Atom.deps` entries for callees defined outside the project
+pub const EXTERNAL_DEP_PREFIX: &str = "external:";
+
 // Re-using the SCIP data structures from our JSON parser
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ScipIndex {
     pub metadata: Metadata,
     pub documents: Vec<Document>,
+    /// Symbols defined outside the project (e.g. in dependencies) that the documents
+    /// reference; missing from some indexes
+    #[serde(default)]
+    pub external_symbols: Vec<Symbol>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Metadata {
     pub tool_info: ToolInfo,
     pub project_root: String,
     pub text_document_encoding: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolInfo {
     pub name: String,
     pub version: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Document {
     pub language: String,
     pub relative_path: String,
@@ -45,13 +63,96 @@ pub struct Document {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Occurrence {
-    pub range: Vec<i32>,
+    pub range: Range,
     pub symbol: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub symbol_roles: Option<i32>,
+    /// Range of the whole enclosing item (e.g. the full function for a definition)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enclosing_range: Option<Range>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A source range from a SCIP occurrence, with 0-indexed lines and columns and an
+/// exclusive end column.
+///
+/// SCIP encodes ranges as `[start_line, start_col, end_col]` for single-line ranges and
+/// `[start_line, start_col, end_line, end_col]` otherwise; (de)serialization uses the
+/// same array form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "Vec<i32>", into = "Vec<i32>")]
+pub struct Range {
+    pub start_line: i32,
+    pub start_col: i32,
+    pub end_line: i32,
+    pub end_col: i32,
+}
+
+impl Range {
+    /// Interpret a 3- or 4-element SCIP range
+    pub fn from_scip(range: &[i32]) -> Option<Self> {
+        match *range {
+            [start_line, start_col, end_col] => Some(Range {
+                start_line,
+                start_col,
+                end_line: start_line,
+                end_col,
+            }),
+            [start_line, start_col, end_line, end_col] => Some(Range {
+                start_line,
+                start_col,
+                end_line,
+                end_col,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Convert back to the SCIP array form (3 elements for single-line ranges)
+    pub fn to_scip(self) -> Vec<i32> {
+        if self.start_line == self.end_line {
+            vec![self.start_line, self.start_col, self.end_col]
+        } else {
+            vec![self.start_line, self.start_col, self.end_line, self.end_col]
+        }
+    }
+
+    /// Exact text covered by this range, treating columns as character offsets
+    pub fn extract(&self, content: &str) -> String {
+        let (Ok(start_line), Ok(end_line), Ok(start_col), Ok(end_col)) = (
+            usize::try_from(self.start_line),
+            usize::try_from(self.end_line),
+            usize::try_from(self.start_col),
+            usize::try_from(self.end_col),
+        ) else {
+            return String::new();
+        };
+        let span = FunctionSpan {
+            start_line: start_line + 1,
+            end_line: end_line + 1,
+            start_col,
+            end_col,
+            ..Default::default()
+        };
+        extract_exact_text_from_span(content, &span)
+    }
+}
+
+impl TryFrom<Vec<i32>> for Range {
+    type Error = String;
+
+    fn try_from(range: Vec<i32>) -> Result<Self, Self::Error> {
+        Range::from_scip(&range)
+            .ok_or_else(|| format!("expected 3 or 4 range elements, got {range:?}"))
+    }
+}
+
+impl From<Range> for Vec<i32> {
+    fn from(range: Range) -> Self {
+        range.to_scip()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Symbol {
     pub symbol: String,
     pub kind: i32,
@@ -61,9 +162,177 @@ pub struct Symbol {
     pub signature_documentation: SignatureDocumentation,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enclosing_symbol: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relationships: Vec<Relationship>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Link from a [`Symbol`] to another symbol, e.g. from a method in an impl block to
+/// the trait method it implements (`is_implementation`)
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Relationship {
+    pub symbol: String,
+    #[serde(default)]
+    pub is_reference: bool,
+    #[serde(default)]
+    pub is_implementation: bool,
+    #[serde(default)]
+    pub is_type_definition: bool,
+    #[serde(default)]
+    pub is_definition: bool,
+}
+
+impl Symbol {
+    /// Typed [`Symbol::kind`], `None` for values outside the SCIP schema
+    pub fn symbol_kind(&self) -> Option<SymbolKind> {
+        SymbolKind::try_from(self.kind).ok()
+    }
+}
+
+/// Defines [`SymbolKind`] and its conversion from `i32` from a single list of values
+macro_rules! symbol_kinds {
+    ($($name:ident = $value:literal,)*) => {
+        /// Kind of a SCIP symbol, as numbered by `SymbolInformation.Kind` in the SCIP
+        /// schema. [`Symbol::kind`] and [`FunctionNode::kind`] stay plain `i32`s in JSON;
+        /// convert with `SymbolKind::try_from(kind)` and `i32::from(symbol_kind)`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(i32)]
+        pub enum SymbolKind {
+            $($name = $value,)*
+        }
+
+        impl TryFrom<i32> for SymbolKind {
+            type Error = String;
+
+            fn try_from(kind: i32) -> Result<Self, String> {
+                match kind {
+                    $($value => Ok(SymbolKind::$name),)*
+                    _ => Err(format!("unknown SCIP symbol kind {kind}")),
+                }
+            }
+        }
+    };
+}
+
+symbol_kinds! {
+    Unspecified = 0,
+    AbstractMethod = 66,
+    Accessor = 72,
+    Array = 1,
+    Assertion = 2,
+    AssociatedType = 3,
+    Attribute = 4,
+    Axiom = 5,
+    Boolean = 6,
+    Class = 7,
+    Constant = 8,
+    Constructor = 9,
+    Contract = 62,
+    DataFamily = 10,
+    Delegate = 73,
+    Enum = 11,
+    EnumMember = 12,
+    Error = 63,
+    Event = 13,
+    Extension = 84,
+    Fact = 14,
+    Field = 15,
+    File = 16,
+    Function = 17,
+    Getter = 18,
+    Grammar = 19,
+    Instance = 20,
+    Interface = 21,
+    Key = 22,
+    Lang = 23,
+    Lemma = 24,
+    Library = 64,
+    Macro = 25,
+    Method = 26,
+    MethodAlias = 74,
+    MethodReceiver = 27,
+    MethodSpecification = 67,
+    Message = 28,
+    Mixin = 85,
+    Modifier = 65,
+    Module = 29,
+    Namespace = 30,
+    Null = 31,
+    Number = 32,
+    Object = 33,
+    Operator = 34,
+    Package = 35,
+    PackageObject = 36,
+    Parameter = 37,
+    ParameterLabel = 38,
+    Pattern = 39,
+    Predicate = 40,
+    Property = 41,
+    Protocol = 42,
+    ProtocolMethod = 68,
+    PureVirtualMethod = 69,
+    Quasiquoter = 43,
+    SelfParameter = 44,
+    Setter = 45,
+    Signature = 46,
+    SingletonClass = 75,
+    SingletonMethod = 76,
+    StaticDataMember = 77,
+    StaticEvent = 78,
+    StaticField = 79,
+    StaticMethod = 80,
+    StaticProperty = 81,
+    StaticVariable = 82,
+    String = 48,
+    Struct = 49,
+    Subscript = 47,
+    Tactic = 50,
+    Theorem = 51,
+    ThisParameter = 52,
+    Trait = 53,
+    TraitMethod = 70,
+    Type = 54,
+    TypeAlias = 55,
+    TypeClass = 56,
+    TypeClassMethod = 71,
+    TypeFamily = 57,
+    TypeParameter = 58,
+    Union = 59,
+    Value = 60,
+    Variable = 61,
+}
+
+impl From<SymbolKind> for i32 {
+    fn from(kind: SymbolKind) -> Self {
+        kind as i32
+    }
+}
+
+impl SymbolKind {
+    /// Functions, methods, constructors and macros: definitions with a body that calls
+    /// other functions
+    pub fn is_function_like(self) -> bool {
+        matches!(
+            self,
+            SymbolKind::Function
+                | SymbolKind::Method
+                | SymbolKind::StaticMethod
+                | SymbolKind::Constructor
+                | SymbolKind::Macro
+        )
+    }
+
+    /// Type definitions that become nodes (structs and enums)
+    pub fn is_type_definition(self) -> bool {
+        matches!(self, SymbolKind::Struct | SymbolKind::Enum)
+    }
+
+    /// Named values that become nodes (`const` and `static` items)
+    pub fn is_value_definition(self) -> bool {
+        matches!(self, SymbolKind::Constant | SymbolKind::StaticVariable)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SignatureDocumentation {
     pub language: String,
     pub text: String,
@@ -71,17 +340,101 @@ pub struct SignatureDocumentation {
 }
 
 /// Represents a node in the call graph
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionNode {
     pub symbol: String,
     pub display_name: String,
     pub signature_text: String, // Signature text from SCIP (for disambiguation)
     pub file_path: String,
-    pub relative_path: String,    // Relative path from project root
+    pub relative_path: String, // Relative path from project root
+    #[serde(serialize_with = "serialize_sorted")]
     pub callers: HashSet<String>, // Symbols that call this function
+    #[serde(serialize_with = "serialize_sorted")]
     pub callees: HashSet<String>, // Symbols that this function calls
-    pub range: Vec<i32>,          // Range of the function in the source file
-    pub body: Option<String>,     // Optional body of the function
+    pub range: Vec<i32>,       // Range of the function in the source file
+    pub body: Option<String>,  // Optional body of the function
+    pub kind: i32,             // SCIP symbol kind (function, struct, enum, ...)
+    pub enclosing_range: Option<Range>, // Range of the whole definition, if SCIP provides it
+    #[serde(default, serialize_with = "serialize_sorted")]
+    pub external_callees: HashSet<String>, // Called symbols defined outside the project
+    #[serde(default)]
+    pub is_test: bool, // Test function or inside a `#[cfg(test)]` module
+    #[serde(default)]
+    pub visibility: Visibility, // Declared visibility, if the source could be parsed
+    #[serde(default)]
+    pub is_async: bool,
+    #[serde(default)]
+    pub is_unsafe: bool,
+    #[serde(default)]
+    pub is_const: bool,
+    #[serde(default)]
+    pub is_external: bool, // Stub for a callee from `ScipIndex.external_symbols`, without a body
+    #[serde(default)]
+    pub enclosing: Option<String>, // Nearest enclosing function or type symbol, see `symbol_enclosure`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub edge_kinds: BTreeMap<String, EdgeKind>, // Callees that are not `EdgeKind::Direct`
+}
+
+/// How a call reaches its callee
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    /// Plain call of a function or inherent method
+    #[default]
+    Direct,
+    /// Call of a trait method resolved at compile time to one implementation, either
+    /// because it names the impl or because the project has only one
+    TraitStatic,
+    /// Call through a trait method whose implementation is only known at run time
+    /// (or not known to the index): the edge to the trait method itself, and with
+    /// [`BuildOptions::fan_out_trait_calls`] the edges to its candidate impls
+    TraitDynamic,
+}
+
+impl EdgeKind {
+    /// DOT edge attribute for this kind, `None` for direct calls (solid edges)
+    fn dot_style(self) -> Option<&'static str> {
+        match self {
+            EdgeKind::Direct => None,
+            EdgeKind::TraitStatic => Some("style=dashed"),
+            EdgeKind::TraitDynamic => Some("style=dotted"),
+        }
+    }
+}
+
+impl FunctionNode {
+    /// Typed [`FunctionNode::kind`], `None` for values outside the SCIP schema
+    pub fn symbol_kind(&self) -> Option<SymbolKind> {
+        SymbolKind::try_from(self.kind).ok()
+    }
+
+    /// [`FunctionNode::callers`] in sorted order, for output that does not depend
+    /// on hash set iteration order
+    pub fn sorted_callers(&self) -> Vec<&String> {
+        let mut callers: Vec<&String> = self.callers.iter().collect();
+        callers.sort();
+        callers
+    }
+
+    /// [`FunctionNode::callees`] in sorted order, see [`FunctionNode::sorted_callers`]
+    pub fn sorted_callees(&self) -> Vec<&String> {
+        let mut callees: Vec<&String> = self.callees.iter().collect();
+        callees.sort();
+        callees
+    }
+
+    /// How this function calls `callee`, [`EdgeKind::Direct`] unless recorded otherwise
+    pub fn edge_kind(&self, callee: &str) -> EdgeKind {
+        self.edge_kinds.get(callee).copied().unwrap_or_default()
+    }
+}
+
+/// Serialize a set as a sorted list so saved graphs are deterministic
+fn serialize_sorted<S: serde::Serializer>(
+    set: &HashSet<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(set.iter().collect::<BTreeSet<_>>())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -95,6 +448,32 @@ pub struct Atom {
     pub relative_path: String,
     pub file_name: String,
     pub parent_folder: String,
+    /// Directory of `relative_path`, e.g. `src/field` for `src/field/mod.rs`; empty
+    /// for files at the project root
+    #[serde(default)]
+    pub parent_dir: String,
+    #[serde(default)]
+    pub is_test: bool,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub module_path: String,
+    #[serde(default)]
+    pub crate_name: String,
+    /// Index into [`find_cycles`] of the recursion group this function belongs to,
+    /// `None` if it is not part of a cycle
+    #[serde(default)]
+    pub scc_id: Option<usize>,
+    #[serde(default)]
+    pub is_async: bool,
+    #[serde(default)]
+    pub is_unsafe: bool,
+    #[serde(default)]
+    pub is_const: bool,
+    /// [`symbol_to_path`] of the nearest function or type enclosing this item, e.g.
+    /// the type of a method or the function around a nested function
+    #[serde(default)]
+    pub enclosing: Option<String>,
 }
 
 /// Create a unique key for a function by combining symbol and signature.
@@ -103,6 +482,56 @@ fn make_unique_key(symbol: &str, signature: &str) -> String {
     format!("{}|{}", symbol, signature)
 }
 
+/// Unique key for one of several definitions sharing a symbol, disambiguated by the
+/// definition line so that impls with identical symbol and signature are all kept.
+fn make_unique_key_at(symbol: &str, signature: &str, line: i32) -> String {
+    format!("{}|{}|{}", symbol, signature, line)
+}
+
+/// Id of a string interned in a [`SymbolInterner`]
+type SymbolId = u32;
+
+/// Maps SCIP symbols and node keys to dense `u32` ids while the call graph is built.
+/// Each string is stored once and shared between both directions.
+#[derive(Debug, Default)]
+struct SymbolInterner {
+    ids: FxHashMap<Rc<str>, SymbolId>,
+    strings: Vec<Rc<str>>,
+}
+
+impl SymbolInterner {
+    /// Id of `s`, adding it if it has not been seen yet
+    fn intern(&mut self, s: &str) -> SymbolId {
+        if let Some(&id) = self.ids.get(s) {
+            return id;
+        }
+        let id = SymbolId::try_from(self.strings.len()).expect("more than u32::MAX symbols");
+        let shared: Rc<str> = Rc::from(s);
+        self.strings.push(Rc::clone(&shared));
+        self.ids.insert(shared, id);
+        id
+    }
+
+    /// Id of `s`, if it has been interned
+    fn get(&self, s: &str) -> Option<SymbolId> {
+        self.ids.get(s).copied()
+    }
+
+    /// The string behind an id returned by [`SymbolInterner::intern`]
+    fn resolve(&self, id: SymbolId) -> &str {
+        &self.strings[id as usize]
+    }
+}
+
+/// Edges of one node during the build, as interned ids: callers are node keys,
+/// callees and external callees are symbols
+#[derive(Debug, Default)]
+struct NodeEdges {
+    callers: FxHashSet<SymbolId>,
+    callees: FxHashSet<SymbolId>,
+    external_callees: FxHashSet<SymbolId>,
+}
+
 /// Extract type parameter info from a signature for trait impls.
 /// For example, from "fn mul(self, scalar: &Scalar) -> MontgomeryPoint"
 /// extracts the self type and parameter types to help distinguish impls.
@@ -200,37 +629,347 @@ pub fn parse_scip_json(file_path: &str) -> Result<ScipIndex, Box<dyn std::error:
     Ok(index)
 }
 
+/// Save a built call graph as JSON, so it can be reloaded with [`load_call_graph`]
+/// instead of being rebuilt from SCIP. Output is sorted and therefore deterministic.
+pub fn save_call_graph<P: AsRef<Path>>(
+    call_graph: &HashMap<String, FunctionNode>,
+    path: P,
+) -> std::io::Result<()> {
+    let sorted: BTreeMap<&String, &FunctionNode> = call_graph.iter().collect();
+    let json = serde_json::to_string(&sorted).map_err(std::io::Error::other)?;
+    fs::write(path, json)
+}
+
+/// Load a call graph written by [`save_call_graph`]
+pub fn load_call_graph<P: AsRef<Path>>(path: P) -> std::io::Result<HashMap<String, FunctionNode>> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Common ancestor of a set of project roots. Roots are compared path component by
+/// component after [`normalize_project_root`], so `file://` URIs and plain paths can be
+/// mixed and `/a/foo` and `/a/foobar` share `/a`. The result is a `file://` URI if the
+/// first root is one.
+fn common_project_root(roots: &[&str]) -> String {
+    let Some(first) = roots.first() else {
+        return String::new();
+    };
+    let normalized: Vec<String> = roots
+        .iter()
+        .map(|root| normalize_project_root(root))
+        .collect();
+    let mut common: Vec<Component> = Path::new(&normalized[0]).components().collect();
+    for root in &normalized[1..] {
+        let shared = common
+            .iter()
+            .zip(Path::new(root).components())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        common.truncate(shared);
+    }
+    let path = to_forward_slashes(&common.iter().collect::<PathBuf>().to_string_lossy());
+    if first.starts_with("file://") {
+        format!("file:///{}", path.trim_start_matches('/'))
+    } else {
+        path
+    }
+}
+
+/// Merge several SCIP indexes (e.g. one per workspace crate) into a single index.
+///
+/// Merge semantics:
+/// - The merged `project_root` is the common ancestor of all project roots, and each
+///   document's `relative_path` is rebased onto it so paths stay unique per crate.
+/// - Documents that end up with the same `relative_path` are merged into one: their
+///   occurrences are concatenated with exact duplicates dropped, and symbols are
+///   deduplicated by (symbol, signature). External symbols are deduplicated the same way.
+/// - Tool info and encoding are taken from the first index.
+///
+/// Returns `None` if `indexes` is empty.
+pub fn merge_scip_indexes(indexes: &[ScipIndex]) -> Option<ScipIndex> {
+    let first = indexes.first()?;
+    let roots: Vec<&str> = indexes
+        .iter()
+        .map(|index| index.metadata.project_root.as_str())
+        .collect();
+    let merged_root = common_project_root(&roots);
+    let merged_path = normalize_project_root(&merged_root);
+
+    let mut documents: Vec<Document> = Vec::new();
+    let mut document_by_path: HashMap<String, usize> = HashMap::new();
+    // (range, symbol, roles) of the occurrences already added to each merged document
+    type OccurrenceKey = (Range, String, Option<i32>);
+    let mut seen_occurrences: HashMap<usize, HashSet<OccurrenceKey>> = HashMap::new();
+    let mut seen_symbols: HashMap<usize, HashSet<(String, String)>> = HashMap::new();
+
+    for index in indexes {
+        let root = normalize_project_root(&index.metadata.project_root);
+        let prefix = Path::new(&root)
+            .strip_prefix(&merged_path)
+            .map(|prefix| to_forward_slashes(&prefix.to_string_lossy()))
+            .unwrap_or_default()
+            .trim_matches('/')
+            .to_string();
+        for doc in &index.documents {
+            let rel_path = normalize_relative_path(&doc.relative_path);
+            let rebased_path = if prefix.is_empty() {
+                rel_path
+            } else {
+                format!("{prefix}/{rel_path}")
+            };
+
+            let doc_index = *document_by_path
+                .entry(rebased_path.clone())
+                .or_insert_with(|| {
+                    documents.push(Document {
+                        language: doc.language.clone(),
+                        relative_path: rebased_path,
+                        occurrences: Vec::new(),
+                        symbols: Vec::new(),
+                        position_encoding: doc.position_encoding,
+                    });
+                    documents.len() - 1
+                });
+
+            let occurrences = seen_occurrences.entry(doc_index).or_default();
+            for occurrence in &doc.occurrences {
+                let key = (
+                    occurrence.range,
+                    occurrence.symbol.clone(),
+                    occurrence.symbol_roles,
+                );
+                if occurrences.insert(key) {
+                    documents[doc_index].occurrences.push(occurrence.clone());
+                }
+            }
+
+            let symbols = seen_symbols.entry(doc_index).or_default();
+            for symbol in &doc.symbols {
+                let key = (
+                    symbol.symbol.clone(),
+                    symbol.signature_documentation.text.clone(),
+                );
+                if symbols.insert(key) {
+                    documents[doc_index].symbols.push(symbol.clone());
+                }
+            }
+        }
+    }
+
+    let mut seen_external: HashSet<(&str, &str)> = HashSet::new();
+    let external_symbols = indexes
+        .iter()
+        .flat_map(|index| &index.external_symbols)
+        .filter(|symbol| {
+            seen_external.insert((&symbol.symbol, &symbol.signature_documentation.text))
+        })
+        .cloned()
+        .collect();
+
+    Some(ScipIndex {
+        metadata: Metadata {
+            tool_info: first.metadata.tool_info.clone(),
+            project_root: merged_root,
+            text_document_encoding: first.metadata.text_document_encoding,
+        },
+        documents,
+        external_symbols,
+    })
+}
+
+/// Build a single call graph from several SCIP indexes.
+///
+/// The indexes are merged with [`merge_scip_indexes`] before running the usual passes,
+/// so calls from one crate into another resolve to the callee's definition.
+pub fn build_call_graph_from_indexes(indexes: &[ScipIndex]) -> HashMap<String, FunctionNode> {
+    match merge_scip_indexes(indexes) {
+        Some(merged) => build_call_graph(&merged),
+        None => HashMap::new(),
+    }
+}
+
+/// Use forward slashes as path separators, so that paths from Windows indexes
+/// match the `/`-joined paths built here and the output is portable.
+fn to_forward_slashes(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// Clean up a document's `relative_path`: forward slashes, without leading `/` or
+/// `./` and without empty or `.` segments, e.g. `./src//foo.rs` -> `src/foo.rs`
+fn normalize_relative_path(path: &str) -> String {
+    to_forward_slashes(path)
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Turn a SCIP `project_root` URI into a filesystem path: strips the `file://` scheme,
+/// the slash before a Windows drive letter (`file:///C:/proj` -> `C:/proj`) and any
+/// trailing slash, so that `format!("{root}/{relative_path}")` is directly readable.
+fn normalize_project_root(project_root: &str) -> String {
+    let path = project_root.strip_prefix("file://").unwrap_or(project_root);
+    let bytes = path.as_bytes();
+    let path = if bytes.len() >= 3
+        && bytes[0] == b'/'
+        && bytes[1].is_ascii_alphabetic()
+        && bytes[2] == b':'
+    {
+        &path[1..]
+    } else {
+        path
+    };
+    to_forward_slashes(path).trim_end_matches('/').to_string()
+}
+
 /// Build a call graph from SCIP JSON data
 ///
 /// Note: Multiple trait implementations (e.g., `impl Mul<A> for B` and `impl Mul<B> for A`)
 /// can have the same SCIP symbol string. We use signature_documentation.text to distinguish them.
 pub fn build_call_graph(scip_data: &ScipIndex) -> HashMap<String, FunctionNode> {
-    let mut call_graph: HashMap<String, FunctionNode> = HashMap::new();
-    let mut symbol_to_file: HashMap<String, String> = HashMap::new();
-    let mut symbol_to_kind: HashMap<String, i32> = HashMap::new();
-    let mut function_symbols: HashSet<String> = HashSet::new();
+    build_call_graph_with_tolerance(scip_data, DEFAULT_MATCH_TOLERANCE)
+}
+
+/// Like [`build_call_graph`], but with a custom line tolerance for matching SCIP
+/// definitions to parsed function spans (see
+/// [`find_best_match_with_tolerance`](crate::verus_parser::find_best_match_with_tolerance)).
+pub fn build_call_graph_with_tolerance(
+    scip_data: &ScipIndex,
+    tolerance: usize,
+) -> HashMap<String, FunctionNode> {
+    build_call_graph_with_report(scip_data, tolerance).0
+}
+
+/// Like [`build_call_graph_with_tolerance`], but also returns a warning for every
+/// source file that could not be read while extracting bodies. Nothing is printed;
+/// callers decide how to surface the warnings.
+pub fn build_call_graph_with_report(
+    scip_data: &ScipIndex,
+    tolerance: usize,
+) -> (HashMap<String, FunctionNode>, Vec<String>) {
+    let options = BuildOptions {
+        tolerance,
+        ..BuildOptions::default()
+    };
+    build_call_graph_with_options(scip_data, &options)
+}
+
+/// Build only the edge structure: every `FunctionNode.body` is left as `None` and no
+/// source file is read, which is much faster on large projects.
+pub fn build_call_graph_edges_only(scip_data: &ScipIndex) -> HashMap<String, FunctionNode> {
+    let options = BuildOptions {
+        extract_bodies: false,
+        ..BuildOptions::default()
+    };
+    build_call_graph_with_options(scip_data, &options).0
+}
+
+/// Options for [`build_call_graph_with_options`]
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// Line tolerance for matching SCIP definitions to parsed function spans
+    pub tolerance: usize,
+    /// Read source files to fill in `FunctionNode.body` (and the span-derived flags)
+    pub extract_bodies: bool,
+    /// Macros whose bodies are parsed as items when looking for spans, by every span
+    /// cache bodies are extracted through
+    pub item_macros: Vec<String>,
+    /// Read bodies through memory-mapped source files (needs the `mmap` feature,
+    /// otherwise a warning is reported and files are read normally)
+    pub mmap: bool,
+    /// Drop test functions and everything under `#[cfg(test)]`, see
+    /// [`exclude_test_functions`] (needs `extract_bodies`)
+    pub exclude_tests: bool,
+    /// Also add an edge to every implementation when a called trait method has
+    /// several of them in the graph (the edge to the trait method is kept)
+    pub fan_out_trait_calls: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            tolerance: DEFAULT_MATCH_TOLERANCE,
+            extract_bodies: true,
+            item_macros: DEFAULT_ITEM_MACROS.iter().map(|m| m.to_string()).collect(),
+            mmap: false,
+            exclude_tests: false,
+            fan_out_trait_calls: false,
+        }
+    }
+}
+
+/// Build a call graph with explicit [`BuildOptions`], returning the warnings collected
+/// along the way (see [`build_call_graph_with_report`])
+pub fn build_call_graph_with_options(
+    scip_data: &ScipIndex,
+    options: &BuildOptions,
+) -> (HashMap<String, FunctionNode>, Vec<String>) {
+    build_call_graph_with_progress(scip_data, options, |_, _, _| {})
+}
+
+/// Phase of [`build_call_graph_with_progress`] reported to the progress callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildPhase {
+    /// Collecting symbol definitions, counted in documents
+    SymbolCollection,
+    /// Creating a node per function or type, counted in documents
+    NodeCreation,
+    /// Building call edges from occurrences, counted in documents
+    EdgeBuilding,
+    /// Extracting bodies from source files, counted in nodes
+    BodyExtraction,
+}
+
+/// Like [`build_call_graph_with_options`], calling `progress(phase, completed, total)`
+/// before each document (or node) is processed and once more with `completed == total`
+/// when the phase ends. Body extraction is not reported when it is disabled.
+pub fn build_call_graph_with_progress(
+    scip_data: &ScipIndex,
+    options: &BuildOptions,
+    mut progress: impl FnMut(BuildPhase, usize, usize),
+) -> (HashMap<String, FunctionNode>, Vec<String>) {
+    let total_documents = scip_data.documents.len();
+    let mut warnings = Vec::new();
+    // Symbols and node keys are interned, so the passes below store and compare `u32`
+    // ids instead of cloning strings; they are resolved back once the edges are built
+    let mut interner = SymbolInterner::default();
+    let mut nodes: FxHashMap<SymbolId, FunctionNode> = FxHashMap::default();
+    let mut edges: FxHashMap<SymbolId, NodeEdges> = FxHashMap::default();
+    // Keys of all nodes sharing a symbol, since references only carry the base symbol
+    let mut nodes_by_symbol: FxHashMap<SymbolId, Vec<SymbolId>> = FxHashMap::default();
+    let mut function_symbols: FxHashSet<SymbolId> = FxHashSet::default();
 
     // Pre-pass: Find where each symbol is DEFINED (symbol_roles == 1)
     // Collect ALL definition occurrences per symbol (there may be multiple for trait impls)
     // Maps symbol -> Vec<(abs_path, rel_path, line_number)>
-    let mut symbol_to_definitions: HashMap<String, Vec<(String, String, i32)>> = HashMap::new();
-    for doc in &scip_data.documents {
-        let project_root = &scip_data.metadata.project_root;
-        let rel_path = doc.relative_path.trim_start_matches('/');
+    let mut symbol_to_definitions: FxHashMap<SymbolId, Vec<(String, String, i32)>> =
+        FxHashMap::default();
+    let project_root = normalize_project_root(&scip_data.metadata.project_root);
+    let enclosure = symbol_enclosure(scip_data);
+    for (completed, doc) in scip_data.documents.iter().enumerate() {
+        progress(BuildPhase::SymbolCollection, completed, total_documents);
+        let rel_path = normalize_relative_path(&doc.relative_path);
         let abs_path = format!("{project_root}/{rel_path}");
 
         for occurrence in &doc.occurrences {
             let is_definition = occurrence.symbol_roles.unwrap_or(0) & 1 == 1;
-            if is_definition && !occurrence.range.is_empty() {
-                let line = occurrence.range[0];
+            if is_definition {
+                let line = occurrence.range.start_line;
                 symbol_to_definitions
-                    .entry(occurrence.symbol.clone())
+                    .entry(interner.intern(&occurrence.symbol))
                     .or_default()
-                    .push((abs_path.clone(), rel_path.to_string(), line));
+                    .push((abs_path.clone(), rel_path.clone(), line));
             }
         }
     }
 
+    progress(
+        BuildPhase::SymbolCollection,
+        total_documents,
+        total_documents,
+    );
+
     // Sort definitions by line number for consistent matching with symbol entries
     for defs in symbol_to_definitions.values_mut() {
         defs.sort_by_key(|(_, _, line)| *line);
@@ -242,182 +981,550 @@ pub fn build_call_graph(scip_data: &ScipIndex) -> HashMap<String, FunctionNode>
     );
 
     // Track how many times we've seen each symbol to match with definition order
-    let mut symbol_seen_count: HashMap<String, usize> = HashMap::new();
+    let mut symbol_seen_count: FxHashMap<SymbolId, usize> = FxHashMap::default();
+    // Map from (symbol, definition line) -> unique_key for occurrence processing
+    let mut symbol_line_to_key: FxHashMap<(SymbolId, i32), SymbolId> = FxHashMap::default();
+
+    // Nodes without a display name, and whether their symbol had a descriptor to name
+    // them after (see `node_display_name`)
+    let mut unnamed_keys: FxHashMap<SymbolId, bool> = FxHashMap::default();
+    // Symbol entries skipped because an earlier entry produced the same key
+    let mut duplicate_keys = 0usize;
 
     // First pass: identify all function symbols and handle duplicates
-    for doc in &scip_data.documents {
+    for (completed, doc) in scip_data.documents.iter().enumerate() {
+        progress(BuildPhase::NodeCreation, completed, total_documents);
         for symbol in &doc.symbols {
-            // Check if this is a function-like symbol (kind 12, 17, 80 etc.)
-            if is_function_like(symbol.kind) {
+            // Check if this is a function-like symbol, a type definition or a const/static.
+            // Local symbols (closures, local bindings) are only unique within their document,
+            // so they would collide across files; they never become nodes.
+            if is_graph_node_kind(symbol.kind) && !is_local_symbol(&symbol.symbol) {
                 let signature = &symbol.signature_documentation.text;
-                let display_name = symbol
-                    .display_name
-                    .clone()
-                    .unwrap_or_else(|| "unknown".to_string());
+                let symbol_id = interner.intern(&symbol.symbol);
 
                 // Track ALL function symbols for dependency tracking
-                function_symbols.insert(symbol.symbol.clone());
-
-                // Create unique key using signature to handle duplicate symbols
-                let unique_key = make_unique_key(&symbol.symbol, signature);
+                function_symbols.insert(symbol_id);
 
                 // Get the nth definition for this symbol (matching symbol entry order with def order)
-                let def_index = *symbol_seen_count.get(&symbol.symbol).unwrap_or(&0);
-                symbol_seen_count
-                    .entry(symbol.symbol.clone())
-                    .and_modify(|c| *c += 1)
-                    .or_insert(1);
+                let seen = symbol_seen_count.entry(symbol_id).or_insert(0);
+                let def_index = *seen;
+                *seen += 1;
+                let defs = symbol_to_definitions.get(&symbol_id);
+                let definition = defs.and_then(|defs| defs.get(def_index));
+
+                // Create unique key using signature to handle duplicate symbols; symbols
+                // with several definitions also get the definition line
+                let unique_key = match (defs, definition) {
+                    (Some(defs), Some((_, _, line))) if defs.len() > 1 => {
+                        make_unique_key_at(&symbol.symbol, signature, *line)
+                    }
+                    _ => make_unique_key(&symbol.symbol, signature),
+                };
+                let key_id = interner.intern(&unique_key);
+                if let Some((_, _, line)) = definition {
+                    symbol_line_to_key.insert((symbol_id, *line), key_id);
+                }
+                // Two entries with the same key: keep the first node
+                if nodes.contains_key(&key_id) {
+                    debug!("Skipping a second symbol entry with key {unique_key}");
+                    duplicate_keys += 1;
+                    continue;
+                }
 
                 // Use the DEFINITION location if available, otherwise fall back to symbols array location
-                let (abs_path, rel_path) =
-                    if let Some(defs) = symbol_to_definitions.get(&symbol.symbol) {
-                        if let Some((def_abs, def_rel, _line)) = defs.get(def_index) {
-                            (def_abs.clone(), def_rel.clone())
-                        } else if let Some((def_abs, def_rel, _)) = defs.first() {
-                            // Fallback to first definition
-                            (def_abs.clone(), def_rel.clone())
-                        } else {
-                            // Fallback: use the document where the symbol appears in symbols array
-                            let project_root = &scip_data.metadata.project_root;
-                            let rel_path = doc.relative_path.trim_start_matches('/');
-                            let abs_path = format!("{project_root}/{rel_path}");
-                            (abs_path, rel_path.to_string())
-                        }
-                    } else {
+                let (abs_path, rel_path) = match definition.or_else(|| defs?.first()) {
+                    Some((def_abs, def_rel, _line)) => (def_abs.clone(), def_rel.clone()),
+                    None => {
                         // Fallback: use the document where the symbol appears in symbols array
-                        let project_root = &scip_data.metadata.project_root;
-                        let rel_path = doc.relative_path.trim_start_matches('/');
+                        let rel_path = normalize_relative_path(&doc.relative_path);
                         let abs_path = format!("{project_root}/{rel_path}");
-                        (abs_path, rel_path.to_string())
-                    };
-
-                symbol_to_file.insert(symbol.symbol.clone(), abs_path.clone());
-                symbol_to_kind.insert(symbol.symbol.clone(), symbol.kind);
+                        (abs_path, rel_path)
+                    }
+                };
 
                 // Initialize node in the call graph with UNIQUE KEY
-                call_graph.insert(
-                    unique_key,
+                nodes.insert(
+                    key_id,
                     FunctionNode {
                         symbol: symbol.symbol.clone(),
-                        display_name,
+                        display_name: node_display_name(symbol, key_id, &mut unnamed_keys),
                         signature_text: signature.clone(),
                         file_path: abs_path,
                         relative_path: rel_path,
-                        callers: HashSet::new(),
+                        callers: HashSet::new(), // Filled from `edges` after the second pass
                         callees: HashSet::new(),
                         range: Vec::new(), // Will be filled in the second pass
                         body: None,        // Will be filled after ranges are set
+                        kind: symbol.kind,
+                        enclosing_range: None, // Will be filled in the second pass
+                        external_callees: HashSet::new(),
+                        is_test: false,                  // Will be set with the body
+                        visibility: Visibility::Private, // Will be set with the body
+                        is_async: false,
+                        is_unsafe: false,
+                        is_const: false,
+                        is_external: false,
+                        enclosing: enclosure.get(symbol.symbol.as_str()).map(|s| s.to_string()),
+                        edge_kinds: BTreeMap::new(), // Filled from `edges` after the second pass
                     },
                 );
+                nodes_by_symbol.entry(symbol_id).or_default().push(key_id);
             }
         }
     }
+    if duplicate_keys > 0 {
+        warnings.push(format!(
+            "{duplicate_keys} symbol(s) had the same key as an earlier symbol entry; kept the first"
+        ));
+    }
 
-    // Build a map from (symbol, line) -> unique_key for occurrence processing
-    let mut symbol_line_to_key: HashMap<(String, i32), String> = HashMap::new();
-    let mut symbol_seen_for_lines: HashMap<String, usize> = HashMap::new();
-    for doc in &scip_data.documents {
-        for symbol in &doc.symbols {
-            if is_function_like(symbol.kind) {
-                let signature = &symbol.signature_documentation.text;
-                let unique_key = make_unique_key(&symbol.symbol, signature);
-
-                if call_graph.contains_key(&unique_key) {
-                    let def_index = *symbol_seen_for_lines.get(&symbol.symbol).unwrap_or(&0);
-                    symbol_seen_for_lines
-                        .entry(symbol.symbol.clone())
-                        .and_modify(|c| *c += 1)
-                        .or_insert(1);
-
-                    if let Some(defs) = symbol_to_definitions.get(&symbol.symbol) {
-                        if let Some((_, _, line)) = defs.get(def_index) {
-                            symbol_line_to_key.insert((symbol.symbol.clone(), *line), unique_key);
-                        }
-                    }
-                }
-            }
+    // Stub nodes for functions defined outside the project, so that calls into
+    // dependencies resolve to a node; stubs nobody calls are dropped after the edges
+    // are built. Project definitions of the same symbol take precedence.
+    let mut stub_keys: FxHashSet<SymbolId> = FxHashSet::default();
+    for symbol in &scip_data.external_symbols {
+        if !is_function_like(symbol.kind) || is_local_symbol(&symbol.symbol) {
+            continue;
+        }
+        let symbol_id = interner.intern(&symbol.symbol);
+        if !function_symbols.insert(symbol_id) {
+            continue;
         }
+        let signature = &symbol.signature_documentation.text;
+        let key_id = interner.intern(&make_unique_key(&symbol.symbol, signature));
+        nodes.insert(
+            key_id,
+            FunctionNode {
+                symbol: symbol.symbol.clone(),
+                display_name: node_display_name(symbol, key_id, &mut unnamed_keys),
+                signature_text: signature.clone(),
+                file_path: String::new(),
+                relative_path: String::new(),
+                callers: HashSet::new(),
+                callees: HashSet::new(),
+                range: Vec::new(),
+                body: None,
+                kind: symbol.kind,
+                enclosing_range: None,
+                external_callees: HashSet::new(),
+                is_test: false,
+                visibility: Visibility::Public,
+                is_async: false,
+                is_unsafe: false,
+                is_const: false,
+                is_external: true,
+                enclosing: enclosure.get(symbol.symbol.as_str()).map(|s| s.to_string()),
+                edge_kinds: BTreeMap::new(),
+            },
+        );
+        nodes_by_symbol.entry(symbol_id).or_default().push(key_id);
+        stub_keys.insert(key_id);
     }
 
+    progress(BuildPhase::NodeCreation, total_documents, total_documents);
+
     // Second pass: analyze occurrences to build the call graph
-    for doc in &scip_data.documents {
+    for (completed, doc) in scip_data.documents.iter().enumerate() {
+        progress(BuildPhase::EdgeBuilding, completed, total_documents);
         // Track the current function context we're in (now using unique key)
-        let mut current_function_key: Option<String> = None;
+        let mut current_function_key: Option<SymbolId> = None;
 
         // Sort occurrences by range to process them in order of appearance
-        let mut ordered_occurrences = doc.occurrences.clone();
-        ordered_occurrences.sort_by(|a, b| {
-            let a_start = (a.range[0], a.range[1]);
-            let b_start = (b.range[0], b.range[1]);
-            a_start.cmp(&b_start)
-        });
+        let mut ordered_occurrences: Vec<&Occurrence> = doc.occurrences.iter().collect();
+        ordered_occurrences.sort_by_key(|o| (o.range.start_line, o.range.start_col));
 
-        for occurrence in &ordered_occurrences {
+        for occurrence in ordered_occurrences {
             let is_definition = occurrence.symbol_roles.unwrap_or(0) & 1 == 1;
-            let line = if !occurrence.range.is_empty() {
-                occurrence.range[0]
-            } else {
-                -1
-            };
+            let line = occurrence.range.start_line;
+            let project_symbol = interner
+                .get(&occurrence.symbol)
+                .filter(|id| function_symbols.contains(id));
 
             // Track when we enter a project function definition
-            if is_definition && function_symbols.contains(&occurrence.symbol) {
+            if let (true, Some(symbol_id)) = (is_definition, project_symbol) {
                 // Look up the unique key for this (symbol, line) pair
-                if let Some(key) = symbol_line_to_key.get(&(occurrence.symbol.clone(), line)) {
-                    current_function_key = Some(key.clone());
-                    if let Some(node) = call_graph.get_mut(key) {
-                        node.range = occurrence.range.clone();
+                if let Some(&key) = symbol_line_to_key.get(&(symbol_id, line)) {
+                    current_function_key = Some(key);
+                    if let Some(node) = nodes.get_mut(&key) {
+                        node.range = occurrence.range.to_scip();
+                        node.enclosing_range = occurrence.enclosing_range;
                     }
                 }
             }
 
+            if is_definition {
+                continue;
+            }
+            let Some(caller_key) = current_function_key else {
+                continue;
+            };
+
             // Track ALL function calls (including to external functions)
             // Note: References use the base symbol, not the unique key
-            if !is_definition && function_symbols.contains(&occurrence.symbol) {
-                if let Some(caller_key) = &current_function_key {
-                    if let Some(caller_node) = call_graph.get_mut(caller_key) {
-                        // For callees, we store the base symbol (not unique key)
-                        // since references don't have signature info
-                        if caller_node.symbol != occurrence.symbol {
-                            caller_node.callees.insert(occurrence.symbol.clone());
-                        }
-                    }
-                }
-
-                // Also update the callee's callers (find by symbol match)
-                // We need to find all nodes with this symbol
-                for (key, node) in call_graph.iter_mut() {
-                    if node.symbol == occurrence.symbol {
-                        if let Some(caller_key) = &current_function_key {
-                            if key != caller_key {
-                                node.callers.insert(caller_key.clone());
-                            }
-                        }
-                    }
+            if let Some(symbol_id) = project_symbol {
+                // For callees, we store the base symbol (not unique key)
+                // since references don't have signature info.
+                // Recursive self-calls are kept as self-edges.
+                edges
+                    .entry(caller_key)
+                    .or_default()
+                    .callees
+                    .insert(symbol_id);
+
+                // Also update the callers of every node with this symbol
+                for &callee_key in nodes_by_symbol.get(&symbol_id).into_iter().flatten() {
+                    edges
+                        .entry(callee_key)
+                        .or_default()
+                        .callers
+                        .insert(caller_key);
                 }
+            } else if is_external_function_symbol(&occurrence.symbol) {
+                // Calls to functions defined outside the project are kept apart
+                let external_id = interner.intern(&occurrence.symbol);
+                edges
+                    .entry(caller_key)
+                    .or_default()
+                    .external_callees
+                    .insert(external_id);
             }
         }
     }
 
-    // Third pass: extract function bodies using verus_syn parser
-    // This approach is much cleaner than brace-counting and handles all Verus syntax
-    let mut span_cache = FileSpanCache::new();
+    // Calls to a trait method land on the trait's symbol; move them onto the impl
+    let implementations =
+        trait_implementations(scip_data, &mut interner, &nodes_by_symbol, &stub_keys);
+    let default_bodies = trait_default_bodies(scip_data, &interner, &implementations);
+    let (rewritten, fanned_out) = resolve_trait_calls(
+        &mut edges,
+        &implementations,
+        &default_bodies,
+        &nodes_by_symbol,
+        options.fan_out_trait_calls,
+    );
+    debug!("Resolved {rewritten} trait method calls to their implementation");
+
+    // Edge kinds follow from the callee: a trait method (implemented in the project
+    // or declared inside a trait) is dispatched, an implementing method is static
+    let trait_symbols: FxHashSet<&str> = scip_data
+        .documents
+        .iter()
+        .flat_map(|doc| &doc.symbols)
+        .chain(&scip_data.external_symbols)
+        .filter(|symbol| symbol.symbol_kind() == Some(SymbolKind::Trait))
+        .map(|symbol| symbol.symbol.as_str())
+        .collect();
+    let trait_methods: FxHashSet<SymbolId> = enclosure
+        .iter()
+        .filter(|(_, enclosing)| trait_symbols.contains(*enclosing))
+        .filter_map(|(symbol, _)| interner.get(symbol))
+        .chain(implementations.keys().copied())
+        .collect();
+    let implementing: FxHashSet<SymbolId> = implementations.values().flatten().copied().collect();
+    let edge_kind = |caller_key: SymbolId, callee: SymbolId| {
+        if fanned_out.contains(&(caller_key, callee)) || trait_methods.contains(&callee) {
+            EdgeKind::TraitDynamic
+        } else if implementing.contains(&callee) {
+            EdgeKind::TraitStatic
+        } else {
+            EdgeKind::Direct
+        }
+    };
 
-    for node in call_graph.values_mut() {
-        if !node.range.is_empty() {
-            let file_path = &node.file_path;
+    // Stubs nobody calls are dropped, so they are not counted as unnamed either
+    let is_kept = |key_id: &SymbolId| {
+        !stub_keys.contains(key_id) || edges.get(key_id).is_some_and(|e| !e.callers.is_empty())
+    };
+    let unnamed_count = |descriptor_named: bool| {
+        unnamed_keys
+            .iter()
+            .filter(|&(key_id, &named)| named == descriptor_named && is_kept(key_id))
+            .count()
+    };
+    let (descriptor_named, unknown_named) = (unnamed_count(true), unnamed_count(false));
+    if descriptor_named > 0 {
+        warnings.push(format!(
+            "{descriptor_named} symbol(s) had no display name; named after their last SCIP descriptor"
+        ));
+    }
+    if unknown_named > 0 {
+        warnings.push(format!(
+            "{unknown_named} symbol(s) had no display name and no parsable SCIP descriptor; named `unknown`"
+        ));
+    }
 
-            // Clean up file path if it starts with file:// prefix
-            let clean_path = if file_path.starts_with("file://") {
-                file_path.trim_start_matches("file://")
-            } else {
-                file_path
-            };
+    // Resolve ids back to strings at the API boundary
+    let resolve = |ids: &FxHashSet<SymbolId>| -> HashSet<String> {
+        ids.iter()
+            .map(|&id| interner.resolve(id).to_string())
+            .collect()
+    };
+    let mut call_graph: HashMap<String, FunctionNode> = nodes
+        .into_iter()
+        .filter(|(key_id, _)| is_kept(key_id))
+        .map(|(key_id, mut node)| {
+            if let Some(node_edges) = edges.get(&key_id) {
+                node.callers = resolve(&node_edges.callers);
+                node.callees = resolve(&node_edges.callees);
+                node.external_callees = resolve(&node_edges.external_callees);
+                node.edge_kinds = node_edges
+                    .callees
+                    .iter()
+                    .map(|&callee| (callee, edge_kind(key_id, callee)))
+                    .filter(|(_, kind)| *kind != EdgeKind::Direct)
+                    .map(|(callee, kind)| (interner.resolve(callee).to_string(), kind))
+                    .collect();
+            }
+            (interner.resolve(key_id).to_string(), node)
+        })
+        .collect();
 
-            debug!(
-                "Extracting body for {} from {}",
-                node.display_name, clean_path
-            );
+    progress(BuildPhase::EdgeBuilding, total_documents, total_documents);
+
+    if !options.extract_bodies {
+        if options.exclude_tests {
+            warnings.push(
+                "Test functions are only detected with bodies; none were excluded".to_string(),
+            );
+        }
+        return (call_graph, warnings);
+    }
+
+    // Third pass: extract function bodies using verus_syn parser
+    extract_bodies(
+        call_graph.values_mut().collect(),
+        options,
+        &mut warnings,
+        &mut progress,
+    );
+    if options.exclude_tests {
+        let removed = exclude_test_functions(&mut call_graph);
+        debug!("Excluded {removed} test functions");
+    }
+    (call_graph, warnings)
+}
+
+/// Project implementations of each trait method, from the `is_implementation`
+/// relationships of the index. Only implementations that are (non-stub) nodes of
+/// the graph are kept, sorted so the result does not depend on document order.
+fn trait_implementations(
+    scip_data: &ScipIndex,
+    interner: &mut SymbolInterner,
+    nodes_by_symbol: &FxHashMap<SymbolId, Vec<SymbolId>>,
+    stub_keys: &FxHashSet<SymbolId>,
+) -> FxHashMap<SymbolId, Vec<SymbolId>> {
+    let mut implementations: FxHashMap<SymbolId, Vec<SymbolId>> = FxHashMap::default();
+    for symbol in scip_data.documents.iter().flat_map(|doc| &doc.symbols) {
+        let Some(impl_id) = interner.get(&symbol.symbol) else {
+            continue;
+        };
+        let is_node = nodes_by_symbol
+            .get(&impl_id)
+            .is_some_and(|keys| keys.iter().any(|key| !stub_keys.contains(key)));
+        if !is_node {
+            continue;
+        }
+        for relationship in &symbol.relationships {
+            if relationship.is_implementation && relationship.symbol != symbol.symbol {
+                let trait_id = interner.intern(&relationship.symbol);
+                implementations.entry(trait_id).or_default().push(impl_id);
+            }
+        }
+    }
+    for impls in implementations.values_mut() {
+        impls.sort_by(|a, b| interner.resolve(*a).cmp(interner.resolve(*b)));
+        impls.dedup();
+    }
+    implementations
+}
+
+/// Trait methods in `implementations` whose default body runs for some type: the
+/// trait has more implementing types in the index than the method has overrides.
+fn trait_default_bodies(
+    scip_data: &ScipIndex,
+    interner: &SymbolInterner,
+    implementations: &FxHashMap<SymbolId, Vec<SymbolId>>,
+) -> FxHashSet<SymbolId> {
+    let mut implementors: FxHashMap<&str, FxHashSet<&str>> = FxHashMap::default();
+    let all_symbols = scip_data
+        .documents
+        .iter()
+        .flat_map(|doc| &doc.symbols)
+        .chain(&scip_data.external_symbols);
+    for symbol in all_symbols {
+        for relationship in &symbol.relationships {
+            if relationship.is_implementation && relationship.symbol != symbol.symbol {
+                implementors
+                    .entry(relationship.symbol.as_str())
+                    .or_default()
+                    .insert(symbol.symbol.as_str());
+            }
+        }
+    }
+    let count = |symbol: &str| implementors.get(symbol).map_or(0, |set| set.len());
+    implementations
+        .keys()
+        .copied()
+        .filter(|&method| {
+            let method = interner.resolve(method);
+            method_owner(method).is_some_and(|owner| count(owner) > count(method))
+        })
+        .collect()
+}
+
+/// Symbol of the type or trait a method symbol belongs to, e.g. `.../Shape#` for
+/// `.../Shape#area().`
+fn method_owner(symbol: &str) -> Option<&str> {
+    let head = symbol.strip_suffix(").")?;
+    let open = head.rfind('(')?;
+    let owner_end = head[..open].rfind('#')? + 1;
+    Some(&symbol[..owner_end])
+}
+
+/// Point calls to a trait method with a single implementation in `implementations`
+/// at that implementation instead, whether the trait method is a project node or
+/// an external callee. A trait method in `default_bodies` counts as one more
+/// candidate, since its own body runs for the types that do not override it. Calls
+/// to trait methods with several candidates keep their edge, and with `fan_out`
+/// also get one to every implementation. Returns the number of edges rewritten or
+/// added, and the `(caller key, impl symbol)` edges that only exist because of the
+/// fan-out.
+fn resolve_trait_calls(
+    edges: &mut FxHashMap<SymbolId, NodeEdges>,
+    implementations: &FxHashMap<SymbolId, Vec<SymbolId>>,
+    default_bodies: &FxHashSet<SymbolId>,
+    nodes_by_symbol: &FxHashMap<SymbolId, Vec<SymbolId>>,
+    fan_out: bool,
+) -> (usize, FxHashSet<(SymbolId, SymbolId)>) {
+    let keys_of = |symbol: &SymbolId| nodes_by_symbol.get(symbol).into_iter().flatten();
+    // (node key, caller key) pairs to apply once the callee sets are updated
+    let mut added_callers = Vec::new();
+    let mut removed_callers = Vec::new();
+    let mut fanned_out = FxHashSet::default();
+    for (&caller_key, node_edges) in edges.iter_mut() {
+        let trait_calls: Vec<SymbolId> = node_edges
+            .callees
+            .iter()
+            .chain(&node_edges.external_callees)
+            .filter(|symbol| implementations.contains_key(symbol))
+            .copied()
+            .collect();
+        for trait_symbol in trait_calls {
+            let impls = &implementations[&trait_symbol];
+            let fan_out_call = match impls.as_slice() {
+                [_] if !default_bodies.contains(&trait_symbol) => {
+                    node_edges.callees.remove(&trait_symbol);
+                    node_edges.external_callees.remove(&trait_symbol);
+                    removed_callers.extend(keys_of(&trait_symbol).map(|&key| (key, caller_key)));
+                    false
+                }
+                _ if !fan_out => continue,
+                _ => true,
+            };
+            for impl_symbol in impls {
+                if node_edges.callees.insert(*impl_symbol) && fan_out_call {
+                    fanned_out.insert((caller_key, *impl_symbol));
+                }
+                added_callers.extend(keys_of(impl_symbol).map(|&key| (key, caller_key)));
+            }
+        }
+    }
+
+    for (key, caller) in &removed_callers {
+        if let Some(node_edges) = edges.get_mut(key) {
+            node_edges.callers.remove(caller);
+        }
+    }
+    let rewritten = added_callers.len();
+    for (key, caller) in added_callers {
+        edges.entry(key).or_default().callers.insert(caller);
+    }
+    (rewritten, fanned_out)
+}
+
+/// Bring `call_graph`, built from an earlier index, up to date with `scip_data` after
+/// the documents at `changed_paths` (relative to the project root) were edited.
+///
+/// The edge passes run over the whole index again, since adding or removing a
+/// definition can change how references in unchanged documents resolve; they are
+/// cheap compared to reading sources. Bodies are only re-extracted for nodes in
+/// changed documents and for nodes that did not exist before; every other node keeps
+/// the body and span-derived flags it already had. Returns the build warnings.
+pub fn update_call_graph(
+    call_graph: &mut HashMap<String, FunctionNode>,
+    scip_data: &ScipIndex,
+    changed_paths: &[String],
+    options: &BuildOptions,
+) -> Vec<String> {
+    let changed: HashSet<String> = changed_paths
+        .iter()
+        .map(|path| normalize_relative_path(path))
+        .collect();
+    let edge_options = BuildOptions {
+        extract_bodies: false,
+        exclude_tests: false,
+        ..options.clone()
+    };
+    let (mut updated, mut warnings) = build_call_graph_with_options(scip_data, &edge_options);
+
+    let mut previous = std::mem::take(call_graph);
+    let mut stale = Vec::new();
+    for (key, node) in updated.iter_mut() {
+        match previous.remove(key) {
+            Some(old)
+                if !changed.contains(&node.relative_path)
+                    && old.relative_path == node.relative_path =>
+            {
+                node.body = old.body;
+                node.is_test = old.is_test;
+                node.visibility = old.visibility;
+                node.is_async = old.is_async;
+                node.is_unsafe = old.is_unsafe;
+                node.is_const = old.is_const;
+            }
+            _ => stale.push(node),
+        }
+    }
+    if options.extract_bodies {
+        extract_bodies(stale, options, &mut warnings, |_, _, _| {});
+        if options.exclude_tests {
+            exclude_test_functions(&mut updated);
+        }
+    }
+    *call_graph = updated;
+    warnings
+}
+
+/// Third pass of the build: fill in `body` and the span-derived flags of `nodes` from
+/// their source files, using the verus_syn parser (which handles all Verus syntax)
+/// with SCIP enclosing ranges and brace counting as fallbacks
+fn extract_bodies(
+    nodes: Vec<&mut FunctionNode>,
+    options: &BuildOptions,
+    warnings: &mut Vec<String>,
+    mut progress: impl FnMut(BuildPhase, usize, usize),
+) {
+    let mut span_cache = FileSpanCache::with_item_macros(options.item_macros.clone());
+    #[cfg(feature = "mmap")]
+    if options.mmap {
+        span_cache = span_cache.with_mmap();
+    }
+    #[cfg(not(feature = "mmap"))]
+    if options.mmap {
+        warnings.push("mmap was requested, but the `mmap` feature is not enabled".to_string());
+    }
+    let tolerance = options.tolerance;
+    let total_nodes = nodes.len();
+
+    for (completed, node) in nodes.into_iter().enumerate() {
+        progress(BuildPhase::BodyExtraction, completed, total_nodes);
+        if !node.range.is_empty() {
+            let clean_path = node.file_path.as_str();
+
+            debug!(
+                "Extracting body for {} from {}",
+                node.display_name, clean_path
+            );
 
             // SCIP gives us the line where the function name appears (0-indexed)
             // Add 1 to convert to 1-indexed for verus_syn
@@ -428,7 +1535,20 @@ pub fn build_call_graph(scip_data: &ScipIndex) -> HashMap<String, FunctionNode>
             };
 
             // Try to get body using verus_syn parser
-            match span_cache.get_function_body(clean_path, &node.display_name, approx_line) {
+            let body = span_cache
+                .find_function(clean_path, &node.display_name, approx_line, tolerance)
+                .and_then(|span| match span {
+                    Some(span) => {
+                        node.is_test = span.is_test;
+                        node.visibility = span.visibility;
+                        node.is_async = span.is_async;
+                        node.is_unsafe = span.is_unsafe;
+                        node.is_const = span.is_const;
+                        span_cache.read_body(clean_path, &span).map(Some)
+                    }
+                    None => Ok(None),
+                });
+            match body {
                 Ok(Some(body)) => {
                     let body_len = body.len();
                     node.body = Some(body);
@@ -448,32 +1568,92 @@ pub fn build_call_graph(scip_data: &ScipIndex) -> HashMap<String, FunctionNode>
                         node.display_name, clean_path
                     );
 
+                    let contents = match fs::read_to_string(clean_path) {
+                        Ok(contents) => contents,
+                        Err(e) => {
+                            warnings.push(format!(
+                                "Failed to read {} for body extraction of {}: {e}",
+                                clean_path, node.display_name
+                            ));
+                            continue;
+                        }
+                    };
+
+                    // Prefer the definition's enclosing range from SCIP, which
+                    // gives exact end positions
+                    if let Some(enclosing) = node.enclosing_range {
+                        let body = enclosing.extract(&contents);
+                        if !body.is_empty() {
+                            debug!(
+                                "Extracted body for {} from enclosing range, length: {}",
+                                node.display_name,
+                                body.len()
+                            );
+                            node.body = Some(body);
+                            continue;
+                        }
+                    }
+
                     // Fallback: use brace-counting to extract body
-                    if let Ok(contents) = fs::read_to_string(clean_path) {
-                        let lines: Vec<&str> = contents.lines().collect();
-                        let start_line = node.range[0] as usize;
-                        if start_line < lines.len() {
-                            let body = extract_body_with_brace_counting(&lines, start_line);
-                            if !body.is_empty() {
-                                let body_len = body.len();
-                                node.body = Some(body);
-                                debug!(
-                                    "Extracted body for {} using fallback, length: {}",
-                                    node.display_name, body_len
-                                );
-                            }
+                    // (not for type definitions, which may have no braces at all)
+                    if !is_function_like(node.kind) {
+                        continue;
+                    }
+                    let lines: Vec<&str> = contents.lines().collect();
+                    let start_line = node.range[0] as usize;
+                    if start_line < lines.len() {
+                        let body = extract_body_with_brace_counting(
+                            &lines,
+                            start_line,
+                            line_ending(&contents),
+                        );
+                        if !body.is_empty() {
+                            let body_len = body.len();
+                            node.body = Some(body);
+                            debug!(
+                                "Extracted body for {} using fallback, length: {}",
+                                node.display_name, body_len
+                            );
                         }
                     }
                 }
             }
         }
     }
-    call_graph
+    progress(BuildPhase::BodyExtraction, total_nodes, total_nodes);
+}
+
+/// Remove the nodes marked `is_test` (`#[test]` functions and items inside
+/// `#[cfg(test)]` modules) along with their edges: they are dropped from the `callers`
+/// of the remaining nodes, and their symbols from `callees` unless another node with
+/// the same symbol is kept. Returns the number of nodes removed.
+pub fn exclude_test_functions(call_graph: &mut HashMap<String, FunctionNode>) -> usize {
+    let before = call_graph.len();
+    call_graph.retain(|_, node| !node.is_test);
+    let kept_symbols: HashSet<String> = call_graph.values().map(|n| n.symbol.clone()).collect();
+    let kept_keys: HashSet<String> = call_graph.keys().cloned().collect();
+    for node in call_graph.values_mut() {
+        node.callers.retain(|caller| kept_keys.contains(caller));
+        node.callees.retain(|callee| kept_symbols.contains(callee));
+        node.edge_kinds
+            .retain(|callee, _| kept_symbols.contains(callee));
+    }
+    before - call_graph.len()
+}
+
+/// Whether a global SCIP symbol names a function or method (descriptor ending in `().`)
+fn is_external_function_symbol(symbol: &str) -> bool {
+    scip_symbol_descriptors(symbol).is_some_and(|descriptors| descriptors.ends_with(")."))
 }
 
 /// Fallback function body extraction using brace-counting
-/// Used when verus_syn cannot find the function (e.g., functions outside verus! macros)
-fn extract_body_with_brace_counting(lines: &[&str], start_line: usize) -> String {
+/// Used when verus_syn cannot find the function (e.g., functions outside verus! macros).
+/// Lines are re-joined with `line_ending` so CRLF sources keep their line breaks.
+fn extract_body_with_brace_counting(
+    lines: &[&str],
+    start_line: usize,
+    line_ending: &str,
+) -> String {
     let mut body_lines = Vec::new();
     let mut open_braces: usize = 0;
     let mut found_body_brace = false;
@@ -526,6 +1706,10 @@ fn extract_body_with_brace_counting(lines: &[&str], start_line: usize) -> String
                     _ => {}
                 }
             }
+            // One-line function: stop here rather than running into the next item
+            if found_body_brace && open_braces == 0 {
+                break;
+            }
             continue;
         }
 
@@ -566,7 +1750,171 @@ fn extract_body_with_brace_counting(lines: &[&str], start_line: usize) -> String
         }
     }
 
-    body_lines.join("\n")
+    body_lines.join(line_ending)
+}
+
+/// Split the space-separated fields at the start of a SCIP symbol.
+/// A double space is an escaped space inside a field. Returns the fields and the rest.
+fn split_scip_fields(symbol: &str, count: usize) -> Option<(Vec<String>, &str)> {
+    let mut fields = Vec::with_capacity(count);
+    let mut field = String::new();
+    let mut chars = symbol.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != ' ' {
+            field.push(c);
+        } else if chars.next_if(|&(_, next)| next == ' ').is_some() {
+            field.push(' ');
+        } else {
+            fields.push(std::mem::take(&mut field));
+            if fields.len() == count {
+                return Some((fields, &symbol[i + 1..]));
+            }
+        }
+    }
+    None
+}
+
+/// Descriptor part of a global SCIP symbol.
+///
+/// SCIP symbols have the form `<scheme> <manager> <package-name> <version> <descriptors>`,
+/// e.g. `rust-analyzer cargo curve25519-dalek 4.1.3 montgomery/MontgomeryPoint#ct_eq().`.
+/// Returns `None` for local symbols and strings that do not follow this grammar.
+fn scip_symbol_descriptors(symbol: &str) -> Option<&str> {
+    if is_local_symbol(symbol) {
+        return None;
+    }
+    split_scip_fields(symbol, 4)
+        .map(|(_, descriptors)| descriptors)
+        .filter(|descriptors| !descriptors.is_empty())
+}
+
+/// Rust module path of the item a SCIP symbol names: the crate name followed by the
+/// leading namespace descriptors. For example
+/// `rust-analyzer cargo curve25519-dalek 4.1.3 montgomery/MontgomeryPoint#mul().`
+/// gives `curve25519_dalek::montgomery`. Returns an empty string for local symbols.
+pub fn symbol_module_path(symbol: &str) -> String {
+    if is_local_symbol(symbol) {
+        return String::new();
+    }
+    let Some((fields, descriptors)) = split_scip_fields(symbol, 4) else {
+        return String::new();
+    };
+    let mut path = vec![fields[2].replace('-', "_")];
+    let mut rest = descriptors;
+    while let Some((name, after)) = scip_descriptor_name(rest) {
+        match after.strip_prefix('/') {
+            Some(after) => {
+                path.push(name);
+                rest = after;
+            }
+            None => break,
+        }
+    }
+    path.join("::")
+}
+
+/// Crate name of the package a SCIP symbol belongs to, with `-` replaced by `_` as in
+/// [`symbol_module_path`]. For example
+/// `rust-analyzer cargo curve25519-dalek 4.1.3 montgomery/MontgomeryPoint#mul().`
+/// gives `curve25519_dalek`. Returns an empty string for local symbols.
+pub fn symbol_crate_name(symbol: &str) -> String {
+    if is_local_symbol(symbol) {
+        return String::new();
+    }
+    split_scip_fields(symbol, 4)
+        .map(|(fields, _)| fields[2].replace('-', "_"))
+        .unwrap_or_default()
+}
+
+/// Split the name off the front of a SCIP descriptor chain, unescaping backticked names
+fn scip_descriptor_name(descriptors: &str) -> Option<(String, &str)> {
+    if let Some(escaped) = descriptors.strip_prefix('`') {
+        let mut name = String::new();
+        let mut chars = escaped.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if c != '`' {
+                name.push(c);
+            } else if chars.next_if(|&(_, next)| next == '`').is_some() {
+                name.push('`');
+            } else {
+                return Some((name, &escaped[i + 1..]));
+            }
+        }
+        return None;
+    }
+    let end = descriptors
+        .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '+' | '-' | '$')))
+        .unwrap_or(descriptors.len());
+    (end > 0).then(|| (descriptors[..end].to_string(), &descriptors[end..]))
+}
+
+/// Name of the last descriptor of a global SCIP symbol, skipping type parameter and
+/// parameter descriptors, e.g. `ct_eq` for `... montgomery/MontgomeryPoint#ct_eq().`.
+/// Returns `None` for local symbols and descriptors that cannot be parsed.
+fn symbol_item_name(symbol: &str) -> Option<String> {
+    let mut rest = scip_symbol_descriptors(symbol)?;
+    let mut last = None;
+    while !rest.is_empty() {
+        // `[T]` and `(x)` name type parameters and parameters of the item before them
+        if let Some(close) = match rest.as_bytes()[0] {
+            b'[' => Some(']'),
+            b'(' => Some(')'),
+            _ => None,
+        } {
+            let (_, after) = scip_descriptor_name(&rest[1..])?;
+            rest = after.strip_prefix(close)?;
+            continue;
+        }
+        let (name, after) = scip_descriptor_name(rest)?;
+        rest = match after.as_bytes().first()? {
+            b'/' | b'#' | b'.' | b'!' | b':' => &after[1..],
+            // Method: `name(<disambiguator>).`
+            b'(' => after[1..].split_once(").")?.1,
+            _ => return None,
+        };
+        last = Some(name);
+    }
+    last
+}
+
+/// Display name for a symbol the index gave none: its [`symbol_item_name`], or
+/// `unknown` if the symbol cannot be parsed
+fn fallback_display_name(symbol: &str) -> String {
+    let name = symbol_item_name(symbol).unwrap_or_else(|| "unknown".to_string());
+    debug!("No display name for {symbol}, using {name}");
+    name
+}
+
+/// Display name for the node `key` of `symbol`, using [`fallback_display_name`] when
+/// the index gave none. `unnamed` tracks the nodes that needed the fallback, mapped to
+/// whether their symbol had a descriptor name.
+fn node_display_name(
+    symbol: &Symbol,
+    key: SymbolId,
+    unnamed: &mut FxHashMap<SymbolId, bool>,
+) -> String {
+    match &symbol.display_name {
+        Some(name) => name.clone(),
+        None => {
+            unnamed.insert(key, symbol_item_name(&symbol.symbol).is_some());
+            fallback_display_name(&symbol.symbol)
+        }
+    }
+}
+
+/// Whether a SCIP symbol is a document-local symbol (`local <id>`)
+fn is_local_symbol(symbol: &str) -> bool {
+    symbol.starts_with("local ")
+}
+
+/// Readable identifier for a local symbol, e.g. `<local:closure:3>`, or `<local:3>`
+/// without a display name. Local ids are only unique within one document.
+fn local_symbol_path(id: &str, display_name: &str) -> String {
+    if display_name.is_empty() {
+        format!("<local:{id}>")
+    } else {
+        format!("<local:{display_name}:{id}>")
+    }
 }
 
 /// Convert a SCIP symbol to a clean path format with display name
@@ -581,21 +1929,12 @@ pub fn symbol_to_path_with_signature(
     display_name: &str,
     signature: Option<&str>,
 ) -> String {
-    // Skip "rust-analyzer cargo " prefix if present
-    let mut parts = symbol.split_whitespace();
-    let mut s = symbol;
-    if parts.next() == Some("rust-analyzer") && parts.next() == Some("cargo") {
-        if let Some(rest) = symbol.find("cargo ").and_then(|pos| symbol.get(pos + 6..)) {
-            s = rest;
-        }
+    if let Some(id) = symbol.strip_prefix("local ") {
+        return local_symbol_path(id.trim(), display_name);
     }
 
-    // Skip version part if present (e.g., "0.1.0 ")
-    if let Some(pos) = s.find(|c: char| c.is_ascii_digit()) {
-        if let Some(space_pos) = s[pos..].find(' ') {
-            s = s[(pos + space_pos + 1)..].trim();
-        }
-    }
+    // Keep only the descriptors, dropping the scheme, manager, package name and version
+    let s = scip_symbol_descriptors(symbol).unwrap_or(symbol).trim();
 
     let mut clean_path = s
         .trim_end_matches('.')
@@ -650,729 +1989,5092 @@ pub fn symbol_to_path_with_signature(
     clean_path
 }
 
+/// Find all nodes whose display name is exactly `name` (case-sensitive).
+/// Several nodes can share a name, e.g. `new` on different types or multiple trait impls.
+/// Results are sorted by symbol and signature.
+pub fn find_by_display_name<'a>(
+    call_graph: &'a HashMap<String, FunctionNode>,
+    name: &str,
+) -> Vec<&'a FunctionNode> {
+    let mut nodes: Vec<&FunctionNode> = call_graph
+        .values()
+        .filter(|node| node.display_name == name)
+        .collect();
+    nodes.sort_by(|a, b| (&a.symbol, &a.signature_text).cmp(&(&b.symbol, &b.signature_text)));
+    nodes
+}
+
+/// Find the node whose [`symbol_to_path`] identifier is exactly `identifier`.
+/// If several nodes map to the same path, the first by symbol and signature is returned.
+pub fn find_by_path<'a>(
+    call_graph: &'a HashMap<String, FunctionNode>,
+    identifier: &str,
+) -> Option<&'a FunctionNode> {
+    call_graph
+        .values()
+        .filter(|node| symbol_to_path(&node.symbol, &node.display_name) == identifier)
+        .min_by(|a, b| (&a.symbol, &a.signature_text).cmp(&(&b.symbol, &b.signature_text)))
+}
+
+/// Find the nodes selected by any of `function_names`, sorted by symbol and signature.
+///
+/// With `exact`, a name must equal the node's display name, SCIP symbol or
+/// [`symbol_to_path`] identifier. Otherwise it also matches any symbol or path
+/// containing it, so e.g. `new` selects every constructor.
+pub fn match_function_nodes<'a>(
+    call_graph: &'a HashMap<String, FunctionNode>,
+    function_names: &[String],
+    exact: bool,
+) -> Vec<&'a FunctionNode> {
+    let mut nodes: Vec<_> = call_graph
+        .values()
+        .filter(|node| {
+            let path = symbol_to_path(&node.symbol, &node.display_name);
+            function_names.iter().any(|name| {
+                if exact {
+                    node.display_name == *name || node.symbol == *name || path == *name
+                } else {
+                    node.display_name == *name
+                        || node.symbol.contains(name.as_str())
+                        || path.contains(name.as_str())
+                }
+            })
+        })
+        .collect();
+    nodes.sort_by(|a, b| (&a.symbol, &a.signature_text).cmp(&(&b.symbol, &b.signature_text)));
+    nodes
+}
+
 /// Write the call graph as a JSON array of Atom objects
 pub fn write_call_graph_as_atoms_json<P: AsRef<std::path::Path>>(
     call_graph: &HashMap<String, FunctionNode>,
     output_path: P,
 ) -> std::io::Result<()> {
-    let atoms: Vec<Atom> = call_graph
-        .values()
-        .map(|node| {
-            // Make sure to unwrap the body or provide a meaningful default
-            let body_content = node.body.clone().unwrap_or_default();
-
-            // Debug print to see what's happening
-            let display_name = &node.display_name;
-            let body_len = body_content.len();
-            debug!("Function: {display_name}, Body length: {body_len}");
-
-            // Get just the folder name instead of the whole path
-            let parent_folder = Path::new(&node.file_path)
-                .parent()
-                .and_then(|p| p.file_name())
-                .and_then(|name| name.to_str())
-                .unwrap_or("unknown")
-                .to_string();
-
-            Atom {
-                // Probe-style identifier: "probe:crate/version/module/Type#Trait<Param>#method()"
-                identifier: symbol_to_code_name(
-                    &node.symbol,
-                    &node.display_name,
-                    Some(&node.signature_text),
-                ),
-                statement_type: "function".to_string(),
-                deps: node
-                    .callees
-                    .iter()
-                    .filter_map(|callee_symbol| {
-                        // For callees, we need to find nodes by symbol (not unique key)
-                        // since callees are stored as base symbols
-                        call_graph.values().find(|n| n.symbol == *callee_symbol)
-                    })
-                    .map(|callee_node| {
-                        // Dependencies also use probe-style format
-                        symbol_to_code_name(
-                            &callee_node.symbol,
-                            &callee_node.display_name,
-                            Some(&callee_node.signature_text),
-                        )
-                    })
-                    .collect(),
-                body: body_content,
-                display_name: node.display_name.clone(),
-                full_path: node.file_path.clone(),
-                relative_path: node.relative_path.clone(),
-                file_name: Path::new(&node.file_path)
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .to_string(),
-                parent_folder,
-            }
-        })
-        .collect();
+    write_atoms_json_from_iter(atoms(call_graph), output_path)
+}
 
-    let json = serde_json::to_string_pretty(&atoms).unwrap();
+/// Write atoms as a pretty-printed JSON array
+pub fn write_atoms_json<P: AsRef<std::path::Path>>(
+    atoms: &[Atom],
+    output_path: P,
+) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(atoms).unwrap();
     std::fs::write(output_path, json)
 }
 
-/// Check if a symbol kind represents a function-like entity
-fn is_function_like(kind: i32) -> bool {
-    // According to SCIP spec: Method=6, Function=17, Constructor=26, Macro=80
-    matches!(kind, 6 | 17 | 26 | 80)
+/// Like [`write_call_graph_as_atoms_json`], but gzip-compressed; the output is
+/// conventionally named `*.json.gz`
+pub fn write_call_graph_as_atoms_json_gz<P: AsRef<std::path::Path>>(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: P,
+) -> std::io::Result<()> {
+    write_atoms_json_gz_from_iter(atoms(call_graph), output_path)
 }
 
-/// Generate a DOT file format for the call graph that can be rendered by Graphviz
-pub fn generate_call_graph_dot(
-    call_graph: &HashMap<String, FunctionNode>,
-    output_path: &str,
+/// Write atoms as a gzip-compressed, pretty-printed JSON array
+pub fn write_atoms_json_gz<P: AsRef<std::path::Path>>(
+    atoms: &[Atom],
+    output_path: P,
 ) -> std::io::Result<()> {
-    use std::collections::BTreeMap;
-    let mut dot = String::from("digraph call_graph {\n");
-    dot.push_str("  rankdir=LR;\n");
-    dot.push_str("  node [shape=box, style=filled, fillcolor=lightblue, fontname=Helvetica];\n");
-    dot.push_str("  edge [color=gray];\n\n");
+    write_json_gz(atoms, output_path)
+}
 
-    // Filter out unwanted paths
-    let skip_paths = [
-        "libsignal/rust/protocol/benches",
-        "libsignal/rust/protocol/tests",
-        "libsignal/rust/protocol/examples",
-    ];
-    let filtered_nodes: Vec<&FunctionNode> = call_graph
-        .values()
-        .filter(|node| !skip_paths.iter().any(|p| node.file_path.contains(p)))
-        .collect();
+/// Like [`write_atoms_json`], but the atoms are serialized one at a time as they
+/// are pulled from `atoms` (e.g. [`atoms`]), so they are never all held in memory.
+/// The output is byte-for-byte the same.
+pub fn write_atoms_json_from_iter<I, P>(atoms: I, output_path: P) -> std::io::Result<()>
+where
+    I: IntoIterator<Item = Atom>,
+    P: AsRef<std::path::Path>,
+{
+    let mut writer = BufWriter::new(fs::File::create(output_path)?);
+    write_atom_stream(&mut writer, atoms)?;
+    writer.flush()
+}
 
-    // Group nodes by module/directory (parent of file path)
-    let mut module_groups: BTreeMap<String, Vec<&FunctionNode>> = BTreeMap::new();
-    for node in &filtered_nodes {
-        let path = std::path::Path::new(&node.file_path);
-        let module = path
-            .parent()
-            .map(|p| p.display().to_string())
-            .unwrap_or_else(|| "root".to_string());
-        module_groups.entry(module).or_default().push(*node);
-    }
+/// Gzip-compressed form of [`write_atoms_json_from_iter`]
+pub fn write_atoms_json_gz_from_iter<I, P>(atoms: I, output_path: P) -> std::io::Result<()>
+where
+    I: IntoIterator<Item = Atom>,
+    P: AsRef<std::path::Path>,
+{
+    let file = fs::File::create(output_path)?;
+    let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+    write_atom_stream(&mut encoder, atoms)?;
+    encoder.finish()?.flush()
+}
 
-    for (cluster_id, (module, nodes)) in module_groups.iter().enumerate() {
-        dot.push_str(&format!("  subgraph cluster_{} {{\n    label = \"{}\";\n    style=filled;\n    color=lightgrey;\n    fontname=Helvetica;\n", cluster_id, module));
-        for node in nodes {
-            let label = node.display_name.clone();
-            let tooltip = if let Some(body) = &node.body {
-                let plain = body.replace(['\n', '\r'], " ").replace('"', "' ");
-                if plain.len() > 200 {
-                    format!("{}...", &plain[..200])
-                } else {
-                    plain
-                }
-            } else {
-                "".to_string()
-            };
-            dot.push_str(&format!(
-                "    \"{}\" [label=\"{}\", tooltip=\"{}\"]\n",
-                node.symbol, label, tooltip
-            ));
-        }
-        dot.push_str("  }\n");
-    }
+/// Serialize atoms as a pretty-printed JSON array without collecting them first
+fn write_atom_stream<W: Write>(
+    writer: W,
+    atoms: impl IntoIterator<Item = Atom>,
+) -> std::io::Result<()> {
+    serde_json::Serializer::pretty(writer)
+        .collect_seq(atoms)
+        .map_err(std::io::Error::other)
+}
 
-    dot.push('\n');
+/// Serialize `value` as pretty-printed JSON into a gzip file. The JSON is streamed
+/// through the encoder, so large outputs are never held in memory as one string.
+pub fn write_json_gz<T: Serialize + ?Sized, P: AsRef<std::path::Path>>(
+    value: &T,
+    output_path: P,
+) -> std::io::Result<()> {
+    let file = fs::File::create(output_path)?;
+    let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+    serde_json::to_writer_pretty(&mut encoder, value).map_err(std::io::Error::other)?;
+    encoder.finish()?.flush()
+}
 
-    // Add edges, but only for filtered nodes
-    let filtered_symbols: std::collections::HashSet<_> =
-        filtered_nodes.iter().map(|n| &n.symbol).collect();
-    for node in &filtered_nodes {
-        for callee in &node.callees {
-            if filtered_symbols.contains(callee) {
-                dot.push_str(&format!("  \"{}\" -> \"{}\"\n", node.symbol, callee));
-            }
+/// Provenance of a generated atom file, so cached atoms can be traced back to the
+/// run that produced them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AtomsMetadata {
+    /// Version of rust-atomizer that wrote the file
+    pub atomizer_version: String,
+    /// Indexer that produced the SCIP input, from its metadata
+    pub tool_info: ToolInfo,
+    /// Generation time in RFC 3339
+    pub generated_at: String,
+    /// Commit of the input repository, if it is a git checkout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+impl AtomsMetadata {
+    /// Metadata for atoms generated now from an index produced by `tool_info`
+    pub fn new(tool_info: ToolInfo, commit: Option<String>) -> Self {
+        AtomsMetadata {
+            atomizer_version: env!("CARGO_PKG_VERSION").to_string(),
+            tool_info,
+            generated_at: chrono::Utc::now().to_rfc3339(),
+            commit,
         }
     }
+}
 
-    dot.push_str("}\n");
-    std::fs::write(output_path, dot)
+/// Atom file written by [`write_atoms_json_with_meta`]: the atom array wrapped
+/// together with its [`AtomsMetadata`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AtomsWithMeta {
+    pub metadata: AtomsMetadata,
+    pub atoms: Vec<Atom>,
 }
 
-/// Generate a DOT file format for a subgraph of the call graph containing only nodes from a specific file path
-pub fn generate_file_subgraph_dot(
+/// Like [`write_call_graph_as_atoms_json`], but the atoms are wrapped in an object
+/// as `{"metadata": ..., "atoms": [...]}`
+pub fn write_call_graph_as_atoms_json_with_meta<P: AsRef<std::path::Path>>(
     call_graph: &HashMap<String, FunctionNode>,
-    file_path: &str,
-    output_path: &str,
+    metadata: &AtomsMetadata,
+    output_path: P,
 ) -> std::io::Result<()> {
-    use std::collections::HashSet;
-    let mut dot = String::from("digraph file_subgraph {\n");
-    dot.push_str("  rankdir=LR;\n");
-    dot.push_str("  node [shape=box, style=filled, fontname=Helvetica];\n");
-    dot.push_str("  edge [color=gray];\n\n");
+    write_atoms_json_with_meta(&call_graph_to_atoms(call_graph), metadata, output_path)
+}
 
-    // Find nodes that belong to the specified file - more flexible path matching
-    let file_nodes: Vec<&FunctionNode> = call_graph
-        .values()
-        .filter(|node| {
-            // Extract the filename from the provided file_path argument
-            let requested_filename = Path::new(file_path)
-                .file_name()
-                .and_then(|f| f.to_str())
-                .unwrap_or(file_path);
-            node.file_path.ends_with(file_path)
-                || node.file_path == file_path
-                || node.symbol.contains(file_path)
-                || node.file_path.contains(requested_filename)
-        })
-        .collect();
+/// Write atoms with their metadata, see [`AtomsWithMeta`]
+pub fn write_atoms_json_with_meta<P: AsRef<std::path::Path>>(
+    atoms: &[Atom],
+    metadata: &AtomsMetadata,
+    output_path: P,
+) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(&serde_json::json!({
+        "metadata": metadata,
+        "atoms": atoms,
+    }))
+    .map_err(std::io::Error::other)?;
+    std::fs::write(output_path, json)
+}
 
-    if file_nodes.is_empty() {
-        // List available paths that contain part of the requested path
-        let matching_paths: HashSet<_> = call_graph
-            .values()
-            .filter(|node| node.file_path.contains(file_path))
-            .map(|node| &node.file_path)
-            .collect();
+/// Convert every node of the call graph into an [`Atom`]
+pub fn call_graph_to_atoms(call_graph: &HashMap<String, FunctionNode>) -> Vec<Atom> {
+    atoms(call_graph).collect()
+}
 
-        if !matching_paths.is_empty() {
-            let mut message = format!(
-                "No exact match for file path: {}\n\nHere are some similar paths:\n",
-                file_path
-            );
-            for path in matching_paths {
-                message.push_str(&format!("  {}\n", path));
-            }
-            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message));
-        }
+/// Like [`call_graph_to_atoms`], but `deps` also lists callees defined outside the
+/// project, as [`EXTERNAL_DEP_PREFIX`] followed by the [`symbol_to_path`] of the symbol
+pub fn call_graph_to_atoms_with_external(call_graph: &HashMap<String, FunctionNode>) -> Vec<Atom> {
+    atoms_with_external(call_graph).collect()
+}
 
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("No functions found in file path: {}", file_path),
-        ));
-    }
+/// Lazily convert the nodes of the call graph into [`Atom`]s, in the same order
+/// and with the same contents as [`call_graph_to_atoms`]. Only the cycle detection
+/// runs up front; each atom (and its body copy) is built when it is pulled, so a
+/// consumer that streams them out never holds every atom at once.
+pub fn atoms(call_graph: &HashMap<String, FunctionNode>) -> impl Iterator<Item = Atom> + '_ {
+    build_atoms(call_graph, false)
+}
 
-    println!("Found {} functions in file {}", file_nodes.len(), file_path);
-    for node in &file_nodes {
-        println!("  - {} ({})", node.display_name, node.symbol);
-    }
+/// Lazy form of [`call_graph_to_atoms_with_external`], see [`atoms`]
+pub fn atoms_with_external(
+    call_graph: &HashMap<String, FunctionNode>,
+) -> impl Iterator<Item = Atom> + '_ {
+    build_atoms(call_graph, true)
+}
 
-    // Get the symbols of nodes in the file
-    let file_symbols: HashSet<String> = file_nodes.iter().map(|n| n.symbol.clone()).collect();
+/// Directory part of a `/`-separated relative path, without a trailing slash
+fn relative_parent_dir(relative_path: &str) -> String {
+    relative_path
+        .rsplit_once('/')
+        .map(|(dir, _)| dir.to_string())
+        .unwrap_or_default()
+}
 
-    // Nodes that are called by or call into nodes from this file (1st degree connections)
-    let mut connected_symbols = HashSet::new();
-    for node in &file_nodes {
-        // Add callees
-        for callee in &node.callees {
-            connected_symbols.insert(callee.clone());
-        }
-        // Add callers
-        for caller in &node.callers {
-            connected_symbols.insert(caller.clone());
+fn build_atoms(
+    call_graph: &HashMap<String, FunctionNode>,
+    include_external: bool,
+) -> impl Iterator<Item = Atom> + '_ {
+    let scc_ids: HashMap<String, usize> = find_cycles(call_graph)
+        .into_iter()
+        .enumerate()
+        .flat_map(|(id, cycle)| cycle.into_iter().map(move |symbol| (symbol, id)))
+        .collect();
+
+    call_graph.values().map(move |node| {
+        // Make sure to unwrap the body or provide a meaningful default
+        let body_content = node.body.clone().unwrap_or_default();
+
+        // Debug print to see what's happening
+        let display_name = &node.display_name;
+        let body_len = body_content.len();
+        debug!("Function: {display_name}, Body length: {body_len}");
+
+        // Graphs loaded from disk may still carry Windows separators
+        let full_path = to_forward_slashes(&node.file_path);
+        let relative_path = to_forward_slashes(&node.relative_path);
+
+        // Get just the folder name instead of the whole path
+        let mut components = full_path.rsplit('/');
+        let file_name = components.next().unwrap_or_default().to_string();
+        let parent_folder = components
+            .next()
+            .filter(|name| !name.is_empty())
+            .unwrap_or("unknown")
+            .to_string();
+        let parent_dir = relative_parent_dir(&relative_path);
+
+        Atom {
+            // Probe-style identifier: "probe:crate/version/module/Type#Trait<Param>#method()"
+            identifier: symbol_to_code_name(
+                &node.symbol,
+                &node.display_name,
+                Some(&node.signature_text),
+            ),
+            statement_type: statement_type_for_kind(node.kind).to_string(),
+            deps: node
+                .sorted_callees()
+                .into_iter()
+                .filter_map(|callee_symbol| {
+                    // For callees, we need to find nodes by symbol (not unique key)
+                    // since callees are stored as base symbols
+                    call_graph.values().find(|n| n.symbol == *callee_symbol)
+                })
+                .map(|callee_node| {
+                    // Dependencies also use probe-style format
+                    symbol_to_code_name(
+                        &callee_node.symbol,
+                        &callee_node.display_name,
+                        Some(&callee_node.signature_text),
+                    )
+                })
+                .chain(
+                    node.external_callees
+                        .iter()
+                        .filter(|_| include_external)
+                        .map(|symbol| {
+                            format!("{EXTERNAL_DEP_PREFIX}{}", symbol_to_path(symbol, ""))
+                        }),
+                )
+                // Distinct callees can map to the same identifier; dedup and
+                // sort so the output is stable
+                .collect::<BTreeSet<String>>()
+                .into_iter()
+                .collect(),
+            body: body_content,
+            display_name: node.display_name.clone(),
+            full_path,
+            relative_path,
+            file_name,
+            parent_folder,
+            parent_dir,
+            is_test: node.is_test,
+            visibility: node.visibility,
+            module_path: symbol_module_path(&node.symbol),
+            crate_name: symbol_crate_name(&node.symbol),
+            scc_id: scc_ids.get(node.symbol.as_str()).copied(),
+            is_async: node.is_async,
+            is_unsafe: node.is_unsafe,
+            is_const: node.is_const,
+            enclosing: node
+                .enclosing
+                .as_deref()
+                .map(|symbol| symbol_to_path(symbol, "")),
         }
-    }
+    })
+}
 
-    // Draw file nodes with blue background
-    for node in &file_nodes {
-        let label = node.display_name.clone();
-        let tooltip = if let Some(body) = &node.body {
-            let plain = body.replace(['\n', '\r'], " ").replace('"', "' ");
-            if plain.len() > 200 {
-                format!("{}...", &plain[..200])
-            } else {
-                plain
-            }
-        } else {
-            "".to_string()
-        };
-        dot.push_str(&format!(
-            "  \"{}\" [label=\"{}\", tooltip=\"{}\", fillcolor=lightblue]\n",
-            node.symbol, label, tooltip
-        ));
-    }
+/// Check if a symbol kind represents a function-like entity, see
+/// [`SymbolKind::is_function_like`]
+fn is_function_like(kind: i32) -> bool {
+    SymbolKind::try_from(kind).is_ok_and(SymbolKind::is_function_like)
+}
 
-    // Draw connected nodes with light gray background
-    for symbol in &connected_symbols {
-        if !file_symbols.contains(symbol) {
-            if let Some(node) = call_graph.get(symbol) {
-                let label = node.display_name.clone();
-                dot.push_str(&format!(
-                    "  \"{}\" [label=\"{}\", fillcolor=lightgray]\n",
-                    node.symbol, label
-                ));
+/// Check if a symbol kind represents a type definition that becomes a node (struct or enum)
+fn is_type_definition(kind: i32) -> bool {
+    SymbolKind::try_from(kind).is_ok_and(SymbolKind::is_type_definition)
+}
+
+/// Check if a symbol kind represents a named value that becomes a node (const or static)
+fn is_value_definition(kind: i32) -> bool {
+    SymbolKind::try_from(kind).is_ok_and(SymbolKind::is_value_definition)
+}
+
+/// Check if a symbol kind becomes a node in the call graph
+fn is_graph_node_kind(kind: i32) -> bool {
+    is_function_like(kind) || is_type_definition(kind) || is_value_definition(kind)
+}
+
+/// Check if a symbol kind can be reported as [`FunctionNode::enclosing`]: a function
+/// or a type (struct, enum, union, trait or type alias)
+fn is_enclosing_kind(kind: i32) -> bool {
+    is_function_like(kind)
+        || SymbolKind::try_from(kind).is_ok_and(|kind| {
+            kind.is_type_definition()
+                || matches!(
+                    kind,
+                    SymbolKind::Union | SymbolKind::Trait | SymbolKind::TypeAlias
+                )
+        })
+}
+
+/// Map each symbol to its nearest enclosing function or type, following
+/// `Symbol.enclosing_symbol` past modules, impls and other items in between.
+/// Symbols without such an ancestor are left out.
+fn symbol_enclosure(scip_data: &ScipIndex) -> FxHashMap<&str, &str> {
+    let all_symbols = || {
+        scip_data
+            .documents
+            .iter()
+            .flat_map(|doc| &doc.symbols)
+            .chain(&scip_data.external_symbols)
+    };
+    let kinds: FxHashMap<&str, i32> = all_symbols()
+        .map(|symbol| (symbol.symbol.as_str(), symbol.kind))
+        .collect();
+    let parents: FxHashMap<&str, &str> = all_symbols()
+        .filter_map(|symbol| Some((symbol.symbol.as_str(), symbol.enclosing_symbol.as_deref()?)))
+        .collect();
+
+    let mut enclosure = FxHashMap::default();
+    for (&symbol, &parent) in &parents {
+        let mut current = parent;
+        // Bounded so that a malformed index with an enclosure cycle cannot hang
+        for _ in 0..parents.len() {
+            if kinds
+                .get(current)
+                .is_some_and(|&kind| is_enclosing_kind(kind))
+            {
+                enclosure.insert(symbol, current);
+                break;
+            }
+            match parents.get(current) {
+                Some(&next) => current = next,
+                None => break,
             }
         }
     }
+    enclosure
+}
 
-    dot.push('\n');
+/// The atom `statement_type` for a SCIP symbol kind
+fn statement_type_for_kind(kind: i32) -> &'static str {
+    match SymbolKind::try_from(kind) {
+        Ok(SymbolKind::Struct) => "struct",
+        Ok(SymbolKind::Enum) => "enum",
+        Ok(SymbolKind::Constant) => "const",
+        Ok(SymbolKind::StaticVariable) => "static",
+        Ok(SymbolKind::Method | SymbolKind::StaticMethod) => "method",
+        Ok(SymbolKind::Constructor) => "constructor",
+        Ok(SymbolKind::Macro) => "macro",
+        _ => "function",
+    }
+}
 
-    // Draw edges from file nodes to their callees
-    for node in &file_nodes {
+/// Build a symbol-level adjacency list from the callee edges.
+/// Callees that are not themselves nodes in the graph are dropped.
+fn callee_adjacency(call_graph: &HashMap<String, FunctionNode>) -> BTreeMap<&str, BTreeSet<&str>> {
+    let symbols: HashSet<&str> = call_graph.values().map(|n| n.symbol.as_str()).collect();
+    let mut adjacency: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for node in call_graph.values() {
+        let callees = adjacency.entry(node.symbol.as_str()).or_default();
         for callee in &node.callees {
-            if file_symbols.contains(callee) || connected_symbols.contains(callee) {
-                dot.push_str(&format!("  \"{}\" -> \"{}\"\n", node.symbol, callee));
+            if symbols.contains(callee.as_str()) {
+                callees.insert(callee.as_str());
             }
         }
     }
+    adjacency
+}
 
-    // Draw edges from callers to file nodes
-    for node in &file_nodes {
-        for caller in &node.callers {
-            if !file_symbols.contains(caller) && connected_symbols.contains(caller) {
-                dot.push_str(&format!("  \"{}\" -> \"{}\"\n", caller, node.symbol));
-            }
-        }
+/// Find cycles in the call graph using Tarjan's strongly connected components algorithm.
+///
+/// Returns every nontrivial SCC (more than one function, or a function calling itself)
+/// as a sorted list of symbols. Callees are stored as base symbols, so nodes sharing a
+/// symbol (e.g. several trait impls) are treated as a single vertex.
+pub fn find_cycles(call_graph: &HashMap<String, FunctionNode>) -> Vec<Vec<String>> {
+    struct Tarjan<'a> {
+        adjacency: &'a BTreeMap<&'a str, BTreeSet<&'a str>>,
+        next_index: usize,
+        indices: HashMap<&'a str, usize>,
+        lowlinks: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: HashSet<&'a str>,
+        cycles: Vec<Vec<String>>,
     }
 
-    dot.push_str("}\n");
-    std::fs::write(output_path, dot)
-}
+    impl<'a> Tarjan<'a> {
+        fn discover(&mut self, v: &'a str) {
+            self.indices.insert(v, self.next_index);
+            self.lowlinks.insert(v, self.next_index);
+            self.next_index += 1;
+            self.stack.push(v);
+            self.on_stack.insert(v);
+        }
 
-/// Generate a DOT file format for a subgraph of the call graph containing only nodes from a specific set of file paths
-pub fn generate_files_subgraph_dot(
-    call_graph: &HashMap<String, FunctionNode>,
-    file_paths: &[String],
-    output_path: &str,
-) -> std::io::Result<()> {
-    use std::collections::{BTreeMap, HashSet};
-    let mut dot = String::from("digraph files_subgraph {\n");
-    dot.push_str("  rankdir=LR;\n");
-    dot.push_str("  node [shape=box, style=filled, fontname=Helvetica];\n");
-    dot.push_str("  edge [color=gray];\n\n");
+        /// Depth-first search from `root` with an explicit stack of (vertex, callees
+        /// not visited yet), so that long call chains cannot overflow the thread stack
+        fn strong_connect(&mut self, root: &'a str) {
+            let adjacency = self.adjacency;
+            self.discover(root);
+            let mut work = vec![(root, adjacency[root].iter())];
+            while let Some((v, callees)) = work.last_mut() {
+                let v = *v;
+                if let Some(&w) = callees.next() {
+                    if !self.indices.contains_key(w) {
+                        self.discover(w);
+                        work.push((w, adjacency[w].iter()));
+                    } else if self.on_stack.contains(w) {
+                        let low = self.lowlinks[v].min(self.indices[w]);
+                        self.lowlinks.insert(v, low);
+                    }
+                    continue;
+                }
 
-    // Helper function for file path matching
-    fn is_file_match(node_path: &str, requested_paths: &[String]) -> bool {
-        for path in requested_paths {
-            // Extract the filename from the provided file_path
-            let requested_filename = Path::new(path)
-                .file_name()
-                .and_then(|f| f.to_str())
-                .unwrap_or(path);
+                // All callees of v are done: report its lowlink to the caller
+                work.pop();
+                if let Some((parent, _)) = work.last() {
+                    let low = self.lowlinks[parent].min(self.lowlinks[v]);
+                    self.lowlinks.insert(parent, low);
+                }
 
-            if node_path.ends_with(path)
-                || node_path == path
-                || node_path.contains(requested_filename)
-            {
-                return true;
+                // v is the root of an SCC: pop it off the stack
+                if self.lowlinks[v] == self.indices[v] {
+                    let mut component = Vec::new();
+                    while let Some(w) = self.stack.pop() {
+                        self.on_stack.remove(w);
+                        component.push(w.to_string());
+                        if w == v {
+                            break;
+                        }
+                    }
+                    if component.len() > 1 || adjacency[v].contains(v) {
+                        component.sort();
+                        self.cycles.push(component);
+                    }
+                }
             }
         }
-        false
     }
 
-    // Find nodes that belong to any of the specified files
-    let file_nodes: Vec<&FunctionNode> = call_graph
-        .values()
-        .filter(|node| is_file_match(&node.file_path, file_paths))
-        .collect();
-
-    if file_nodes.is_empty() {
-        // List available paths that contain part of the requested paths
-        let mut matching_paths: HashSet<&String> = HashSet::new();
-        for path in file_paths {
-            for node in call_graph.values() {
-                if node.file_path.contains(path) {
-                    matching_paths.insert(&node.file_path);
-                }
-            }
+    let adjacency = callee_adjacency(call_graph);
+    let mut tarjan = Tarjan {
+        adjacency: &adjacency,
+        next_index: 0,
+        indices: HashMap::new(),
+        lowlinks: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: HashSet::new(),
+        cycles: Vec::new(),
+    };
+    for &v in adjacency.keys() {
+        if !tarjan.indices.contains_key(v) {
+            tarjan.strong_connect(v);
         }
+    }
 
-        if !matching_paths.is_empty() {
-            let mut message = format!(
-                "No exact matches for file paths: {:?}\n\nHere are some similar paths:\n",
-                file_paths
-            );
-            for path in matching_paths {
-                message.push_str(&format!("  {}\n", path));
-            }
-            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message));
-        }
+    let mut cycles = tarjan.cycles;
+    cycles.sort();
+    cycles
+}
 
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("No functions found in file paths: {:?}", file_paths),
-        ));
-    }
+/// Display names that are treated as roots by default when looking for dead functions
+pub const DEFAULT_ROOTS: &[&str] = &["main"];
 
-    println!(
-        "Found {} functions in the specified files",
-        file_nodes.len()
-    );
+/// Find functions that are never called from within the graph.
+///
+/// Returns every node that nothing but itself calls (a recursive function's self-edge
+/// does not keep it alive), except those whose `display_name` is listed in `roots`
+/// (e.g. [`DEFAULT_ROOTS`]). Results are sorted by symbol.
+///
+/// Note: this only sees intra-crate calls. `pub` functions used by other crates,
+/// trait-required methods invoked through dynamic dispatch, and functions called only
+/// from macros or tests outside the index may legitimately have no callers here.
+pub fn find_dead_functions<'a>(
+    call_graph: &'a HashMap<String, FunctionNode>,
+    roots: &[&str],
+) -> Vec<&'a FunctionNode> {
+    let mut dead: Vec<&FunctionNode> = call_graph
+        .values()
+        .filter(|node| {
+            node.callers
+                .iter()
+                .all(|caller| caller_symbol(call_graph, caller) == node.symbol)
+        })
+        .filter(|node| !roots.contains(&node.display_name.as_str()))
+        .collect();
+    dead.sort_by(|a, b| (&a.symbol, &a.signature_text).cmp(&(&b.symbol, &b.signature_text)));
+    dead
+}
 
-    // Get the symbols of nodes in the files
-    let file_symbols: HashSet<String> = file_nodes.iter().map(|n| n.symbol.clone()).collect();
+/// Heuristic check for `#[test]` functions: the extracted body (which includes
+/// attributes) carries a test attribute, or the name follows the `test_` convention.
+fn looks_like_test(node: &FunctionNode) -> bool {
+    let has_test_attr = node
+        .body
+        .as_deref()
+        .is_some_and(|body| body.contains("#[test]") || body.contains("#[tokio::test]"));
+    has_test_attr || node.display_name.starts_with("test_")
+}
 
-    // Nodes that are called by or call into nodes from these files (1st degree connections)
-    let mut connected_symbols = HashSet::new();
-    for node in &file_nodes {
-        // Add callees
-        for callee in &node.callees {
-            connected_symbols.insert(callee.clone());
-        }
-        // Add callers
-        for caller in &node.callers {
-            connected_symbols.insert(caller.clone());
-        }
-    }
+/// Find the entry points of the graph: functions with no callers.
+///
+/// These are the roots for reachability analysis (`main`, tests, public API, ...).
+/// If `main_and_tests_only` is set, only functions named `main` or that look like
+/// `#[test]` functions are returned. Results are sorted by symbol.
+pub fn find_entry_points(
+    call_graph: &HashMap<String, FunctionNode>,
+    main_and_tests_only: bool,
+) -> Vec<&FunctionNode> {
+    let mut entry_points: Vec<&FunctionNode> = call_graph
+        .values()
+        .filter(|node| {
+            // Recursive functions are only called by themselves
+            node.callers
+                .iter()
+                .all(|caller| caller_symbol(call_graph, caller) == node.symbol)
+        })
+        .filter(|node| !main_and_tests_only || node.display_name == "main" || looks_like_test(node))
+        .collect();
+    entry_points
+        .sort_by(|a, b| (&a.symbol, &a.signature_text).cmp(&(&b.symbol, &b.signature_text)));
+    entry_points
+}
 
-    // Group file nodes by their file path for subgraph clustering
-    let mut file_groups: BTreeMap<String, Vec<&FunctionNode>> = BTreeMap::new();
-    for node in &file_nodes {
-        file_groups
-            .entry(node.file_path.clone())
+/// Resolve an entry of a node's `callers` set to a base symbol.
+/// Callers are recorded by unique key, so look the node up to recover its symbol.
+fn caller_symbol(call_graph: &HashMap<String, FunctionNode>, caller_key: &str) -> String {
+    call_graph
+        .get(caller_key)
+        .map(|node| node.symbol.clone())
+        .unwrap_or_else(|| caller_key.to_string())
+}
+
+/// Breadth-first search from `seeds`, following callee and/or caller edges.
+fn transitive_closure(
+    call_graph: &HashMap<String, FunctionNode>,
+    seeds: &[String],
+    follow_callees: bool,
+    follow_callers: bool,
+) -> HashSet<String> {
+    bfs_distances(
+        call_graph,
+        seeds,
+        follow_callees,
+        follow_callers,
+        usize::MAX,
+    )
+    .into_keys()
+    .collect()
+}
+
+/// Breadth-first search from `seeds` up to `max_depth` edges away, returning each
+/// reached symbol with its distance from the nearest seed (seeds are at 0).
+fn bfs_distances(
+    call_graph: &HashMap<String, FunctionNode>,
+    seeds: &[String],
+    follow_callees: bool,
+    follow_callers: bool,
+    max_depth: usize,
+) -> HashMap<String, usize> {
+    let mut nodes_by_symbol: HashMap<&str, Vec<&FunctionNode>> = HashMap::new();
+    for node in call_graph.values() {
+        nodes_by_symbol
+            .entry(node.symbol.as_str())
             .or_default()
             .push(node);
     }
 
-    // Draw clusters for each file with blue background nodes
-    for (cluster_id, (file_path, nodes)) in file_groups.iter().enumerate() {
-        let file_label = Path::new(file_path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown");
-
-        dot.push_str(&format!("  subgraph cluster_{} {{\n", cluster_id));
-        dot.push_str(&format!("    label = \"{}\";\n", file_label));
-        dot.push_str("    style=filled;\n");
-        dot.push_str("    color=lightblue;\n");
-        dot.push_str("    fontname=Helvetica;\n");
-
-        for node in nodes {
-            let label = node.display_name.clone();
-            let tooltip = if let Some(body) = &node.body {
-                let plain = body.replace(['\n', '\r'], " ").replace('"', "' ");
-                if plain.len() > 200 {
-                    format!("{}...", &plain[..200])
-                } else {
-                    plain
+    let mut distances: HashMap<String, usize> = seeds.iter().map(|s| (s.clone(), 0)).collect();
+    let mut queue: VecDeque<String> = seeds.iter().cloned().collect();
+    while let Some(symbol) = queue.pop_front() {
+        let distance = distances[&symbol];
+        if distance >= max_depth {
+            continue;
+        }
+        for node in nodes_by_symbol.get(symbol.as_str()).into_iter().flatten() {
+            let mut neighbors = Vec::new();
+            if follow_callees {
+                neighbors.extend(node.callees.iter().cloned());
+            }
+            if follow_callers {
+                neighbors.extend(node.callers.iter().map(|k| caller_symbol(call_graph, k)));
+            }
+            for neighbor in neighbors {
+                if !distances.contains_key(&neighbor) {
+                    distances.insert(neighbor.clone(), distance + 1);
+                    queue.push_back(neighbor);
                 }
-            } else {
-                "".to_string()
-            };
-            dot.push_str(&format!(
-                "    \"{}\" [label=\"{}\", tooltip=\"{}\", fillcolor=white]\n",
-                node.symbol, label, tooltip
-            ));
+            }
         }
+    }
+    distances
+}
 
-        dot.push_str("  }\n");
+/// Fill colors for nodes around the seed files, by distance (1, 2, 3 and beyond)
+const NEIGHBOR_FILLCOLORS: [&str; 3] = ["lightgray", "gainsboro", "whitesmoke"];
+
+/// Draw the neighbors of the seed-file nodes (colored by distance) and every edge
+/// found by the search, i.e. edges with an endpoint closer than `neighbor_depth`.
+fn push_file_neighbors(
+    dot: &mut String,
+    call_graph: &HashMap<String, FunctionNode>,
+    distances: &HashMap<String, usize>,
+    neighbor_depth: usize,
+    options: &DotOptions,
+) {
+    let mut node_by_symbol: BTreeMap<&str, &FunctionNode> = BTreeMap::new();
+    for node in call_graph.values() {
+        node_by_symbol.entry(node.symbol.as_str()).or_insert(node);
     }
 
-    // Draw connected nodes with light gray background
-    for symbol in &connected_symbols {
-        if !file_symbols.contains(symbol) {
-            if let Some(node) = call_graph.get(symbol) {
-                let label = node.display_name.clone();
+    for (symbol, node) in &node_by_symbol {
+        match distances.get(*symbol) {
+            Some(&distance) if distance > 0 => {
+                let fillcolor =
+                    NEIGHBOR_FILLCOLORS[(distance - 1).min(NEIGHBOR_FILLCOLORS.len() - 1)];
                 dot.push_str(&format!(
-                    "  \"{}\" [label=\"{}\", fillcolor=lightgray]\n",
-                    node.symbol, label
+                    "  \"{}\" [label=\"{}\", fillcolor={}{}]\n",
+                    node.symbol,
+                    node.display_name,
+                    fillcolor,
+                    dot_node_attrs(node, options)
                 ));
             }
+            _ => {}
         }
     }
 
     dot.push('\n');
 
-    // Draw edges
-    // From file nodes to their callees
-    for node in &file_nodes {
+    let mut edges = BTreeSet::new();
+    for node in call_graph.values() {
+        let Some(&from) = distances.get(&node.symbol) else {
+            continue;
+        };
         for callee in &node.callees {
-            if file_symbols.contains(callee) || connected_symbols.contains(callee) {
-                dot.push_str(&format!("  \"{}\" -> \"{}\"\n", node.symbol, callee));
+            if let Some(&to) = distances.get(callee) {
+                if from.min(to) < neighbor_depth {
+                    edges.insert((node.symbol.as_str(), callee.as_str()));
+                }
             }
         }
     }
-
-    // From callers to file nodes
-    for node in &file_nodes {
-        for caller in &node.callers {
-            if !file_symbols.contains(caller) && connected_symbols.contains(caller) {
-                dot.push_str(&format!("  \"{}\" -> \"{}\"\n", caller, node.symbol));
-            }
-        }
+    for (caller, callee) in edges {
+        dot.push_str(&format!("  \"{}\" -> \"{}\"\n", caller, callee));
     }
-
-    dot.push_str("}\n");
-    std::fs::write(output_path, dot)
 }
 
-/// Generate a DOT file format for a subgraph of the call graph containing only specified functions and their transitive dependencies
-pub fn generate_function_subgraph_dot(
+/// Return every symbol reachable from `seeds` by following callee edges (the seeds included).
+pub fn transitive_callees(
     call_graph: &HashMap<String, FunctionNode>,
-    function_names: &[String],
-    output_path: &str,
-    include_callers: bool,
-) -> std::io::Result<()> {
-    use std::collections::{BTreeMap, HashSet, VecDeque};
-    let mut dot = String::from("digraph function_subgraph {\n");
-    dot.push_str("  rankdir=LR;\n");
-    dot.push_str("  node [shape=box, style=filled, fontname=Helvetica];\n");
-    dot.push_str("  edge [color=gray];\n\n");
+    seeds: &[String],
+) -> HashSet<String> {
+    transitive_closure(call_graph, seeds, true, false)
+}
 
-    // Find nodes that match the specified function names
-    let mut matched_nodes = Vec::new();
-    let mut matched_symbols = HashSet::new();
+/// Return every symbol that can reach one of `seeds` through calls (the seeds included).
+pub fn transitive_callers(
+    call_graph: &HashMap<String, FunctionNode>,
+    seeds: &[String],
+) -> HashSet<String> {
+    transitive_closure(call_graph, seeds, false, true)
+}
 
-    // Helper function to match function names to nodes
-    for function_name in function_names {
-        let matches: Vec<_> = call_graph
+/// Base symbols of the nodes named by `entries`; entries matching no node are skipped
+/// with a warning.
+fn resolve_entry_symbols(
+    call_graph: &HashMap<String, FunctionNode>,
+    entries: &[String],
+) -> Vec<String> {
+    let mut symbols = Vec::new();
+    for entry in entries {
+        if let Some(node) = call_graph.get(entry) {
+            symbols.push(node.symbol.clone());
+            continue;
+        }
+        let matched: BTreeSet<&str> = call_graph
             .values()
             .filter(|node| {
-                node.display_name == *function_name
-                    || node.symbol.contains(function_name)
-                    || symbol_to_path(&node.symbol, &node.display_name).contains(function_name)
+                node.symbol == *entry
+                    || symbol_to_code_name(
+                        &node.symbol,
+                        &node.display_name,
+                        Some(&node.signature_text),
+                    ) == *entry
+                    || symbol_to_path(&node.symbol, &node.display_name) == *entry
             })
+            .map(|node| node.symbol.as_str())
             .collect();
-
-        for node in matches {
-            matched_nodes.push(node);
-            matched_symbols.insert(node.symbol.clone());
+        if matched.is_empty() {
+            warn!("Entry point {entry} matches no function in the call graph");
         }
+        symbols.extend(matched.into_iter().map(str::to_string));
     }
+    symbols
+}
 
-    if matched_nodes.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!(
-                "No functions found matching the provided names: {:?}",
-                function_names
-            ),
-        ));
+/// Every symbol transitively reachable from `entry_symbols` through callee edges, the
+/// entries included. This is the complement of what is dead when starting from e.g.
+/// `main`.
+///
+/// An entry can be a node key, a SCIP symbol, an atom identifier (see
+/// [`symbol_to_code_name`]) or a [`symbol_to_path`] identifier.
+pub fn reachable_from(
+    call_graph: &HashMap<String, FunctionNode>,
+    entry_symbols: &[String],
+) -> HashSet<String> {
+    let seeds = resolve_entry_symbols(call_graph, entry_symbols);
+    transitive_callees(call_graph, &seeds)
+}
+
+/// Copy of the graph with only the nodes reachable from `entry_symbols` (see
+/// [`reachable_from`]). Callers outside the kept nodes are dropped from `callers`.
+pub fn retain_reachable(
+    call_graph: &HashMap<String, FunctionNode>,
+    entry_symbols: &[String],
+) -> HashMap<String, FunctionNode> {
+    let reachable = reachable_from(call_graph, entry_symbols);
+    let kept_keys: HashSet<&String> = call_graph
+        .iter()
+        .filter(|(_, node)| reachable.contains(&node.symbol))
+        .map(|(key, _)| key)
+        .collect();
+    call_graph
+        .iter()
+        .filter(|(key, _)| kept_keys.contains(key))
+        .map(|(key, node)| {
+            let mut node = node.clone();
+            node.callers.retain(|caller| kept_keys.contains(caller));
+            (key.clone(), node)
+        })
+        .collect()
+}
+
+/// Compile glob patterns over `/`-separated relative paths (e.g. `src/crypto/**`)
+/// into one matcher; the error names the first invalid pattern
+pub fn compile_globs(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| format!("invalid glob {pattern:?}: {e}"))?;
+        builder.add(glob);
     }
+    builder.build().map_err(|e| e.to_string())
+}
 
-    println!(
+/// Keep only the nodes whose `relative_path` matches any of `patterns`, see
+/// [`compile_globs`]. Edges among kept nodes are preserved; `callers` and `callees`
+/// pointing at dropped nodes are removed. External stubs have no path and are dropped.
+pub fn retain_by_glob(
+    call_graph: &HashMap<String, FunctionNode>,
+    patterns: &[String],
+) -> Result<HashMap<String, FunctionNode>, String> {
+    let globs = compile_globs(patterns)?;
+    Ok(retain_nodes(call_graph, |node| {
+        globs.is_match(&node.relative_path)
+    }))
+}
+
+/// The complement of [`retain_by_glob`]: drop the nodes whose `relative_path` matches
+/// any of `patterns`, along with their edges
+pub fn exclude_by_glob(
+    call_graph: &HashMap<String, FunctionNode>,
+    patterns: &[String],
+) -> Result<HashMap<String, FunctionNode>, String> {
+    let globs = compile_globs(patterns)?;
+    Ok(retain_nodes(call_graph, |node| {
+        !globs.is_match(&node.relative_path)
+    }))
+}
+
+/// Keep only the nodes defined under the directory `subtree` (relative to the project
+/// root, e.g. `src/protocol`), matching whole path segments. Calls from kept nodes to
+/// dropped ones are removed from `callees`; with `keep_outgoing` they are moved to
+/// `external_callees` instead, so that atoms can list them as external deps.
+pub fn retain_subtree(
+    call_graph: &HashMap<String, FunctionNode>,
+    subtree: &str,
+    keep_outgoing: bool,
+) -> HashMap<String, FunctionNode> {
+    let subtree = normalize_relative_path(subtree);
+    let mut retained = retain_nodes(call_graph, |node| {
+        subtree.is_empty()
+            || node
+                .relative_path
+                .strip_prefix(&subtree)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    });
+    if keep_outgoing {
+        for (key, node) in retained.iter_mut() {
+            let leaving: Vec<String> = call_graph[key]
+                .callees
+                .difference(&node.callees)
+                .cloned()
+                .collect();
+            node.external_callees.extend(leaving);
+        }
+    }
+    retained
+}
+
+/// Copy of `call_graph` with only the nodes for which `keep` holds, pruning callers
+/// that were dropped and callees whose symbol no longer has a node
+fn retain_nodes(
+    call_graph: &HashMap<String, FunctionNode>,
+    keep: impl Fn(&FunctionNode) -> bool,
+) -> HashMap<String, FunctionNode> {
+    let kept: HashMap<&String, &FunctionNode> =
+        call_graph.iter().filter(|(_, node)| keep(node)).collect();
+    let kept_symbols: HashSet<&str> = kept.values().map(|node| node.symbol.as_str()).collect();
+    kept.iter()
+        .map(|(&key, &node)| {
+            let mut node = node.clone();
+            node.callers.retain(|caller| kept.contains_key(caller));
+            node.callees
+                .retain(|callee| kept_symbols.contains(callee.as_str()));
+            node.edge_kinds
+                .retain(|callee, _| kept_symbols.contains(callee.as_str()));
+            (key.clone(), node)
+        })
+        .collect()
+}
+
+/// Per-function fan-in/fan-out metrics, as written by [`write_metrics_json`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionMetrics {
+    pub symbol: String,
+    pub display_name: String,
+    pub fan_in: usize,
+    pub fan_out: usize,
+    pub body_lines: usize,
+}
+
+/// Compute (fan-in, fan-out) for every node, keyed like the graph: fan-in is the
+/// number of callers of the node and fan-out the number of symbols it calls. Nodes
+/// sharing a symbol (e.g. several trait impls) are counted separately, as in
+/// [`write_metrics_json`].
+pub fn compute_degree_metrics(
+    call_graph: &HashMap<String, FunctionNode>,
+) -> HashMap<String, (usize, usize)> {
+    call_graph
+        .iter()
+        .map(|(key, node)| (key.clone(), (node.callers.len(), node.callees.len())))
+        .collect()
+}
+
+/// Write per-function fan-in, fan-out and body line count as JSON, sorted by symbol.
+/// There is one entry per node, with the fan-in and fan-out of
+/// [`compute_degree_metrics`], so nodes sharing a symbol get one entry each.
+pub fn write_metrics_json<P: AsRef<std::path::Path>>(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: P,
+) -> std::io::Result<()> {
+    let degrees = compute_degree_metrics(call_graph);
+    let mut nodes: Vec<(&String, &FunctionNode)> = call_graph.iter().collect();
+    nodes.sort_by(|(_, a), (_, b)| {
+        (&a.symbol, &a.signature_text).cmp(&(&b.symbol, &b.signature_text))
+    });
+
+    let metrics: Vec<FunctionMetrics> = nodes
+        .into_iter()
+        .map(|(key, node)| {
+            let (fan_in, fan_out) = degrees[key];
+            FunctionMetrics {
+                symbol: node.symbol.clone(),
+                display_name: node.display_name.clone(),
+                fan_in,
+                fan_out,
+                body_lines: node.body.as_deref().map_or(0, |b| b.lines().count()),
+            }
+        })
+        .collect();
+
+    let json = serde_json::to_string_pretty(&metrics).unwrap();
+    std::fs::write(output_path, json)
+}
+
+/// Functions whose extracted body is longer than `max_lines`, with their line counts,
+/// largest first (ties sorted by symbol and signature).
+///
+/// This relies on extracted bodies: a graph built without them (e.g. with
+/// [`build_call_graph_edges_only`]) always gives an empty result.
+pub fn find_large_functions(
+    call_graph: &HashMap<String, FunctionNode>,
+    max_lines: usize,
+) -> Vec<(&FunctionNode, usize)> {
+    let mut large: Vec<(&FunctionNode, usize)> = call_graph
+        .values()
+        .filter(|node| is_function_like(node.kind))
+        .filter_map(|node| Some((node, node.body.as_deref()?.lines().count())))
+        .filter(|&(_, lines)| lines > max_lines)
+        .collect();
+    large.sort_by(|(a, a_lines), (b, b_lines)| {
+        b_lines
+            .cmp(a_lines)
+            .then_with(|| (&a.symbol, &a.signature_text).cmp(&(&b.symbol, &b.signature_text)))
+    });
+    large
+}
+
+/// Default bucket edges for [`body_size_histogram`], in body lines
+pub const DEFAULT_HISTOGRAM_BUCKETS: &[usize] = &[0, 10, 25, 50, 100, 250];
+
+/// Number of functions per body size bucket, by body line count (`None` bodies count
+/// as 0 lines). `buckets` holds the lower edge of each bucket, so e.g. `[0, 10, 50]`
+/// counts 0-9, 10-49 and 50 or more lines; edges are sorted and deduplicated first.
+/// Functions shorter than the smallest edge are not counted.
+///
+/// Returns `(lower edge, function count)` pairs in ascending order. Type and value
+/// definitions are not functions and are skipped.
+pub fn body_size_histogram(
+    call_graph: &HashMap<String, FunctionNode>,
+    buckets: &[usize],
+) -> Vec<(usize, usize)> {
+    let edges: BTreeSet<usize> = buckets.iter().copied().collect();
+    let mut histogram: Vec<(usize, usize)> = edges.into_iter().map(|edge| (edge, 0)).collect();
+    for node in call_graph
+        .values()
+        .filter(|node| is_function_like(node.kind))
+    {
+        let lines = node.body.as_deref().map_or(0, |b| b.lines().count());
+        if let Some(bucket) = histogram.iter_mut().rev().find(|(edge, _)| *edge <= lines) {
+            bucket.1 += 1;
+        }
+    }
+    histogram
+}
+
+/// One bucket of [`body_size_histogram`], as written by [`write_histogram_json`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub min_lines: usize,
+    /// Exclusive upper bound, `None` for the last bucket
+    pub max_lines: Option<usize>,
+    pub functions: usize,
+}
+
+/// Write [`body_size_histogram`] as a JSON array of [`HistogramBucket`]s
+pub fn write_histogram_json<P: AsRef<std::path::Path>>(
+    call_graph: &HashMap<String, FunctionNode>,
+    buckets: &[usize],
+    output_path: P,
+) -> std::io::Result<()> {
+    let histogram = body_size_histogram(call_graph, buckets);
+    let rows: Vec<HistogramBucket> = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, &(min_lines, functions))| HistogramBucket {
+            min_lines,
+            max_lines: histogram.get(i + 1).map(|&(edge, _)| edge),
+            functions,
+        })
+        .collect();
+
+    let json = serde_json::to_string_pretty(&rows).unwrap();
+    std::fs::write(output_path, json)
+}
+
+/// Default damping factor for [`compute_pagerank`]
+pub const DEFAULT_PAGERANK_DAMPING: f64 = 0.85;
+
+/// Default iteration count for [`compute_pagerank`]
+pub const DEFAULT_PAGERANK_ITERATIONS: usize = 50;
+
+/// Compute PageRank scores per symbol, treating callee edges as links.
+///
+/// Functions called by many (important) functions rank highest. The rank of dangling
+/// nodes (no callees) is redistributed uniformly, so the scores always sum to 1.0.
+/// See [`DEFAULT_PAGERANK_DAMPING`] and [`DEFAULT_PAGERANK_ITERATIONS`] for defaults.
+pub fn compute_pagerank(
+    call_graph: &HashMap<String, FunctionNode>,
+    damping: f64,
+    iterations: usize,
+) -> HashMap<String, f64> {
+    let adjacency = callee_adjacency(call_graph);
+    let n = adjacency.len();
+    if n == 0 {
+        return HashMap::new();
+    }
+
+    let uniform = 1.0 / n as f64;
+    let mut ranks: HashMap<&str, f64> = adjacency.keys().map(|&s| (s, uniform)).collect();
+
+    for _ in 0..iterations {
+        let dangling_rank: f64 = adjacency
+            .iter()
+            .filter(|(_, callees)| callees.is_empty())
+            .map(|(symbol, _)| ranks[symbol])
+            .sum();
+        let base = (1.0 - damping) * uniform + damping * dangling_rank * uniform;
+
+        let mut next: HashMap<&str, f64> = adjacency.keys().map(|&s| (s, base)).collect();
+        for (symbol, callees) in &adjacency {
+            if callees.is_empty() {
+                continue;
+            }
+            let share = damping * ranks[symbol] / callees.len() as f64;
+            for callee in callees {
+                *next.get_mut(callee).unwrap() += share;
+            }
+        }
+        ranks = next;
+    }
+
+    // Normalize to guard against floating point drift
+    let total: f64 = ranks.values().sum();
+    ranks
+        .into_iter()
+        .map(|(symbol, rank)| (symbol.to_string(), rank / total))
+        .collect()
+}
+
+/// Functions and call edges that differ between two call graphs.
+/// Functions are identified by [`symbol_to_path`], edges by `(caller, callee)` paths.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CallGraphDiff {
+    pub added_functions: Vec<String>,
+    pub removed_functions: Vec<String>,
+    pub added_edges: Vec<(String, String)>,
+    pub removed_edges: Vec<(String, String)>,
+}
+
+impl CallGraphDiff {
+    /// True if both graphs have the same functions and edges
+    pub fn is_empty(&self) -> bool {
+        self.added_functions.is_empty()
+            && self.removed_functions.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+    }
+}
+
+/// Collect the function paths and the (caller, callee) path edges of a graph
+fn graph_paths(
+    call_graph: &HashMap<String, FunctionNode>,
+) -> (BTreeSet<String>, BTreeSet<(String, String)>) {
+    let path_of: HashMap<&str, String> = call_graph
+        .values()
+        .map(|node| {
+            (
+                node.symbol.as_str(),
+                symbol_to_path(&node.symbol, &node.display_name),
+            )
+        })
+        .collect();
+
+    let functions: BTreeSet<String> = path_of.values().cloned().collect();
+    let mut edges = BTreeSet::new();
+    for node in call_graph.values() {
+        let caller = &path_of[node.symbol.as_str()];
+        for callee in &node.callees {
+            if let Some(callee_path) = path_of.get(callee.as_str()) {
+                edges.insert((caller.clone(), callee_path.clone()));
+            }
+        }
+    }
+    (functions, edges)
+}
+
+/// Compare two call graphs, e.g. built from two revisions of the same codebase.
+///
+/// Functions are keyed by [`symbol_to_path`] rather than by file, so moving a function
+/// between files of the same module does not show up as a change. Results are sorted.
+pub fn diff_call_graphs(
+    old: &HashMap<String, FunctionNode>,
+    new: &HashMap<String, FunctionNode>,
+) -> CallGraphDiff {
+    let (old_functions, old_edges) = graph_paths(old);
+    let (new_functions, new_edges) = graph_paths(new);
+
+    CallGraphDiff {
+        added_functions: new_functions.difference(&old_functions).cloned().collect(),
+        removed_functions: old_functions.difference(&new_functions).cloned().collect(),
+        added_edges: new_edges.difference(&old_edges).cloned().collect(),
+        removed_edges: old_edges.difference(&new_edges).cloned().collect(),
+    }
+}
+
+/// Number of functions listed in [`GraphStats::top_fan_out`]
+const TOP_FAN_OUT_COUNT: usize = 5;
+
+/// Summary statistics of a call graph, see [`call_graph_summary`]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct GraphStats {
+    pub functions: usize,
+    pub edges: usize,
+    pub functions_with_bodies: usize,
+    /// `(path, fan_out)` of the functions calling the most others, highest first
+    pub top_fan_out: Vec<(String, usize)>,
+}
+
+/// Summarize a call graph: node and edge counts, extracted bodies and the
+/// functions with the highest fan-out.
+pub fn call_graph_summary(call_graph: &HashMap<String, FunctionNode>) -> GraphStats {
+    let mut fan_out: Vec<(String, usize)> = call_graph
+        .values()
+        .map(|node| {
+            (
+                symbol_to_path(&node.symbol, &node.display_name),
+                node.callees.len(),
+            )
+        })
+        .collect();
+    fan_out.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    fan_out.truncate(TOP_FAN_OUT_COUNT);
+
+    GraphStats {
+        functions: call_graph.len(),
+        edges: call_graph.values().map(|node| node.callees.len()).sum(),
+        functions_with_bodies: call_graph.values().filter(|n| n.body.is_some()).count(),
+        top_fan_out: fan_out,
+    }
+}
+
+impl std::fmt::Display for GraphStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Functions: {}", self.functions)?;
+        writeln!(f, "Edges: {}", self.edges)?;
+        write!(
+            f,
+            "Functions with bodies: {}/{}",
+            self.functions_with_bodies, self.functions
+        )?;
+        if !self.top_fan_out.is_empty() {
+            write!(f, "\nTop functions by fan-out:")?;
+            for (path, fan_out) in &self.top_fan_out {
+                write!(f, "\n  {fan_out:>4}  {path}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maximum number of body characters shown in a DOT tooltip
+const DOT_TOOLTIP_MAX_CHARS: usize = 200;
+
+/// Turn a function body into a single-line DOT tooltip: quotes are made DOT-safe,
+/// the text is truncated, then `<`, `>` and `&` are HTML-escaped like the SVG output
+/// and backslashes are doubled so none can escape the closing quote.
+fn dot_tooltip(body: Option<&str>) -> String {
+    let Some(body) = body else {
+        return String::new();
+    };
+    let plain = body.replace(['\n', '\r'], " ").replace('"', "' ");
+    let plain = match plain.char_indices().nth(DOT_TOOLTIP_MAX_CHARS) {
+        Some((end, _)) => format!("{}...", &plain[..end]),
+        None => plain,
+    };
+    html_escape::encode_text(&plain).replace('\\', "\\\\")
+}
+
+/// Nodes included in the DOT renderings, leaving out benchmark, test and example paths
+fn dot_filtered_nodes(call_graph: &HashMap<String, FunctionNode>) -> Vec<&FunctionNode> {
+    let skip_paths = [
+        "libsignal/rust/protocol/benches",
+        "libsignal/rust/protocol/tests",
+        "libsignal/rust/protocol/examples",
+    ];
+    let mut nodes: Vec<&FunctionNode> = call_graph
+        .values()
+        .filter(|node| !skip_paths.iter().any(|p| node.file_path.contains(p)))
+        .collect();
+    nodes.sort_by(|a, b| (&a.symbol, &a.signature_text).cmp(&(&b.symbol, &b.signature_text)));
+    nodes
+}
+
+/// Module (directory) a node is clustered under: the parent of its file path
+fn module_of(node: &FunctionNode) -> String {
+    std::path::Path::new(&node.file_path)
+        .parent()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "root".to_string())
+}
+
+/// Directory of a node's path relative to the project root, `root` for files at the
+/// top; unlike [`module_of`] it does not depend on where the project is checked out
+fn relative_module_of(node: &FunctionNode) -> String {
+    let dir = relative_parent_dir(&node.relative_path);
+    if dir.is_empty() {
+        "root".to_string()
+    } else {
+        dir
+    }
+}
+
+/// Group nodes by module/directory, see [`module_of`]
+fn group_by_module<'a>(nodes: &[&'a FunctionNode]) -> BTreeMap<String, Vec<&'a FunctionNode>> {
+    let mut module_groups: BTreeMap<String, Vec<&FunctionNode>> = BTreeMap::new();
+    for node in nodes {
+        module_groups
+            .entry(module_of(node))
+            .or_default()
+            .push(*node);
+    }
+    module_groups
+}
+
+/// Scales node sizes by the number of lines in the function body, as a crude
+/// picture of where the code volume is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeSizing {
+    /// Size of functions without a body
+    pub min: f64,
+    /// Size of functions with `max_lines` lines or more
+    pub max: f64,
+    /// Line count at which nodes stop growing
+    pub max_lines: usize,
+}
+
+impl NodeSizing {
+    /// Size for `node`, linear in its body line count between `min` and `max`
+    pub fn size(&self, node: &FunctionNode) -> f64 {
+        let lines = node.body.as_deref().map_or(0, |body| body.lines().count());
+        if self.max_lines == 0 {
+            return if lines > 0 { self.max } else { self.min };
+        }
+        let fraction = lines.min(self.max_lines) as f64 / self.max_lines as f64;
+        self.min + (self.max - self.min) * fraction
+    }
+}
+
+/// Presentation options shared by the DOT generators
+#[derive(Debug, Clone, PartialEq)]
+pub struct DotOptions {
+    /// Graphviz layout direction: `LR` (left to right), `TB` (top to bottom), `RL` or `BT`
+    pub rankdir: String,
+    /// Font for node and cluster labels, e.g. `Courier` for a monospace font
+    pub fontname: String,
+    /// Link each node to its source, e.g.
+    /// `https://github.com/org/repo/blob/main/{path}#L{line}`. `{path}` is replaced by the
+    /// node's relative path and `{line}` by its 1-based start line. Graphviz turns the
+    /// resulting `URL` attributes into clickable links in SVG output.
+    pub url_template: Option<String>,
+    /// Scale node widths (in inches) by body line count
+    pub node_sizing: Option<NodeSizing>,
+    /// Draw trait calls differently from direct calls in [`call_graph_to_dot`]:
+    /// [`EdgeKind::TraitStatic`] edges dashed and [`EdgeKind::TraitDynamic`] dotted
+    pub edge_kind_styles: bool,
+}
+
+impl Default for DotOptions {
+    fn default() -> Self {
+        DotOptions {
+            rankdir: "LR".to_string(),
+            fontname: "Helvetica".to_string(),
+            url_template: None,
+            node_sizing: None,
+            edge_kind_styles: false,
+        }
+    }
+}
+
+/// Source link for a node built from `template`, see [`DotOptions::url_template`].
+/// Nodes without a range link to line 1.
+fn node_url(node: &FunctionNode, template: &str) -> String {
+    let line = node.range.first().map_or(1, |start| start + 1);
+    template
+        .replace("{path}", &node.relative_path)
+        .replace("{line}", &line.to_string())
+        .replace('"', "%22")
+}
+
+/// Extra DOT attributes for a function node (each with a leading `, `), from `options`
+fn dot_node_attrs(node: &FunctionNode, options: &DotOptions) -> String {
+    let mut attrs = String::new();
+    if let Some(template) = &options.url_template {
+        attrs.push_str(&format!(", URL=\"{}\"", node_url(node, template)));
+    }
+    if let Some(sizing) = &options.node_sizing {
+        attrs.push_str(&format!(", width={:.2}", sizing.size(node)));
+    }
+    attrs
+}
+
+/// DOT statement for a function node, labelled with its name and its body as tooltip
+fn dot_node_line(node: &FunctionNode, options: &DotOptions) -> String {
+    format!(
+        "    \"{}\" [label=\"{}\", tooltip=\"{}\"{}]\n",
+        node.symbol,
+        node.display_name,
+        dot_tooltip(node.body.as_deref()),
+        dot_node_attrs(node, options)
+    )
+}
+
+/// Generate a DOT file format for the call graph that can be rendered by Graphviz
+///
+/// If `highlight_cycles` is set, edges that are part of a cycle (see [`find_cycles`])
+/// are colored red.
+pub fn generate_call_graph_dot(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: &str,
+    highlight_cycles: bool,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    std::fs::write(
+        output_path,
+        call_graph_to_dot(call_graph, highlight_cycles, options),
+    )
+}
+
+/// Like [`generate_call_graph_dot`], writing the DOT source to `writer`
+pub fn generate_call_graph_dot_to<W: Write>(
+    call_graph: &HashMap<String, FunctionNode>,
+    writer: &mut W,
+    highlight_cycles: bool,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    writer.write_all(call_graph_to_dot(call_graph, highlight_cycles, options).as_bytes())
+}
+
+/// Render the call graph as a DOT string, see [`generate_call_graph_dot`]
+pub fn call_graph_to_dot(
+    call_graph: &HashMap<String, FunctionNode>,
+    highlight_cycles: bool,
+    options: &DotOptions,
+) -> String {
+    let mut dot = String::from("digraph call_graph {\n");
+    dot.push_str(&format!("  rankdir={};\n", options.rankdir));
+    dot.push_str(&format!(
+        "  node [shape=box, style=filled, fillcolor=lightblue, fontname=\"{}\"];\n",
+        options.fontname
+    ));
+    dot.push_str("  edge [color=gray];\n\n");
+
+    let filtered_nodes = dot_filtered_nodes(call_graph);
+    let module_groups = group_by_module(&filtered_nodes);
+
+    for (cluster_id, (module, nodes)) in module_groups.iter().enumerate() {
+        dot.push_str(&format!("  subgraph cluster_{} {{\n    label = \"{}\";\n    style=filled;\n    color=lightgrey;\n    fontname=\"{}\";\n", cluster_id, module, options.fontname));
+        for node in nodes {
+            dot.push_str(&dot_node_line(node, options));
+        }
+        dot.push_str("  }\n");
+    }
+
+    dot.push('\n');
+
+    // Map each symbol on a cycle to the index of its cycle
+    let mut cycle_of: HashMap<String, usize> = HashMap::new();
+    if highlight_cycles {
+        for (cycle_id, cycle) in find_cycles(call_graph).into_iter().enumerate() {
+            for symbol in cycle {
+                cycle_of.insert(symbol, cycle_id);
+            }
+        }
+    }
+
+    // Add edges, but only for filtered nodes
+    let filtered_symbols: std::collections::HashSet<_> =
+        filtered_nodes.iter().map(|n| &n.symbol).collect();
+    for node in &filtered_nodes {
+        for callee in node.sorted_callees() {
+            if filtered_symbols.contains(callee) {
+                let in_cycle = matches!(
+                    (cycle_of.get(&node.symbol), cycle_of.get(callee)),
+                    (Some(a), Some(b)) if a == b
+                );
+                let mut attrs = Vec::new();
+                if in_cycle {
+                    attrs.push("color=red");
+                }
+                if options.edge_kind_styles {
+                    attrs.extend(node.edge_kind(callee).dot_style());
+                }
+                if attrs.is_empty() {
+                    dot.push_str(&format!("  \"{}\" -> \"{}\"\n", node.symbol, callee));
+                } else {
+                    dot.push_str(&format!(
+                        "  \"{}\" -> \"{}\" [{}]\n",
+                        node.symbol,
+                        callee,
+                        attrs.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Generate a PlantUML component diagram of the call graph, see
+/// [`call_graph_to_plantuml`]
+pub fn generate_call_graph_plantuml<P: AsRef<Path>>(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: P,
+) -> std::io::Result<()> {
+    std::fs::write(output_path, call_graph_to_plantuml(call_graph))
+}
+
+/// Make `text` safe inside a double-quoted PlantUML name: `"` has no escape there
+/// and becomes `'`, a backslash would start an escape like `\n` and is doubled, and
+/// line breaks become spaces
+fn plantuml_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "'")
+        .replace(['\n', '\r'], " ")
+}
+
+/// Render the call graph as PlantUML: every function is a `component` labelled with
+/// its display name inside a `package` per module (see [`relative_module_of`]), and every
+/// call is a `-->` arrow. Components get short aliases (`f0`, `f1`, ...) in key
+/// order, since SCIP symbols are not valid PlantUML identifiers.
+pub fn call_graph_to_plantuml(call_graph: &HashMap<String, FunctionNode>) -> String {
+    let mut nodes: Vec<(&String, &FunctionNode)> = call_graph.iter().collect();
+    nodes.sort_by_key(|(key, _)| *key);
+    let alias_of: HashMap<&str, String> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, (key, _))| (key.as_str(), format!("f{i}")))
+        .collect();
+    let mut aliases_by_symbol: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (key, node) in &nodes {
+        aliases_by_symbol
+            .entry(node.symbol.as_str())
+            .or_default()
+            .push(&alias_of[key.as_str()]);
+    }
+
+    let mut packages: BTreeMap<String, Vec<(&String, &FunctionNode)>> = BTreeMap::new();
+    for &(key, node) in &nodes {
+        packages
+            .entry(relative_module_of(node))
+            .or_default()
+            .push((key, node));
+    }
+
+    let mut uml = String::from("@startuml\n");
+    for (module, members) in &packages {
+        uml.push_str(&format!("package \"{}\" {{\n", plantuml_escape(module)));
+        for (key, node) in members {
+            uml.push_str(&format!(
+                "  component \"{}\" as {}\n",
+                plantuml_escape(&node.display_name),
+                alias_of[key.as_str()]
+            ));
+        }
+        uml.push_str("}\n");
+    }
+
+    let mut edges: BTreeSet<(&str, &str)> = BTreeSet::new();
+    for (key, node) in &nodes {
+        let from = alias_of[key.as_str()].as_str();
+        for callee in &node.callees {
+            for &to in aliases_by_symbol.get(callee.as_str()).into_iter().flatten() {
+                edges.insert((from, to));
+            }
+        }
+    }
+    for (from, to) in edges {
+        uml.push_str(&format!("{from} --> {to}\n"));
+    }
+    uml.push_str("@enduml\n");
+    uml
+}
+
+/// Node of a [`D3Graph`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct D3Node {
+    /// SCIP symbol of the function
+    pub id: String,
+    /// Display name
+    pub name: String,
+    /// Index of the node's module (see [`module_of`]) in sorted module order
+    pub group: usize,
+}
+
+/// Link of a [`D3Graph`], between node ids
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct D3Link {
+    pub source: String,
+    pub target: String,
+    /// Number of definitions of `source` that call `target`; 1 unless several nodes
+    /// share the symbol (e.g. trait impls)
+    pub value: usize,
+}
+
+/// Call graph in the `{"nodes": [...], "links": [...]}` shape used by D3's force
+/// layout, see [`call_graph_to_d3`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct D3Graph {
+    pub nodes: Vec<D3Node>,
+    pub links: Vec<D3Link>,
+}
+
+/// Convert the call graph for D3. Nodes are identified by their SCIP symbol, so nodes
+/// sharing a symbol become one D3 node (named after the first by key) and their calls
+/// are summed into the link `value`. Nodes and links are sorted by id.
+pub fn call_graph_to_d3(call_graph: &HashMap<String, FunctionNode>) -> D3Graph {
+    let mut nodes: Vec<(&String, &FunctionNode)> = call_graph.iter().collect();
+    nodes.sort_by_key(|(key, _)| *key);
+    let modules: BTreeSet<String> = nodes.iter().map(|(_, node)| module_of(node)).collect();
+    let group_of = |node: &FunctionNode| {
+        let module = module_of(node);
+        modules
+            .iter()
+            .position(|m| *m == module)
+            .unwrap_or_default()
+    };
+
+    let mut d3_nodes: BTreeMap<&str, D3Node> = BTreeMap::new();
+    let mut links: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+    for (_, node) in &nodes {
+        d3_nodes
+            .entry(node.symbol.as_str())
+            .or_insert_with(|| D3Node {
+                id: node.symbol.clone(),
+                name: node.display_name.clone(),
+                group: group_of(node),
+            });
+    }
+    for (_, node) in &nodes {
+        for callee in &node.callees {
+            if d3_nodes.contains_key(callee.as_str()) {
+                *links
+                    .entry((node.symbol.as_str(), callee.as_str()))
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    D3Graph {
+        links: links
+            .into_iter()
+            .map(|((source, target), value)| D3Link {
+                source: source.to_string(),
+                target: target.to_string(),
+                value,
+            })
+            .collect(),
+        nodes: d3_nodes.into_values().collect(),
+    }
+}
+
+/// Write the call graph as D3 force-layout JSON, see [`call_graph_to_d3`]
+pub fn generate_call_graph_d3_json<P: AsRef<Path>>(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: P,
+) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(&call_graph_to_d3(call_graph))
+        .map_err(std::io::Error::other)?;
+    std::fs::write(output_path, json)
+}
+
+/// Rows per `UNWIND` statement in [`call_graph_to_cypher`]
+pub const CYPHER_BATCH_SIZE: usize = 500;
+
+/// Quote `text` as a Cypher string literal
+fn cypher_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('\'');
+    for c in text.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Write a Cypher script that loads the call graph into Neo4j, e.g. with
+/// `cypher-shell < file`, see [`call_graph_to_cypher`]
+pub fn write_call_graph_cypher<P: AsRef<Path>>(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: P,
+) -> std::io::Result<()> {
+    std::fs::write(output_path, call_graph_to_cypher(call_graph))
+}
+
+/// Render the call graph as Cypher statements: a uniqueness constraint on
+/// `Function.symbol`, then batches of [`CYPHER_BATCH_SIZE`] rows that `MERGE` one
+/// `(:Function {symbol, name, path, file})` node per SCIP symbol and
+/// `(a)-[:CALLS]->(b)` relationships. `path` is the [`symbol_to_path`] identifier and
+/// `file` the relative source path. Running the script again updates the graph in place.
+pub fn call_graph_to_cypher(call_graph: &HashMap<String, FunctionNode>) -> String {
+    cypher_script(call_graph, CYPHER_BATCH_SIZE)
+}
+
+fn cypher_script(call_graph: &HashMap<String, FunctionNode>, batch_size: usize) -> String {
+    let mut nodes: Vec<(&String, &FunctionNode)> = call_graph.iter().collect();
+    nodes.sort_by_key(|(key, _)| *key);
+    // One node per symbol, like the edges, which only carry the callee's symbol
+    let mut functions: BTreeMap<&str, &FunctionNode> = BTreeMap::new();
+    for (_, node) in &nodes {
+        functions.entry(node.symbol.as_str()).or_insert(node);
+    }
+    let calls: BTreeSet<(&str, &str)> = nodes
+        .iter()
+        .flat_map(|(_, node)| {
+            node.callees
+                .iter()
+                .filter(|callee| functions.contains_key(callee.as_str()))
+                .map(|callee| (node.symbol.as_str(), callee.as_str()))
+        })
+        .collect();
+
+    let mut cypher = String::from(
+        "CREATE CONSTRAINT function_symbol IF NOT EXISTS FOR (f:Function) REQUIRE f.symbol IS UNIQUE;\n",
+    );
+    let functions: Vec<(&str, &FunctionNode)> = functions.into_iter().collect();
+    for batch in functions.chunks(batch_size.max(1)) {
+        let rows: Vec<String> = batch
+            .iter()
+            .map(|(symbol, node)| {
+                format!(
+                    "  {{symbol: {}, name: {}, path: {}, file: {}}}",
+                    cypher_string(symbol),
+                    cypher_string(&node.display_name),
+                    cypher_string(&symbol_to_path(symbol, &node.display_name)),
+                    cypher_string(&node.relative_path)
+                )
+            })
+            .collect();
+        cypher.push_str(&format!(
+            "UNWIND [\n{}\n] AS row\nMERGE (f:Function {{symbol: row.symbol}})\nSET f.name = row.name, f.path = row.path, f.file = row.file;\n",
+            rows.join(",\n")
+        ));
+    }
+    let calls: Vec<(&str, &str)> = calls.into_iter().collect();
+    for batch in calls.chunks(batch_size.max(1)) {
+        let rows: Vec<String> = batch
+            .iter()
+            .map(|(from, to)| {
+                format!(
+                    "  {{from: {}, to: {}}}",
+                    cypher_string(from),
+                    cypher_string(to)
+                )
+            })
+            .collect();
+        cypher.push_str(&format!(
+            "UNWIND [\n{}\n] AS row\nMATCH (a:Function {{symbol: row.from}}), (b:Function {{symbol: row.to}})\nMERGE (a)-[:CALLS]->(b);\n",
+            rows.join(",\n")
+        ));
+    }
+    cypher
+}
+
+/// Rendering-time filter for weighted edges; the graph itself is never modified.
+///
+/// Only the module summary ([`module_summary_dot`]) has weighted edges, so it is the
+/// only output the filter applies to; the function-level DOT, SVG, PlantUML, D3 and
+/// Cypher outputs draw every call. It sees the graph after any subtree, include or
+/// exclude filtering. It is unrelated to [`SvgLayout::max_nodes`], which never drops
+/// nodes or edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeFilter {
+    /// Edges with a weight below this are not drawn
+    pub min_edge_weight: u32,
+    /// Also leave out nodes that have no drawn edge left
+    pub drop_isolated: bool,
+}
+
+impl Default for EdgeFilter {
+    fn default() -> Self {
+        EdgeFilter {
+            min_edge_weight: 1,
+            drop_isolated: false,
+        }
+    }
+}
+
+/// Write an architecture-level DOT graph with one node per module (clustered as in
+/// [`generate_call_graph_dot`]) and an edge A -> B weighted by the number of calls from
+/// functions in A to functions in B, see [`module_summary_dot`]
+pub fn generate_module_summary_dot(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: &str,
+    filter: &EdgeFilter,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    std::fs::write(output_path, module_summary_dot(call_graph, filter, options))
+}
+
+/// Like [`generate_module_summary_dot`], writing the DOT source to `writer`
+pub fn generate_module_summary_dot_to<W: Write>(
+    call_graph: &HashMap<String, FunctionNode>,
+    writer: &mut W,
+    filter: &EdgeFilter,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    writer.write_all(module_summary_dot(call_graph, filter, options).as_bytes())
+}
+
+/// Render the module summary graph as a DOT string, see [`generate_module_summary_dot`].
+/// Edges lighter than `filter.min_edge_weight` are dropped before `filter.drop_isolated`
+/// removes modules without any remaining edge. Only the layout direction and font of
+/// `options` apply to the summary.
+pub fn module_summary_dot(
+    call_graph: &HashMap<String, FunctionNode>,
+    filter: &EdgeFilter,
+    options: &DotOptions,
+) -> String {
+    let filtered_nodes = dot_filtered_nodes(call_graph);
+    let module_groups = group_by_module(&filtered_nodes);
+    let mut module_by_symbol: HashMap<&str, String> = HashMap::new();
+    for node in &filtered_nodes {
+        module_by_symbol
+            .entry(node.symbol.as_str())
+            .or_insert_with(|| module_of(node));
+    }
+
+    let mut weights: BTreeMap<(String, String), usize> = BTreeMap::new();
+    for node in &filtered_nodes {
+        let caller_module = module_of(node);
+        for callee in &node.callees {
+            match module_by_symbol.get(callee.as_str()) {
+                Some(callee_module) if *callee_module != caller_module => {
+                    *weights
+                        .entry((caller_module.clone(), callee_module.clone()))
+                        .or_default() += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    weights.retain(|_, weight| *weight >= filter.min_edge_weight as usize);
+    let connected: HashSet<&str> = weights
+        .keys()
+        .flat_map(|(from, to)| [from.as_str(), to.as_str()])
+        .collect();
+
+    let mut dot = String::from("digraph module_graph {\n");
+    dot.push_str(&format!("  rankdir={};\n", options.rankdir));
+    dot.push_str(&format!(
+        "  node [shape=box, style=filled, fillcolor=lightblue, fontname=\"{}\"];\n",
+        options.fontname
+    ));
+    dot.push_str(&format!(
+        "  edge [color=gray, fontname=\"{}\"];\n\n",
+        options.fontname
+    ));
+    for (module, nodes) in &module_groups {
+        if filter.drop_isolated && !connected.contains(module.as_str()) {
+            continue;
+        }
+        let label = Path::new(&nodes[0].relative_path)
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| "root".to_string());
+        dot.push_str(&format!(
+            "  \"{}\" [label=\"{} ({})\", tooltip=\"{}\"]\n",
+            module,
+            label,
+            nodes.len(),
+            module
+        ));
+    }
+    dot.push('\n');
+    for ((from, to), weight) in &weights {
+        dot.push_str(&format!(
+            "  \"{from}\" -> \"{to}\" [label=\"{weight}\", weight={weight}]\n"
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Image formats accepted by [`render_dot_to_image`]
+pub const GRAPHVIZ_FORMATS: [&str; 3] = ["png", "svg", "pdf"];
+
+/// Render a DOT file to an image by running Graphviz (`dot -T<format>`).
+///
+/// `format` must be one of [`GRAPHVIZ_FORMATS`]. Fails with a clear message if `dot`
+/// is not on `PATH`, and with Graphviz's stderr if rendering fails.
+pub fn render_dot_to_image<P: AsRef<Path>, Q: AsRef<Path>>(
+    dot_path: P,
+    output_path: Q,
+    format: &str,
+) -> std::io::Result<()> {
+    if !GRAPHVIZ_FORMATS.contains(&format) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "unsupported image format {format:?} (expected one of {})",
+                GRAPHVIZ_FORMATS.join(", ")
+            ),
+        ));
+    }
+
+    let output = std::process::Command::new("dot")
+        .arg(format!("-T{format}"))
+        .arg(dot_path.as_ref())
+        .arg("-o")
+        .arg(output_path.as_ref())
+        .output()
+        .map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                std::io::Error::new(
+                    e.kind(),
+                    "Graphviz `dot` not found on PATH; install Graphviz to render images",
+                )
+            } else {
+                e
+            }
+        })?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "dot exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Write one DOT file per module (directory, clustered as in [`generate_call_graph_dot`])
+/// into `output_dir`. Each file holds the module's functions plus dashed stub nodes for
+/// callees in other modules, and is named after the module's relative directory
+/// (e.g. `src_field.dot`). Directories that map to the same name, such as `src/a_b`
+/// and `src/a/b`, get a numeric suffix (`src_a_b_2.dot`). Returns the paths written,
+/// sorted.
+pub fn generate_per_module_dot<P: AsRef<Path>>(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_dir: P,
+    options: &DotOptions,
+) -> std::io::Result<Vec<PathBuf>> {
+    let output_dir = output_dir.as_ref();
+    std::fs::create_dir_all(output_dir)?;
+
+    let filtered_nodes = dot_filtered_nodes(call_graph);
+    let mut by_symbol: BTreeMap<&str, &FunctionNode> = BTreeMap::new();
+    for node in &filtered_nodes {
+        by_symbol.entry(node.symbol.as_str()).or_insert(node);
+    }
+
+    let mut written = Vec::new();
+    let mut taken = HashSet::new();
+    for (module, nodes) in group_by_module(&filtered_nodes) {
+        let mut dot = String::from("digraph call_graph {\n");
+        dot.push_str(&format!("  rankdir={};\n", options.rankdir));
+        dot.push_str(&format!("  label=\"{module}\";\n"));
+        dot.push_str(&format!(
+            "  node [shape=box, style=filled, fillcolor=lightblue, fontname=\"{}\"];\n",
+            options.fontname
+        ));
+        dot.push_str("  edge [color=gray];\n\n");
+
+        let mut sorted_nodes = nodes.clone();
+        sorted_nodes.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        for node in &sorted_nodes {
+            dot.push_str(&dot_node_line(node, options));
+        }
+
+        let mut edges = BTreeSet::new();
+        let mut stubs = BTreeSet::new();
+        for node in &sorted_nodes {
+            for callee in &node.callees {
+                let Some(callee_node) = by_symbol.get(callee.as_str()) else {
+                    continue;
+                };
+                if module_of(callee_node) != module {
+                    stubs.insert(callee.as_str());
+                }
+                edges.insert((node.symbol.as_str(), callee.as_str()));
+            }
+        }
+        for stub in &stubs {
+            let callee_node = by_symbol[stub];
+            dot.push_str(&format!(
+                "    \"{}\" [label=\"{}\", tooltip=\"{}\", style=dashed, fillcolor=white{}]\n",
+                stub,
+                callee_node.display_name,
+                module_of(callee_node),
+                dot_node_attrs(callee_node, options)
+            ));
+        }
+        dot.push('\n');
+        for (caller, callee) in edges {
+            dot.push_str(&format!("  \"{caller}\" -> \"{callee}\"\n"));
+        }
+        dot.push_str("}\n");
+
+        let relative_dir = Path::new(&nodes[0].relative_path)
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| "root".to_string());
+        let base_name: String = relative_dir
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let mut file_name = base_name.clone();
+        let mut suffix = 1;
+        while !taken.insert(file_name.clone()) {
+            suffix += 1;
+            file_name = format!("{base_name}_{suffix}");
+        }
+        let path = output_dir.join(format!("{file_name}.dot"));
+        std::fs::write(&path, dot)?;
+        written.push(path);
+    }
+    written.sort();
+    Ok(written)
+}
+
+/// Crate of a node for [`generate_per_crate_dot`], `unknown` if the symbol has none
+fn crate_of(node: &FunctionNode) -> String {
+    let crate_name = symbol_crate_name(&node.symbol);
+    if crate_name.is_empty() {
+        "unknown".to_string()
+    } else {
+        crate_name
+    }
+}
+
+/// Write one DOT file per crate (see [`symbol_crate_name`]) into `output_dir`, named
+/// `<crate>.dot`. Each graph clusters the crate's functions by module and adds dashed
+/// stub nodes, labeled with their crate, for callees in other crates. Returns the
+/// paths written, sorted.
+pub fn generate_per_crate_dot<P: AsRef<Path>>(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_dir: P,
+    options: &DotOptions,
+) -> std::io::Result<Vec<PathBuf>> {
+    let output_dir = output_dir.as_ref();
+    std::fs::create_dir_all(output_dir)?;
+
+    let filtered_nodes = dot_filtered_nodes(call_graph);
+    let mut by_symbol: BTreeMap<&str, &FunctionNode> = BTreeMap::new();
+    for node in &filtered_nodes {
+        by_symbol.entry(node.symbol.as_str()).or_insert(node);
+    }
+    let mut by_crate: BTreeMap<String, Vec<&FunctionNode>> = BTreeMap::new();
+    for node in by_symbol.values() {
+        by_crate.entry(crate_of(node)).or_default().push(node);
+    }
+
+    let mut written = Vec::new();
+    for (crate_name, nodes) in &by_crate {
+        let mut dot = String::from("digraph call_graph {\n");
+        dot.push_str(&format!("  rankdir={};\n", options.rankdir));
+        dot.push_str(&format!("  label=\"{crate_name}\";\n"));
+        dot.push_str(&format!("  fontname=\"{}\";\n", options.fontname));
+        dot.push_str(&format!(
+            "  node [shape=box, style=filled, fillcolor=lightblue, fontname=\"{}\"];\n",
+            options.fontname
+        ));
+        dot.push_str("  edge [color=gray];\n\n");
+
+        for (i, (module, module_nodes)) in group_by_module(nodes).into_iter().enumerate() {
+            dot.push_str(&format!(
+                "  subgraph cluster_{i} {{\n    label = \"{module}\";\n    style=filled;\n    color=lightgrey;\n    fontname=\"{}\";\n",
+                options.fontname
+            ));
+            for node in module_nodes {
+                dot.push_str(&dot_node_line(node, options));
+            }
+            dot.push_str("  }\n");
+        }
+
+        let mut edges = BTreeSet::new();
+        let mut stubs = BTreeSet::new();
+        for node in nodes {
+            for callee in &node.callees {
+                let Some(callee_node) = by_symbol.get(callee.as_str()) else {
+                    continue;
+                };
+                if crate_of(callee_node) != *crate_name {
+                    stubs.insert(callee.as_str());
+                }
+                edges.insert((node.symbol.as_str(), callee.as_str()));
+            }
+        }
+        for stub in &stubs {
+            let callee_node = by_symbol[stub];
+            dot.push_str(&format!(
+                "  \"{}\" [label=\"{}\\n({})\", style=dashed, fillcolor=white{}]\n",
+                stub,
+                callee_node.display_name,
+                crate_of(callee_node),
+                dot_node_attrs(callee_node, options)
+            ));
+        }
+        dot.push('\n');
+        for (caller, callee) in edges {
+            dot.push_str(&format!("  \"{caller}\" -> \"{callee}\"\n"));
+        }
+        dot.push_str("}\n");
+
+        let path = output_dir.join(format!("{crate_name}.dot"));
+        std::fs::write(&path, dot)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Generate a DOT file with only the `pub` functions of the graph and the calls among
+/// them, i.e. the crate's external surface. Private, `pub(crate)` and restricted
+/// functions and all type definitions are left out.
+///
+/// Visibility comes from the parsed source, so functions whose body could not be
+/// extracted count as private. Returns the number of public functions drawn.
+pub fn generate_public_api_subgraph_dot(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: &str,
+    options: &DotOptions,
+) -> std::io::Result<usize> {
+    // Render fully before touching `output_path`, so no file is created on error
+    let mut dot = Vec::new();
+    let count = generate_public_api_subgraph_dot_to(call_graph, &mut dot, options)?;
+    std::fs::write(output_path, dot)?;
+    Ok(count)
+}
+
+/// Like [`generate_public_api_subgraph_dot`], writing the DOT source to `writer`
+pub fn generate_public_api_subgraph_dot_to<W: Write>(
+    call_graph: &HashMap<String, FunctionNode>,
+    writer: &mut W,
+    options: &DotOptions,
+) -> std::io::Result<usize> {
+    let mut public: BTreeMap<&str, &FunctionNode> = BTreeMap::new();
+    for node in dot_filtered_nodes(call_graph) {
+        if is_function_like(node.kind) && node.visibility == Visibility::Public {
+            public.entry(node.symbol.as_str()).or_insert(node);
+        }
+    }
+    debug!("Found {} public functions", public.len());
+
+    let mut dot = String::from("digraph public_api {\n");
+    dot.push_str(&format!("  rankdir={};\n", options.rankdir));
+    dot.push_str(&format!(
+        "  node [shape=box, style=filled, fillcolor=lightblue, fontname=\"{}\"];\n",
+        options.fontname
+    ));
+    dot.push_str("  edge [color=gray];\n\n");
+    for node in public.values() {
+        dot.push_str(&dot_node_line(node, options));
+    }
+    dot.push('\n');
+
+    let mut edges = BTreeSet::new();
+    for (symbol, node) in &public {
+        for callee in &node.callees {
+            if public.contains_key(callee.as_str()) {
+                edges.insert((*symbol, callee.as_str()));
+            }
+        }
+    }
+    for (caller, callee) in edges {
+        dot.push_str(&format!("  \"{caller}\" -> \"{callee}\"\n"));
+    }
+    dot.push_str("}\n");
+    writer.write_all(dot.as_bytes())?;
+    Ok(public.len())
+}
+
+/// Generate a DOT file format for a subgraph of the call graph containing only nodes from a specific file path
+///
+/// Functions up to `neighbor_depth` calls away (in either direction) are included as well,
+/// colored by their distance from the file. A depth of 1 keeps only direct callers and callees.
+pub fn generate_file_subgraph_dot(
+    call_graph: &HashMap<String, FunctionNode>,
+    file_path: &str,
+    output_path: &str,
+    neighbor_depth: usize,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    // Render fully before touching `output_path`, so no file is created on error
+    let mut dot = Vec::new();
+    generate_file_subgraph_dot_to(call_graph, file_path, &mut dot, neighbor_depth, options)?;
+    std::fs::write(output_path, dot)
+}
+
+/// Like [`generate_file_subgraph_dot`], writing the DOT source to `writer`
+pub fn generate_file_subgraph_dot_to<W: Write>(
+    call_graph: &HashMap<String, FunctionNode>,
+    file_path: &str,
+    writer: &mut W,
+    neighbor_depth: usize,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    use std::collections::HashSet;
+    let mut dot = String::from("digraph file_subgraph {\n");
+    dot.push_str(&format!("  rankdir={};\n", options.rankdir));
+    dot.push_str(&format!(
+        "  node [shape=box, style=filled, fontname=\"{}\"];\n",
+        options.fontname
+    ));
+    dot.push_str("  edge [color=gray];\n\n");
+
+    // Find nodes that belong to the specified file - more flexible path matching
+    let file_nodes: Vec<&FunctionNode> = call_graph
+        .values()
+        .filter(|node| {
+            // Extract the filename from the provided file_path argument
+            let requested_filename = Path::new(file_path)
+                .file_name()
+                .and_then(|f| f.to_str())
+                .unwrap_or(file_path);
+            node.file_path.ends_with(file_path)
+                || node.file_path == file_path
+                || node.symbol.contains(file_path)
+                || node.file_path.contains(requested_filename)
+        })
+        .collect();
+
+    if file_nodes.is_empty() {
+        // List available paths that contain part of the requested path
+        let matching_paths: HashSet<_> = call_graph
+            .values()
+            .filter(|node| node.file_path.contains(file_path))
+            .map(|node| &node.file_path)
+            .collect();
+
+        if !matching_paths.is_empty() {
+            let mut message = format!(
+                "No exact match for file path: {}\n\nHere are some similar paths:\n",
+                file_path
+            );
+            for path in matching_paths {
+                message.push_str(&format!("  {}\n", path));
+            }
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message));
+        }
+
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No functions found in file path: {}", file_path),
+        ));
+    }
+
+    info!("Found {} functions in file {}", file_nodes.len(), file_path);
+    for node in &file_nodes {
+        debug!("  - {} ({})", node.display_name, node.symbol);
+    }
+
+    // Nodes connected to this file through calls in either direction, up to
+    // `neighbor_depth` edges away
+    let file_symbols: Vec<String> = file_nodes.iter().map(|n| n.symbol.clone()).collect();
+    let distances = bfs_distances(call_graph, &file_symbols, true, true, neighbor_depth);
+
+    // Draw file nodes with blue background
+    for node in &file_nodes {
+        let label = node.display_name.clone();
+        let tooltip = dot_tooltip(node.body.as_deref());
+        dot.push_str(&format!(
+            "  \"{}\" [label=\"{}\", tooltip=\"{}\", fillcolor=lightblue{}]\n",
+            node.symbol,
+            label,
+            tooltip,
+            dot_node_attrs(node, options)
+        ));
+    }
+
+    // Draw connected nodes, lighter the further they are from the file, and the edges
+    push_file_neighbors(&mut dot, call_graph, &distances, neighbor_depth, options);
+
+    dot.push_str("}\n");
+    writer.write_all(dot.as_bytes())
+}
+
+/// Generate a DOT file format for a subgraph of the call graph containing only nodes from a specific set of file paths
+///
+/// See [`generate_file_subgraph_dot`] for how `neighbor_depth` is applied.
+pub fn generate_files_subgraph_dot(
+    call_graph: &HashMap<String, FunctionNode>,
+    file_paths: &[String],
+    output_path: &str,
+    neighbor_depth: usize,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    // Render fully before touching `output_path`, so no file is created on error
+    let mut dot = Vec::new();
+    generate_files_subgraph_dot_to(call_graph, file_paths, &mut dot, neighbor_depth, options)?;
+    std::fs::write(output_path, dot)
+}
+
+/// Like [`generate_files_subgraph_dot`], writing the DOT source to `writer`
+pub fn generate_files_subgraph_dot_to<W: Write>(
+    call_graph: &HashMap<String, FunctionNode>,
+    file_paths: &[String],
+    writer: &mut W,
+    neighbor_depth: usize,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    use std::collections::{BTreeMap, HashSet};
+    let mut dot = String::from("digraph files_subgraph {\n");
+    dot.push_str(&format!("  rankdir={};\n", options.rankdir));
+    dot.push_str(&format!(
+        "  node [shape=box, style=filled, fontname=\"{}\"];\n",
+        options.fontname
+    ));
+    dot.push_str("  edge [color=gray];\n\n");
+
+    // Helper function for file path matching
+    fn is_file_match(node_path: &str, requested_paths: &[String]) -> bool {
+        for path in requested_paths {
+            // Extract the filename from the provided file_path
+            let requested_filename = Path::new(path)
+                .file_name()
+                .and_then(|f| f.to_str())
+                .unwrap_or(path);
+
+            if node_path.ends_with(path)
+                || node_path == path
+                || node_path.contains(requested_filename)
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Find nodes that belong to any of the specified files
+    let file_nodes: Vec<&FunctionNode> = call_graph
+        .values()
+        .filter(|node| is_file_match(&node.file_path, file_paths))
+        .collect();
+
+    if file_nodes.is_empty() {
+        // List available paths that contain part of the requested paths
+        let mut matching_paths: HashSet<&String> = HashSet::new();
+        for path in file_paths {
+            for node in call_graph.values() {
+                if node.file_path.contains(path) {
+                    matching_paths.insert(&node.file_path);
+                }
+            }
+        }
+
+        if !matching_paths.is_empty() {
+            let mut message = format!(
+                "No exact matches for file paths: {:?}\n\nHere are some similar paths:\n",
+                file_paths
+            );
+            for path in matching_paths {
+                message.push_str(&format!("  {}\n", path));
+            }
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message));
+        }
+
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No functions found in file paths: {:?}", file_paths),
+        ));
+    }
+
+    info!(
+        "Found {} functions in the specified files",
+        file_nodes.len()
+    );
+
+    // Nodes connected to these files through calls in either direction, up to
+    // `neighbor_depth` edges away
+    let file_symbols: Vec<String> = file_nodes.iter().map(|n| n.symbol.clone()).collect();
+    let distances = bfs_distances(call_graph, &file_symbols, true, true, neighbor_depth);
+
+    // Group file nodes by their file path for subgraph clustering
+    let mut file_groups: BTreeMap<String, Vec<&FunctionNode>> = BTreeMap::new();
+    for node in &file_nodes {
+        file_groups
+            .entry(node.file_path.clone())
+            .or_default()
+            .push(node);
+    }
+
+    // Draw clusters for each file with blue background nodes
+    for (cluster_id, (file_path, nodes)) in file_groups.iter().enumerate() {
+        let file_label = Path::new(file_path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown");
+
+        dot.push_str(&format!("  subgraph cluster_{} {{\n", cluster_id));
+        dot.push_str(&format!("    label = \"{}\";\n", file_label));
+        dot.push_str("    style=filled;\n");
+        dot.push_str("    color=lightblue;\n");
+        dot.push_str(&format!("    fontname=\"{}\";\n", options.fontname));
+
+        for node in nodes {
+            let label = node.display_name.clone();
+            let tooltip = dot_tooltip(node.body.as_deref());
+            dot.push_str(&format!(
+                "    \"{}\" [label=\"{}\", tooltip=\"{}\", fillcolor=white{}]\n",
+                node.symbol,
+                label,
+                tooltip,
+                dot_node_attrs(node, options)
+            ));
+        }
+
+        dot.push_str("  }\n");
+    }
+
+    // Draw connected nodes, lighter the further they are from the files, and the edges
+    push_file_neighbors(&mut dot, call_graph, &distances, neighbor_depth, options);
+
+    dot.push_str("}\n");
+    writer.write_all(dot.as_bytes())
+}
+
+/// Generate a DOT file format for a subgraph of the call graph containing only specified functions and their transitive dependencies
+///
+/// Functions are selected with [`match_function_nodes`]; pass `exact` to avoid substring matches.
+pub fn generate_function_subgraph_dot(
+    call_graph: &HashMap<String, FunctionNode>,
+    function_names: &[String],
+    output_path: &str,
+    include_callers: bool,
+    exact: bool,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    // Render fully before touching `output_path`, so no file is created on error
+    let mut dot = Vec::new();
+    generate_function_subgraph_dot_to(
+        call_graph,
+        function_names,
+        &mut dot,
+        include_callers,
+        exact,
+        options,
+    )?;
+    std::fs::write(output_path, dot)
+}
+
+/// Like [`generate_function_subgraph_dot`], writing the DOT source to `writer`
+pub fn generate_function_subgraph_dot_to<W: Write>(
+    call_graph: &HashMap<String, FunctionNode>,
+    function_names: &[String],
+    writer: &mut W,
+    include_callers: bool,
+    exact: bool,
+    options: &DotOptions,
+) -> std::io::Result<()> {
+    use std::collections::{BTreeMap, HashSet};
+    let mut dot = String::from("digraph function_subgraph {\n");
+    dot.push_str(&format!("  rankdir={};\n", options.rankdir));
+    dot.push_str(&format!(
+        "  node [shape=box, style=filled, fontname=\"{}\"];\n",
+        options.fontname
+    ));
+    dot.push_str("  edge [color=gray];\n\n");
+
+    // Find nodes that match the specified function names
+    let matched_nodes = match_function_nodes(call_graph, function_names, exact);
+    let matched_symbols: HashSet<String> = matched_nodes.iter().map(|n| n.symbol.clone()).collect();
+
+    if matched_nodes.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!(
+                "No functions found matching the provided names: {:?}",
+                function_names
+            ),
+        ));
+    }
+
+    info!(
         "Found {} functions matching the provided names",
         matched_nodes.len()
     );
-    for node in &matched_nodes {
-        println!("  - {} ({})", node.display_name, node.symbol);
+    for node in &matched_nodes {
+        debug!(
+            "  - {} ({})",
+            symbol_to_path(&node.symbol, &node.display_name),
+            node.symbol
+        );
+    }
+
+    // Build the transitive closure of dependencies (and optionally of callers)
+    let seeds: Vec<String> = matched_symbols.iter().cloned().collect();
+    let mut included_symbols = transitive_callees(call_graph, &seeds);
+    if include_callers {
+        included_symbols.extend(transitive_callers(call_graph, &seeds));
+    }
+    let mut included_symbols: Vec<String> = included_symbols.into_iter().collect();
+    included_symbols.sort();
+
+    // Group nodes by file path for visual organization
+    let mut file_groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for symbol in &included_symbols {
+        if let Some(node) = call_graph.get(symbol) {
+            file_groups
+                .entry(node.file_path.clone())
+                .or_default()
+                .push(symbol.clone());
+        }
+    }
+
+    // Create clusters for each file
+    for (cluster_id, (file_path, symbols)) in file_groups.iter().enumerate() {
+        let file_label = Path::new(file_path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown");
+
+        dot.push_str(&format!("  subgraph cluster_{} {{\n", cluster_id));
+        dot.push_str(&format!("    label = \"{}\";\n", file_label));
+        dot.push_str("    style=filled;\n");
+        dot.push_str("    color=lightgrey;\n");
+        dot.push_str(&format!("    fontname=\"{}\";\n", options.fontname));
+
+        for symbol in symbols {
+            if let Some(node) = call_graph.get(symbol) {
+                let label = node.display_name.clone();
+                let tooltip = dot_tooltip(node.body.as_deref());
+
+                // Color the initially matched nodes differently
+                let fillcolor = if matched_symbols.contains(symbol) {
+                    "lightblue"
+                } else {
+                    "white"
+                };
+
+                dot.push_str(&format!(
+                    "    \"{}\" [label=\"{}\", tooltip=\"{}\", fillcolor={}{}]\n",
+                    node.symbol,
+                    label,
+                    tooltip,
+                    fillcolor,
+                    dot_node_attrs(node, options)
+                ));
+            }
+        }
+
+        dot.push_str("  }\n");
+    }
+
+    dot.push('\n');
+
+    // Draw edges between all included nodes
+    for symbol in &included_symbols {
+        if let Some(node) = call_graph.get(symbol) {
+            for callee in node.sorted_callees() {
+                if included_symbols.binary_search(callee).is_ok() {
+                    dot.push_str(&format!("  \"{}\" -> \"{}\"\n", node.symbol, callee));
+                }
+            }
+        }
+    }
+
+    dot.push_str("}\n");
+    writer.write_all(dot.as_bytes())
+}
+
+/// Seed for the node positions of [`generate_call_graph_svg`]
+pub const DEFAULT_LAYOUT_SEED: u64 = 0;
+
+/// Write a simple self-contained SVG rendering of the call graph.
+///
+/// Nodes have a radius of 40 pixels, or are scaled by body line count with `node_sizing`.
+/// Positions are drawn with [`DEFAULT_LAYOUT_SEED`], see
+/// [`generate_call_graph_svg_with_seed`].
+pub fn generate_call_graph_svg(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: &str,
+    node_sizing: Option<&NodeSizing>,
+) -> std::io::Result<()> {
+    generate_call_graph_svg_with_seed(call_graph, output_path, node_sizing, DEFAULT_LAYOUT_SEED)
+}
+
+/// Like [`generate_call_graph_svg`], with node positions drawn from a PRNG seeded with
+/// `seed`. The same graph and seed always give the same file, so a layout can be
+/// reproduced, or another seed tried when nodes overlap.
+pub fn generate_call_graph_svg_with_seed(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: &str,
+    node_sizing: Option<&NodeSizing>,
+    seed: u64,
+) -> std::io::Result<()> {
+    let layout = SvgLayout {
+        seed,
+        ..SvgLayout::default()
+    };
+    generate_call_graph_svg_with_layout(call_graph, output_path, node_sizing, &layout)
+}
+
+/// Default number of simulation steps of [`SvgLayout`]
+pub const DEFAULT_LAYOUT_ITERATIONS: usize = 200;
+
+/// Default node limit of [`SvgLayout`]
+pub const DEFAULT_LAYOUT_MAX_NODES: usize = 1000;
+
+/// Force-directed layout parameters for [`generate_call_graph_svg_with_layout`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SvgLayout {
+    /// Seed for the random initial positions
+    pub seed: u64,
+    /// Simulation steps; 0 keeps the initial positions
+    pub iterations: usize,
+    /// Graphs with more nodes keep their initial positions, since every step takes
+    /// time quadratic in the node count. Only SVG output uses it, counting the nodes
+    /// left after subtree, include or exclude filtering; no node is ever dropped.
+    /// Weighted-edge filtering is separate, see [`EdgeFilter`].
+    pub max_nodes: usize,
+}
+
+impl Default for SvgLayout {
+    fn default() -> Self {
+        SvgLayout {
+            seed: DEFAULT_LAYOUT_SEED,
+            iterations: DEFAULT_LAYOUT_ITERATIONS,
+            max_nodes: DEFAULT_LAYOUT_MAX_NODES,
+        }
+    }
+}
+
+/// Fruchterman-Reingold spring layout: every pair of nodes repels, nodes joined by
+/// an edge attract, and the distance a node may move per step cools linearly to zero.
+/// Positions start at `initial` and are returned in the same order, not yet fitted to
+/// the `width` x `height` viewport.
+fn spring_layout(
+    initial: &[(f64, f64)],
+    edges: &[(usize, usize)],
+    iterations: usize,
+    width: f64,
+    height: f64,
+) -> Vec<(f64, f64)> {
+    let n = initial.len();
+    let mut positions = initial.to_vec();
+    if n < 2 {
+        return positions;
+    }
+    // Ideal distance between nodes
+    let k = (width * height / n as f64).sqrt();
+    let initial_temperature = width / 10.0;
+    // Unit vector from `b` to `a` and the distance between them
+    let direction = |a: (f64, f64), b: (f64, f64)| {
+        let (dx, dy) = (a.0 - b.0, a.1 - b.1);
+        let distance = (dx * dx + dy * dy).sqrt().max(0.01);
+        (dx / distance, dy / distance, distance)
+    };
+
+    for step in 0..iterations {
+        let temperature = initial_temperature * (1.0 - step as f64 / iterations as f64);
+        let mut displacement = vec![(0.0, 0.0); n];
+        for i in 0..n {
+            for j in i + 1..n {
+                let (ux, uy, distance) = direction(positions[i], positions[j]);
+                let force = k * k / distance;
+                displacement[i].0 += ux * force;
+                displacement[i].1 += uy * force;
+                displacement[j].0 -= ux * force;
+                displacement[j].1 -= uy * force;
+            }
+        }
+        for &(a, b) in edges {
+            if a == b {
+                continue;
+            }
+            let (ux, uy, distance) = direction(positions[a], positions[b]);
+            let force = distance * distance / k;
+            displacement[a].0 -= ux * force;
+            displacement[a].1 -= uy * force;
+            displacement[b].0 += ux * force;
+            displacement[b].1 += uy * force;
+        }
+        for (position, (dx, dy)) in positions.iter_mut().zip(displacement) {
+            let length = (dx * dx + dy * dy).sqrt();
+            if length > 0.0 {
+                let moved = length.min(temperature);
+                position.0 += dx / length * moved;
+                position.1 += dy / length * moved;
+            }
+        }
+    }
+    positions
+}
+
+/// Scale and translate `positions` so they span the viewport minus `margin` on each
+/// side; a dimension with no extent is centered
+fn fit_to_viewport(positions: &mut [(f64, f64)], width: f64, height: f64, margin: f64) {
+    let fit = |values: Vec<&mut f64>, size: f64| {
+        let min = values.iter().map(|v| **v).fold(f64::INFINITY, f64::min);
+        let max = values.iter().map(|v| **v).fold(f64::NEG_INFINITY, f64::max);
+        let available = (size - 2.0 * margin).max(0.0);
+        for value in values {
+            *value = if max > min {
+                margin + (*value - min) / (max - min) * available
+            } else {
+                size / 2.0
+            };
+        }
+    };
+    fit(positions.iter_mut().map(|(x, _)| x).collect(), width);
+    fit(positions.iter_mut().map(|(_, y)| y).collect(), height);
+}
+
+/// Like [`generate_call_graph_svg`], with the force-directed layout configured by
+/// `layout`. Nodes sharing a symbol are drawn at the same position.
+pub fn generate_call_graph_svg_with_layout(
+    call_graph: &HashMap<String, FunctionNode>,
+    output_path: &str,
+    node_sizing: Option<&NodeSizing>,
+    layout: &SvgLayout,
+) -> std::io::Result<()> {
+    let node_radius = |node: &FunctionNode| node_sizing.map_or(40.0, |sizing| sizing.size(node));
+    let width = 1200;
+    let height = 800;
+    let mut svg = format!(
+        "<svg xmlns='http://www.w3.org/2000/svg' width='{}' height='{}' style='background:#fff;font-family:sans-serif'>\n",
+        width, height
+    );
+
+    // A simple spring layout; for complex graphs, use a dot file with Graphviz.
+    // Visit nodes in key order so that positions do not depend on hash map order.
+    let mut nodes: Vec<(&String, &FunctionNode)> = call_graph.iter().collect();
+    nodes.sort_by_key(|(key, _)| *key);
+
+    // Random initial positions, one per symbol
+    let mut rng = StdRng::seed_from_u64(layout.seed);
+    let mut index_of: HashMap<&str, usize> = HashMap::new();
+    let mut initial = Vec::new();
+    for (_, node) in &nodes {
+        index_of.entry(node.symbol.as_str()).or_insert_with(|| {
+            let x = rng.random_range(200..1000) as f64;
+            let y = rng.random_range(100..700) as f64;
+            initial.push((x, y));
+            initial.len() - 1
+        });
+    }
+
+    let mut positions = initial;
+    if positions.len() > layout.max_nodes {
+        warn!(
+            "Skipping the SVG layout simulation for {} nodes (limit {})",
+            positions.len(),
+            layout.max_nodes
+        );
+    } else if layout.iterations > 0 {
+        let edges: Vec<(usize, usize)> = nodes
+            .iter()
+            .flat_map(|(_, node)| {
+                let from = index_of[node.symbol.as_str()];
+                node.callees
+                    .iter()
+                    .filter_map(|callee| index_of.get(callee.as_str()))
+                    .map(move |&to| (from, to))
+            })
+            .collect();
+        positions = spring_layout(
+            &positions,
+            &edges,
+            layout.iterations,
+            width as f64,
+            height as f64,
+        );
+        let margin = nodes
+            .iter()
+            .map(|(_, node)| node_radius(node))
+            .fold(0.0, f64::max)
+            + 10.0;
+        fit_to_viewport(&mut positions, width as f64, height as f64, margin);
+    }
+    let position = |symbol: &str| {
+        let (x, y) = positions[index_of[symbol]];
+        // Round so the file does not carry float noise
+        ((x * 10.0).round() / 10.0, (y * 10.0).round() / 10.0)
+    };
+
+    // Arrows marker definition
+    svg.push_str("<defs><marker id='arrow' markerWidth='10' markerHeight='10' refX='10' refY='5' orient='auto' markerUnits='strokeWidth'><path d='M0,0 L10,5 L0,10 z' fill='#888'/></marker></defs>\n");
+
+    // Draw edges
+    for (_, node) in &nodes {
+        let (x1, y1) = position(&node.symbol);
+        for callee in node.sorted_callees() {
+            if index_of.contains_key(callee.as_str()) {
+                let (x2, y2) = position(callee);
+                svg.push_str(&format!(
+                    "<line x1='{}' y1='{}' x2='{}' y2='{}' stroke='#888' stroke-width='2' marker-end='url(#arrow)'/>\n",
+                    x1, y1, x2, y2
+                ));
+            }
+        }
+    }
+
+    // Draw nodes
+    for (_, node) in &nodes {
+        let (x, y) = position(&node.symbol);
+        let body = node
+            .body
+            .as_ref()
+            .map(|b| html_escape::encode_safe(b))
+            .unwrap_or_default();
+        svg.push_str(&format!(
+            "<g>\
+                <circle cx='{}' cy='{}' r='{}' fill='#4a90e2' stroke='#222' stroke-width='2'/>\
+                <text x='{}' y='{}' text-anchor='middle' alignment-baseline='middle' fill='#fff' font-size='14'>{}</text>\
+                <title>{}</title>\
+            </g>\n",
+            x,
+            y,
+            node_radius(node),
+            x,
+            y,
+            html_escape::encode_safe(&node.display_name),
+            body
+        ));
+    }
+
+    svg.push_str("</svg>\n");
+    fs::write(output_path, svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::NamedTempFile;
+
+    /// Check that DOT output is structurally sound: quoted strings are closed and
+    /// braces and brackets are balanced outside of them. When Graphviz is installed,
+    /// the output must also be accepted by `dot -Tcanon`.
+    fn validate_dot(dot: &str) -> Result<(), String> {
+        let mut closers = Vec::new();
+        let mut chars = dot.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => loop {
+                    match chars.next() {
+                        Some((_, '\\')) => {
+                            chars.next();
+                        }
+                        Some((_, '"')) => break,
+                        Some(_) => {}
+                        None => return Err(format!("string opened at byte {i} is not closed")),
+                    }
+                },
+                '{' => closers.push('}'),
+                '[' => closers.push(']'),
+                '}' | ']' if closers.pop() != Some(c) => {
+                    return Err(format!("unbalanced `{c}` at byte {i}"));
+                }
+                _ => {}
+            }
+        }
+        if let Some(closer) = closers.pop() {
+            return Err(format!("missing `{closer}` at end of input"));
+        }
+
+        let Ok(mut child) = std::process::Command::new("dot")
+            .arg("-Tcanon")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+        else {
+            // Graphviz is optional for the tests
+            return Ok(());
+        };
+        use std::io::Write as _;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(dot.as_bytes())
+            .map_err(|e| e.to_string())?;
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).into_owned())
+        }
+    }
+
+    /// Build a minimal node keyed by its symbol with the given callees
+    fn test_node(symbol: &str, callees: &[&str]) -> FunctionNode {
+        let display_name = symbol
+            .trim_end_matches("().")
+            .trim_end_matches('#')
+            .rsplit(['/', ' ', '#'])
+            .next()
+            .unwrap_or(symbol);
+        FunctionNode {
+            symbol: symbol.to_string(),
+            display_name: display_name.to_string(),
+            signature_text: format!("fn {}()", display_name),
+            file_path: "/tmp/test.rs".to_string(),
+            relative_path: "test.rs".to_string(),
+            callers: HashSet::new(),
+            callees: callees.iter().map(|c| c.to_string()).collect(),
+            range: vec![],
+            body: None,
+            kind: 17,
+            enclosing_range: None,
+            external_callees: HashSet::new(),
+            is_test: false,
+            visibility: Visibility::Public,
+            is_async: false,
+            is_unsafe: false,
+            is_const: false,
+            is_external: false,
+            enclosing: None,
+            edge_kinds: BTreeMap::new(),
+        }
+    }
+
+    /// Build a graph from `(symbol, callees)` pairs, filling in callers from the callees
+    fn test_graph(edges: &[(&str, &[&str])]) -> HashMap<String, FunctionNode> {
+        let mut call_graph: HashMap<String, FunctionNode> = edges
+            .iter()
+            .map(|(symbol, callees)| (symbol.to_string(), test_node(symbol, callees)))
+            .collect();
+        for (symbol, callees) in edges {
+            for callee in *callees {
+                if let Some(node) = call_graph.get_mut(*callee) {
+                    node.callers.insert(symbol.to_string());
+                }
+            }
+        }
+        call_graph
+    }
+
+    #[test]
+    fn test_call_graph_summary() {
+        let mut call_graph = test_graph(&[
+            ("crate/a().", &["crate/b().", "crate/c()."]),
+            ("crate/b().", &["crate/c()."]),
+            ("crate/c().", &[]),
+        ]);
+        call_graph.get_mut("crate/a().").unwrap().body = Some("fn a() {}".to_string());
+
+        let stats = call_graph_summary(&call_graph);
+        assert_eq!(stats.functions, 3);
+        assert_eq!(stats.edges, 3);
+        assert_eq!(stats.functions_with_bodies, 1);
+        let fan_out: Vec<usize> = stats.top_fan_out.iter().map(|(_, n)| *n).collect();
+        assert_eq!(fan_out, vec![2, 1, 0]);
+
+        let text = stats.to_string();
+        assert!(text.contains("Functions: 3"));
+        assert!(text.contains("Functions with bodies: 1/3"));
+    }
+
+    #[test]
+    fn test_function_body_extraction() {
+        let mut call_graph = HashMap::new();
+        call_graph.insert(
+            "f1".to_string(),
+            FunctionNode {
+                symbol: "f1".to_string(),
+                display_name: "foo".to_string(),
+                signature_text: "fn foo()".to_string(),
+                file_path: "/tmp/foo.rs".to_string(),
+                relative_path: "tmp/foo.rs".to_string(),
+                callers: HashSet::new(),
+                callees: HashSet::new(),
+                range: vec![],
+                body: Some("fn foo() { println!(\"Hello\"); }".to_string()),
+                kind: 17,
+                enclosing_range: None,
+                external_callees: HashSet::new(),
+                is_test: false,
+                visibility: Visibility::Public,
+                is_async: false,
+                is_unsafe: false,
+                is_const: false,
+                is_external: false,
+                enclosing: None,
+                edge_kinds: BTreeMap::new(),
+            },
+        );
+        let tmp = NamedTempFile::new().unwrap();
+        generate_call_graph_dot(
+            &call_graph,
+            tmp.path().to_str().unwrap(),
+            false,
+            &DotOptions::default(),
+        )
+        .unwrap();
+        let dot = fs::read_to_string(tmp.path()).unwrap();
+        // Note: quotes are replaced with "' " in the tooltip for DOT format safety
+        assert!(dot.contains("tooltip=\"fn foo() { println!(' Hello' ); }\""));
+        validate_dot(&dot).unwrap();
+    }
+
+    #[test]
+    fn test_call_graph_svg_output() {
+        let mut call_graph = test_graph(&[("crate/a().", &["crate/b()."]), ("crate/b().", &[])]);
+        call_graph.get_mut("crate/a().").unwrap().body = Some("fn a() { b() }".to_string());
+        let tmp = NamedTempFile::new().unwrap();
+        generate_call_graph_svg(&call_graph, tmp.path().to_str().unwrap(), None).unwrap();
+        let svg = fs::read_to_string(tmp.path()).unwrap();
+
+        let header = svg.lines().next().unwrap();
+        assert!(header.starts_with("<svg ") && header.ends_with("'>"));
+        assert!(!svg.contains("\\n"));
+        assert_eq!(svg.matches("<g>").count(), 2);
+        assert_eq!(svg.matches("</g>").count(), 2);
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_call_graph_plantuml() {
+        let mut call_graph = test_graph(&[
+            ("crate/a().", &["crate/b()."]),
+            ("crate/b().", &["crate/a()."]),
+            ("crate/c().", &[]),
+        ]);
+        let c = call_graph.get_mut("crate/c().").unwrap();
+        c.display_name = "say \"hi\"\\n".to_string();
+        c.file_path = "/tmp/util/c.rs".to_string();
+        c.relative_path = "util/c.rs".to_string();
+
+        let uml = call_graph_to_plantuml(&call_graph);
+        assert_eq!(
+            uml,
+            "@startuml\n\
+             package \"root\" {\n  component \"a\" as f0\n  component \"b\" as f1\n}\n\
+             package \"util\" {\n  component \"say 'hi'\\\\n\" as f2\n}\n\
+             f0 --> f1\nf1 --> f0\n\
+             @enduml\n"
+        );
+    }
+
+    #[test]
+    fn test_call_graph_d3_json() {
+        let mut call_graph = test_graph(&[
+            ("crate/main().", &["crate/util()."]),
+            ("crate/util().", &[]),
+        ]);
+        call_graph.get_mut("crate/util().").unwrap().file_path = "/tmp/util/util.rs".to_string();
+        // A second definition of `main` (e.g. another impl) calling `util` too
+        let mut other_main = test_node("crate/main().", &["crate/util()."]);
+        other_main.signature_text = "fn main(x: u8)".to_string();
+        call_graph.insert("crate/main().|fn main(x: u8)".to_string(), other_main);
+
+        let tmp = NamedTempFile::new().unwrap();
+        generate_call_graph_d3_json(&call_graph, tmp.path()).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(tmp.path()).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "nodes": [
+                    {"id": "crate/main().", "name": "main", "group": 0},
+                    {"id": "crate/util().", "name": "util", "group": 1},
+                ],
+                "links": [
+                    {"source": "crate/main().", "target": "crate/util().", "value": 2},
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_call_graph_cypher() {
+        assert_eq!(cypher_string("it's a\\b\n"), "'it\\'s a\\\\b\\n'");
+
+        let call_graph = test_graph(&[
+            ("crate/a().", &["crate/b().", "crate/c()."]),
+            ("crate/b().", &[]),
+            ("crate/c().", &["crate/a()."]),
+        ]);
+        let cypher = cypher_script(&call_graph, 2);
+        let statements: Vec<&str> = cypher.split_terminator(";\n").collect();
+        // Constraint, two node batches (2 + 1) and two edge batches (2 + 1)
+        assert_eq!(statements.len(), 5);
+        assert!(statements[0].starts_with("CREATE CONSTRAINT"));
+        assert!(statements[1]
+            .contains("{symbol: 'crate/a().', name: 'a', path: 'crate/a', file: 'test.rs'}"));
+        assert!(statements[2].starts_with("UNWIND [\n  {symbol: 'crate/c().'"));
+        assert!(statements[3].contains(
+            "{from: 'crate/a().', to: 'crate/b().'},\n  {from: 'crate/a().', to: 'crate/c().'}"
+        ));
+        assert!(statements[4].contains("{from: 'crate/c().', to: 'crate/a().'}"));
+        assert!(statements[4].ends_with("MERGE (a)-[:CALLS]->(b)"));
+    }
+
+    #[test]
+    fn test_sorted_edges() {
+        let edges: &[(&str, &[&str])] = &[
+            ("crate/main().", &["crate/c().", "crate/a().", "crate/b()."]),
+            ("crate/a().", &[]),
+            ("crate/b().", &["crate/a()."]),
+            ("crate/c().", &["crate/a()."]),
+        ];
+        let call_graph = test_graph(edges);
+        let main = &call_graph["crate/main()."];
+        assert_eq!(
+            main.sorted_callees(),
+            vec!["crate/a().", "crate/b().", "crate/c()."]
+        );
+        assert_eq!(
+            call_graph["crate/a()."].sorted_callers(),
+            vec!["crate/b().", "crate/c().", "crate/main()."]
+        );
+
+        // Fresh maps iterate in different orders, the DOT output must not
+        let dot = call_graph_to_dot(&call_graph, false, &DotOptions::default());
+        for _ in 0..5 {
+            assert_eq!(
+                call_graph_to_dot(&test_graph(edges), false, &DotOptions::default()),
+                dot
+            );
+        }
+        let main_edges: Vec<&str> = dot
+            .lines()
+            .filter(|line| line.starts_with("  \"crate/main().\" ->"))
+            .collect();
+        assert_eq!(
+            main_edges,
+            vec![
+                "  \"crate/main().\" -> \"crate/a().\"",
+                "  \"crate/main().\" -> \"crate/b().\"",
+                "  \"crate/main().\" -> \"crate/c().\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spring_layout() {
+        // A triangle and two isolated nodes, starting on a line
+        let initial: Vec<(f64, f64)> = (0..5).map(|i| (100.0 + i as f64 * 50.0, 400.0)).collect();
+        let edges = [(0, 1), (1, 2), (2, 0)];
+        let mut positions = spring_layout(&initial, &edges, 200, 1200.0, 800.0);
+        let distance =
+            |a: (f64, f64), b: (f64, f64)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+        let linked = distance(positions[0], positions[1]);
+        assert!(linked < distance(positions[0], positions[3]));
+        assert!(linked < distance(positions[3], positions[4]));
+
+        fit_to_viewport(&mut positions, 1200.0, 800.0, 50.0);
+        assert!(positions
+            .iter()
+            .all(|&(x, y)| (50.0..=1150.0).contains(&x) && (50.0..=750.0).contains(&y)));
+        assert_eq!(
+            spring_layout(&initial[..1], &[], 200, 1200.0, 800.0),
+            initial[..1]
+        );
+    }
+
+    #[test]
+    fn test_svg_layout_seed() {
+        let edges: &[(&str, &[&str])] = &[
+            ("crate/a().", &["crate/b().", "crate/c()."]),
+            ("crate/b().", &["crate/c()."]),
+            ("crate/c().", &[]),
+        ];
+        let render = |seed: u64| {
+            // A fresh map per render, so iteration order differs between calls
+            let tmp = NamedTempFile::new().unwrap();
+            let path = tmp.path().to_str().unwrap();
+            generate_call_graph_svg_with_seed(&test_graph(edges), path, None, seed).unwrap();
+            fs::read_to_string(tmp.path()).unwrap()
+        };
+        assert_eq!(render(7), render(7));
+        assert_ne!(render(7), render(8));
+        assert_eq!(render(DEFAULT_LAYOUT_SEED), {
+            let tmp = NamedTempFile::new().unwrap();
+            generate_call_graph_svg(&test_graph(edges), tmp.path().to_str().unwrap(), None)
+                .unwrap();
+            fs::read_to_string(tmp.path()).unwrap()
+        });
+    }
+
+    #[test]
+    fn test_dot_tooltip_escaping() {
+        let mut call_graph = test_graph(&[("crate/cmp().", &[])]);
+        call_graph.get_mut("crate/cmp().").unwrap().body =
+            Some("fn cmp() -> bool { a < b && c > d }".to_string());
+        let tmp = NamedTempFile::new().unwrap();
+        generate_call_graph_dot(
+            &call_graph,
+            tmp.path().to_str().unwrap(),
+            false,
+            &DotOptions::default(),
+        )
+        .unwrap();
+        let dot = fs::read_to_string(tmp.path()).unwrap();
+        assert!(dot.contains("tooltip=\"fn cmp() -&gt; bool { a &lt; b &amp;&amp; c &gt; d }\""));
+        validate_dot(&dot).unwrap();
+    }
+
+    #[test]
+    fn test_validate_dot() {
+        let mut call_graph = test_graph(&[("crate/a().", &["crate/b()."]), ("crate/b().", &[])]);
+        call_graph.get_mut("crate/a().").unwrap().body =
+            Some("fn a() { let s = \"{\\\"}\"; b() }\n// ends in \\".to_string());
+        call_graph.get_mut("crate/b().").unwrap().body = Some("fn b() { '[' }".to_string());
+        for highlight_cycles in [false, true] {
+            let dot = call_graph_to_dot(&call_graph, highlight_cycles, &DotOptions::default());
+            validate_dot(&dot).unwrap();
+        }
+        let summary =
+            module_summary_dot(&call_graph, &EdgeFilter::default(), &DotOptions::default());
+        validate_dot(&summary).unwrap();
+
+        assert!(validate_dot("digraph { \"a\" -> \"b }").is_err());
+        assert!(validate_dot("digraph { \"a\\\" -> b }").is_err());
+        assert!(validate_dot("digraph { a [label=\"x\"; }").is_err());
+        assert!(validate_dot("digraph { a } }").is_err());
+    }
+
+    #[test]
+    fn test_brace_counting_adjacent_one_line_functions() {
+        let lines = vec![
+            "fn a() -> u32 { 1 }",
+            "fn b() -> u32 { 2 }",
+            "",
+            "fn c() {}",
+        ];
+        assert_eq!(
+            extract_body_with_brace_counting(&lines, 0, "\n"),
+            "fn a() -> u32 { 1 }"
+        );
+        assert_eq!(
+            extract_body_with_brace_counting(&lines, 1, "\n"),
+            "fn b() -> u32 { 2 }"
+        );
+        assert_eq!(
+            extract_body_with_brace_counting(&lines, 3, "\n"),
+            "fn c() {}"
+        );
+
+        // CRLF sources keep their line endings
+        let contents = "fn d() {\r\n    4\r\n}\r\n";
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(
+            extract_body_with_brace_counting(&lines, 0, line_ending(contents)),
+            "fn d() {\r\n    4\r\n}"
+        );
+    }
+
+    #[test]
+    fn test_symbol_to_path_with_digits_in_package() {
+        assert_eq!(
+            symbol_to_path(
+                "rust-analyzer cargo curve25519-dalek 4.1.3 montgomery/MontgomeryPoint#ct_eq().",
+                "ct_eq"
+            ),
+            "montgomery/MontgomeryPoint/ct_eq"
+        );
+        assert_eq!(
+            symbol_to_path(
+                "rust-analyzer cargo sha2 0.10.8 sha256/compress256().",
+                "compress256"
+            ),
+            "sha256/compress256"
+        );
+        assert_eq!(
+            symbol_to_path(
+                "rust-analyzer cargo base64 0.22.1 engine/Engine#encode().",
+                "encode"
+            ),
+            "engine/Engine/encode"
+        );
+        // Escaped space inside the package name
+        assert_eq!(
+            symbol_to_path("rust-analyzer cargo my  crate 1.0.0 util/run().", "run"),
+            "util/run"
+        );
+        // Not a full SCIP symbol: used as-is
+        assert_eq!(symbol_to_path("crate/a().", "a"), "crate/a");
+    }
+
+    #[test]
+    fn test_find_by_display_name_and_path() {
+        let call_graph = test_graph(&[
+            ("rust-analyzer cargo c 0.1.0 a/Foo#new().", &[]),
+            ("rust-analyzer cargo c 0.1.0 b/Bar#new().", &[]),
+            ("rust-analyzer cargo c 0.1.0 b/renew().", &[]),
+        ]);
+        let found: Vec<_> = find_by_display_name(&call_graph, "new")
+            .iter()
+            .map(|n| symbol_to_path(&n.symbol, &n.display_name))
+            .collect();
+        assert_eq!(found, vec!["a/Foo/new", "b/Bar/new"]);
+        assert!(find_by_display_name(&call_graph, "ne").is_empty());
+
+        let node = find_by_path(&call_graph, "b/Bar/new").unwrap();
+        assert_eq!(node.symbol, "rust-analyzer cargo c 0.1.0 b/Bar#new().");
+        assert!(find_by_path(&call_graph, "Bar/new").is_none());
+    }
+
+    #[test]
+    fn test_symbol_to_code_name() {
+        // Test basic conversion
+        let symbol =
+            "rust-analyzer cargo curve25519-dalek 4.1.3 montgomery/MontgomeryPoint#ct_eq().";
+        let code_name = symbol_to_code_name(symbol, "ct_eq", None);
+        assert_eq!(
+            code_name,
+            "probe:curve25519-dalek/4.1.3/montgomery/MontgomeryPoint#ct_eq()"
+        );
+
+        // Test with trait method and type disambiguation
+        let symbol = "rust-analyzer cargo curve25519-dalek 4.1.3 montgomery/Mul#mul().";
+        let signature = "fn mul(self, scalar: &Scalar) -> MontgomeryPoint";
+        let code_name = symbol_to_code_name(symbol, "mul", Some(signature));
+        assert_eq!(
+            code_name,
+            "probe:curve25519-dalek/4.1.3/montgomery/Mul<Scalar>#mul()"
+        );
+
+        // Test simple function (no trait)
+        let symbol = "rust-analyzer cargo mylib 0.1.0 utils/helper().";
+        let code_name = symbol_to_code_name(symbol, "helper", None);
+        assert_eq!(code_name, "probe:mylib/0.1.0/utils/helper()");
+    }
+
+    #[test]
+    fn test_find_cycles() {
+        let call_graph =
+            test_graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a", "d"]), ("d", &[])]);
+        let cycles = find_cycles(&call_graph);
+        assert_eq!(cycles, vec![vec!["a", "b", "c"]]);
+
+        let tmp = NamedTempFile::new().unwrap();
+        generate_call_graph_dot(
+            &call_graph,
+            tmp.path().to_str().unwrap(),
+            true,
+            &DotOptions::default(),
+        )
+        .unwrap();
+        let dot = fs::read_to_string(tmp.path()).unwrap();
+        assert!(dot.contains("\"a\" -> \"b\" [color=red]"));
+        assert!(dot.contains("\"c\" -> \"a\" [color=red]"));
+        assert!(dot.contains("\"c\" -> \"d\"\n"));
+    }
+
+    #[test]
+    fn test_find_cycles_long_chain() {
+        // Deep enough to overflow the stack of a recursive search
+        let names: Vec<String> = (0..50_000).map(|i| format!("f{i:05}")).collect();
+        let callees: Vec<[&str; 1]> = (0..names.len())
+            .map(|i| [names[(i + 1) % names.len()].as_str()])
+            .collect();
+        let edges: Vec<(&str, &[&str])> = names
+            .iter()
+            .zip(&callees)
+            .map(|(name, callees)| (name.as_str(), &callees[..]))
+            .collect();
+        let cycles = find_cycles(&test_graph(&edges));
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].len(), names.len());
+    }
+
+    #[test]
+    fn test_atom_scc_ids() {
+        let call_graph = test_graph(&[
+            ("is_even", &["is_odd"]),
+            ("is_odd", &["is_even"]),
+            ("fact", &["fact"]),
+            ("main", &["is_even", "fact"]),
+        ]);
+        let atoms = call_graph_to_atoms(&call_graph);
+        let scc_id = |name: &str| {
+            atoms
+                .iter()
+                .find(|a| a.display_name == name)
+                .unwrap()
+                .scc_id
+        };
+        assert!(scc_id("is_even").is_some());
+        assert_eq!(scc_id("is_even"), scc_id("is_odd"));
+        assert!(scc_id("fact").is_some());
+        assert_ne!(scc_id("fact"), scc_id("is_even"));
+        assert_eq!(scc_id("main"), None);
+    }
+
+    #[test]
+    fn test_find_dead_functions() {
+        let call_graph = test_graph(&[
+            ("main", &["used"]),
+            ("used", &[]),
+            ("unused", &["used"]),
+            ("handler", &[]),
+            // Only called by itself
+            ("fib", &["fib"]),
+        ]);
+        assert_eq!(call_graph["fib"].callers.len(), 1);
+
+        let dead: Vec<_> = find_dead_functions(&call_graph, DEFAULT_ROOTS)
+            .iter()
+            .map(|n| n.symbol.as_str())
+            .collect();
+        assert_eq!(dead, vec!["fib", "handler", "unused"]);
+
+        let dead: Vec<_> = find_dead_functions(&call_graph, &["main", "handler"])
+            .iter()
+            .map(|n| n.symbol.as_str())
+            .collect();
+        assert_eq!(dead, vec!["fib", "unused"]);
+    }
+
+    #[test]
+    fn test_find_entry_points() {
+        let mut call_graph = test_graph(&[
+            ("main", &["helper"]),
+            ("helper", &[]),
+            ("api", &["helper"]),
+            ("check_helper", &["helper"]),
+        ]);
+        call_graph.get_mut("check_helper").unwrap().body =
+            Some("#[test]\nfn check_helper() { helper(); }".to_string());
+
+        let roots: Vec<_> = find_entry_points(&call_graph, false)
+            .iter()
+            .map(|n| n.symbol.as_str())
+            .collect();
+        assert_eq!(roots, vec!["api", "check_helper", "main"]);
+
+        let roots: Vec<_> = find_entry_points(&call_graph, true)
+            .iter()
+            .map(|n| n.symbol.as_str())
+            .collect();
+        assert_eq!(roots, vec!["check_helper", "main"]);
+    }
+
+    #[test]
+    fn test_transitive_closures() {
+        // Diamond: top -> left, right -> bottom
+        let call_graph = test_graph(&[
+            ("top", &["left", "right"]),
+            ("left", &["bottom"]),
+            ("right", &["bottom"]),
+            ("bottom", &[]),
+            ("other", &["right"]),
+        ]);
+        let set = |symbols: &[&str]| -> HashSet<String> {
+            symbols.iter().map(|s| s.to_string()).collect()
+        };
+
+        assert_eq!(
+            transitive_callees(&call_graph, &["top".to_string()]),
+            set(&["top", "left", "right", "bottom"])
+        );
+        assert_eq!(
+            transitive_callees(&call_graph, &["right".to_string()]),
+            set(&["right", "bottom"])
+        );
+        assert_eq!(
+            transitive_callers(&call_graph, &["bottom".to_string()]),
+            set(&["bottom", "left", "right", "top", "other"])
+        );
+        assert_eq!(
+            transitive_callers(&call_graph, &["left".to_string()]),
+            set(&["left", "top"])
+        );
+    }
+
+    #[test]
+    fn test_retain_by_glob() {
+        let main = "rust-analyzer cargo app 0.1.0 main().";
+        let sign = "rust-analyzer cargo app 0.1.0 crypto/sign().";
+        let hash = "rust-analyzer cargo app 0.1.0 crypto/hash/hash().";
+        let mut index = test_index("file:///app", "src/main.rs", &[(main, 0)], &[(sign, 1)]);
+        index.documents.extend(
+            test_index(
+                "file:///app",
+                "src/crypto/mod.rs",
+                &[(sign, 0)],
+                &[(hash, 1)],
+            )
+            .documents,
+        );
+        index
+            .documents
+            .extend(test_index("file:///app", "src/crypto/hash.rs", &[(hash, 0)], &[]).documents);
+        let call_graph = build_call_graph_edges_only(&index);
+
+        let all = retain_by_glob(&call_graph, &["**/*.rs".to_string()]).unwrap();
+        assert_eq!(all, call_graph);
+
+        let crypto = retain_by_glob(&call_graph, &["src/crypto/**".to_string()]).unwrap();
+        let mut symbols: Vec<&str> = crypto.values().map(|n| n.symbol.as_str()).collect();
+        symbols.sort();
+        assert_eq!(symbols, vec![hash, sign]);
+        let sign_node = crypto.values().find(|n| n.symbol == sign).unwrap();
+        assert!(sign_node.callers.is_empty());
+        assert_eq!(sign_node.callees, HashSet::from([hash.to_string()]));
+
+        let without = exclude_by_glob(&call_graph, &["src/crypto/hash.rs".to_string()]).unwrap();
+        assert_eq!(without.len(), 2);
+        assert!(without
+            .values()
+            .all(|n| n.callees.iter().all(|c| c != hash)));
+
+        assert!(retain_by_glob(&call_graph, &["src/[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_retain_subtree() {
+        let main = "rust-analyzer cargo app 0.1.0 main().";
+        let send = "rust-analyzer cargo app 0.1.0 protocol/send().";
+        let encode = "rust-analyzer cargo app 0.1.0 protocol/encode().";
+        let log = "rust-analyzer cargo app 0.1.0 log().";
+        let mut index = test_index("file:///app", "src/main.rs", &[(main, 0)], &[(send, 1)]);
+        for (path, functions, calls) in [
+            (
+                "src/protocol/send.rs",
+                &[(send, 0)][..],
+                &[(encode, 1), (log, 2)][..],
+            ),
+            ("src/protocol/encode.rs", &[(encode, 0)][..], &[][..]),
+            ("src/protocol_old.rs", &[(log, 0)][..], &[][..]),
+        ] {
+            index
+                .documents
+                .extend(test_index("file:///app", path, functions, calls).documents);
+        }
+        let call_graph = build_call_graph_edges_only(&index);
+
+        let subtree = retain_subtree(&call_graph, "./src/protocol/", false);
+        let mut symbols: Vec<&str> = subtree.values().map(|n| n.symbol.as_str()).collect();
+        symbols.sort();
+        assert_eq!(symbols, vec![encode, send]);
+        let send_node = subtree.values().find(|n| n.symbol == send).unwrap();
+        assert!(send_node.callers.is_empty());
+        assert_eq!(send_node.callees, HashSet::from([encode.to_string()]));
+        assert!(send_node.external_callees.is_empty());
+
+        let subtree = retain_subtree(&call_graph, "src/protocol", true);
+        let send_node = subtree.values().find(|n| n.symbol == send).unwrap();
+        assert_eq!(send_node.callees, HashSet::from([encode.to_string()]));
+        assert_eq!(send_node.external_callees, HashSet::from([log.to_string()]));
+
+        assert_eq!(retain_subtree(&call_graph, "", false), call_graph);
+    }
+
+    #[test]
+    fn test_reachable_from() {
+        let main = "rust-analyzer cargo demo 0.1.0 main().";
+        let helper = "rust-analyzer cargo demo 0.1.0 helper().";
+        let unused = "rust-analyzer cargo demo 0.1.0 unused().";
+        let call_graph = test_graph(&[(main, &[helper]), (helper, &[]), (unused, &[helper])]);
+        let expected: HashSet<String> = [main, helper].iter().map(|s| s.to_string()).collect();
+
+        assert_eq!(reachable_from(&call_graph, &[main.to_string()]), expected);
+        let identifier = "probe:demo/0.1.0/main()".to_string();
+        assert_eq!(reachable_from(&call_graph, &[identifier]), expected);
+        assert!(reachable_from(&call_graph, &["missing".to_string()]).is_empty());
+
+        let pruned = retain_reachable(&call_graph, &[main.to_string()]);
+        assert_eq!(pruned.len(), 2);
+        assert!(!pruned.contains_key(unused));
+        let callers: Vec<&String> = pruned[helper].callers.iter().collect();
+        assert_eq!(callers, vec![main]);
+    }
+
+    #[test]
+    fn test_degree_metrics() {
+        let mut call_graph = test_graph(&[
+            ("hub", &["a", "b", "c"]),
+            ("a", &["c"]),
+            ("b", &["c"]),
+            ("c", &[]),
+        ]);
+        call_graph.get_mut("c").unwrap().body = Some("fn c() {\n    1\n}".to_string());
+
+        let metrics = compute_degree_metrics(&call_graph);
+        assert_eq!(metrics["hub"], (0, 3));
+        assert_eq!(metrics["c"], (3, 0));
+
+        let tmp = NamedTempFile::new().unwrap();
+        write_metrics_json(&call_graph, tmp.path()).unwrap();
+        let written: Vec<FunctionMetrics> =
+            serde_json::from_str(&fs::read_to_string(tmp.path()).unwrap()).unwrap();
+        let symbols: Vec<_> = written.iter().map(|m| m.symbol.as_str()).collect();
+        assert_eq!(symbols, vec!["a", "b", "c", "hub"]);
+        assert_eq!(written[2].body_lines, 3);
+
+        // Nodes sharing a symbol get the same numbers from both
+        let mut other_a = call_graph["a"].clone();
+        other_a.signature_text = "fn a(x: u8)".to_string();
+        other_a.callees.clear();
+        call_graph.insert("a#2".to_string(), other_a);
+        let metrics = compute_degree_metrics(&call_graph);
+        assert_eq!((metrics["a"], metrics["a#2"]), ((1, 1), (1, 0)));
+        write_metrics_json(&call_graph, tmp.path()).unwrap();
+        let written: Vec<FunctionMetrics> =
+            serde_json::from_str(&fs::read_to_string(tmp.path()).unwrap()).unwrap();
+        let fan_out: Vec<_> = written
+            .iter()
+            .map(|m| (m.symbol.as_str(), m.fan_out))
+            .collect();
+        assert_eq!(fan_out[..2], [("a", 1), ("a", 0)]);
+    }
+
+    #[test]
+    fn test_body_size_histogram() {
+        let mut call_graph = test_graph(&[("a", &[]), ("b", &[]), ("c", &[]), ("d", &[])]);
+        call_graph.get_mut("b").unwrap().body = Some("fn b() {}".to_string());
+        call_graph.get_mut("c").unwrap().body = Some("fn c() {\n".repeat(12));
+        call_graph.get_mut("d").unwrap().kind = i32::from(SymbolKind::Struct);
+
+        assert_eq!(
+            body_size_histogram(&call_graph, &[10, 0, 1]),
+            vec![(0, 1), (1, 1), (10, 1)]
+        );
+        assert_eq!(body_size_histogram(&call_graph, &[5]), vec![(5, 1)]);
+
+        let tmp = NamedTempFile::new().unwrap();
+        write_histogram_json(&call_graph, &[0, 10], tmp.path()).unwrap();
+        let written: Vec<HistogramBucket> =
+            serde_json::from_str(&fs::read_to_string(tmp.path()).unwrap()).unwrap();
+        assert_eq!(written[0].max_lines, Some(10));
+        assert_eq!(written[0].functions, 2);
+        assert_eq!(written[1].max_lines, None);
+    }
+
+    #[test]
+    fn test_find_large_functions() {
+        let mut call_graph = test_graph(&[("a", &[]), ("b", &[]), ("c", &[]), ("d", &[])]);
+        call_graph.get_mut("a").unwrap().body = Some("line\n".repeat(5));
+        call_graph.get_mut("b").unwrap().body = Some("line\n".repeat(20));
+        call_graph.get_mut("c").unwrap().body = Some("line\n".repeat(3));
+
+        let large: Vec<(&str, usize)> = find_large_functions(&call_graph, 3)
+            .into_iter()
+            .map(|(node, lines)| (node.symbol.as_str(), lines))
+            .collect();
+        assert_eq!(large, vec![("b", 20), ("a", 5)]);
+        assert!(find_large_functions(&call_graph, 100).is_empty());
+    }
+
+    #[test]
+    fn test_compute_pagerank() {
+        let call_graph = test_graph(&[
+            ("a", &["util"]),
+            ("b", &["util"]),
+            ("c", &["util", "b"]),
+            ("util", &[]),
+        ]);
+        let ranks = compute_pagerank(
+            &call_graph,
+            DEFAULT_PAGERANK_DAMPING,
+            DEFAULT_PAGERANK_ITERATIONS,
+        );
+
+        let total: f64 = ranks.values().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!(ranks["util"] > ranks["b"]);
+        assert!(ranks["b"] > ranks["a"]);
+        assert!((ranks["a"] - ranks["c"]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_diff_call_graphs() {
+        let old = test_graph(&[
+            (
+                "rust-analyzer cargo app 0.1.0 main().",
+                &["rust-analyzer cargo app 0.1.0 a()."],
+            ),
+            (
+                "rust-analyzer cargo app 0.1.0 a().",
+                &["rust-analyzer cargo app 0.1.0 b()."],
+            ),
+            ("rust-analyzer cargo app 0.1.0 b().", &[]),
+        ]);
+        let new = test_graph(&[
+            (
+                "rust-analyzer cargo app 0.2.0 main().",
+                &[
+                    "rust-analyzer cargo app 0.2.0 a().",
+                    "rust-analyzer cargo app 0.2.0 c().",
+                ],
+            ),
+            ("rust-analyzer cargo app 0.2.0 a().", &[]),
+            ("rust-analyzer cargo app 0.2.0 b().", &[]),
+            ("rust-analyzer cargo app 0.2.0 c().", &[]),
+        ]);
+        let path = |name: &str| {
+            symbol_to_path(&format!("rust-analyzer cargo app 0.1.0 {}().", name), name)
+        };
+
+        let diff = diff_call_graphs(&old, &new);
+        assert_eq!(diff.added_functions, vec![path("c")]);
+        assert!(diff.removed_functions.is_empty());
+        assert_eq!(diff.added_edges, vec![(path("main"), path("c"))]);
+        assert_eq!(diff.removed_edges, vec![(path("a"), path("b"))]);
+        assert!(diff_call_graphs(&old, &old).is_empty());
+    }
+
+    /// Build a one-document SCIP index defining `functions` (name, line) and
+    /// referencing `calls` (symbol, line) from within them
+    fn test_index(
+        root: &str,
+        path: &str,
+        functions: &[(&str, i32)],
+        calls: &[(&str, i32)],
+    ) -> ScipIndex {
+        let mut occurrences = Vec::new();
+        let mut symbols = Vec::new();
+        for (symbol, line) in functions {
+            occurrences.push(Occurrence {
+                range: Range::from_scip(&[*line, 3, 6]).unwrap(),
+                symbol: symbol.to_string(),
+                symbol_roles: Some(1),
+                enclosing_range: None,
+            });
+            symbols.push(Symbol {
+                symbol: symbol.to_string(),
+                kind: 17,
+                display_name: Some(test_node(symbol, &[]).display_name),
+                documentation: None,
+                signature_documentation: SignatureDocumentation {
+                    language: "rust".to_string(),
+                    text: format!("fn {}()", symbol),
+                    position_encoding: 1,
+                },
+                enclosing_symbol: None,
+                relationships: Vec::new(),
+            });
+        }
+        for (symbol, line) in calls {
+            occurrences.push(Occurrence {
+                range: Range::from_scip(&[*line, 4, 7]).unwrap(),
+                symbol: symbol.to_string(),
+                symbol_roles: None,
+                enclosing_range: None,
+            });
+        }
+        ScipIndex {
+            external_symbols: Vec::new(),
+            metadata: Metadata {
+                tool_info: ToolInfo {
+                    name: "rust-analyzer".to_string(),
+                    version: "0.0.0".to_string(),
+                },
+                project_root: root.to_string(),
+                text_document_encoding: 1,
+            },
+            documents: vec![Document {
+                language: "rust".to_string(),
+                relative_path: path.to_string(),
+                occurrences,
+                symbols,
+                position_encoding: 1,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_duplicate_symbols_keyed_by_range() {
+        // `impl Neg for &Scalar` and `impl Neg for Scalar` can share symbol and signature
+        let neg = "rust-analyzer cargo crate 0.1.0 scalar/Neg#neg().";
+        let run = "rust-analyzer cargo crate 0.1.0 run().";
+        let index = test_index(
+            "file:///nonexistent",
+            "src/lib.rs",
+            &[(neg, 0), (neg, 2), (run, 4)],
+            &[(neg, 5)],
+        );
+        let call_graph = build_call_graph(&index);
+        assert_eq!(call_graph.len(), 3);
+
+        let mut neg_lines: Vec<i32> = call_graph
+            .values()
+            .filter(|n| n.symbol == neg)
+            .map(|n| n.range[0])
+            .collect();
+        neg_lines.sort();
+        assert_eq!(neg_lines, vec![0, 2]);
+        assert!(call_graph
+            .values()
+            .filter(|n| n.symbol == neg)
+            .all(|n| n.callers.len() == 1));
+
+        // Single-definition symbols keep the plain symbol|signature key
+        let run_key = make_unique_key(run, &format!("fn {}()", run));
+        assert!(call_graph[&run_key].callees.contains(neg));
+    }
+
+    #[test]
+    fn test_recursive_self_edge() {
+        let fib = "rust-analyzer cargo crate 0.1.0 fib().";
+        let index = test_index(
+            "file:///nonexistent",
+            "src/lib.rs",
+            &[(fib, 0)],
+            &[(fib, 1)],
+        );
+        let call_graph = build_call_graph(&index);
+        let node = call_graph.values().next().unwrap();
+        assert!(node.callees.contains(fib));
+        assert_eq!(node.callers.len(), 1);
+
+        assert_eq!(find_cycles(&call_graph), vec![vec![fib.to_string()]]);
+        assert_eq!(find_entry_points(&call_graph, false).len(), 1);
+
+        let tmp = NamedTempFile::new().unwrap();
+        generate_call_graph_dot(
+            &call_graph,
+            tmp.path().to_str().unwrap(),
+            false,
+            &DotOptions::default(),
+        )
+        .unwrap();
+        let dot = fs::read_to_string(tmp.path()).unwrap();
+        assert!(dot.contains(&format!("\"{fib}\" -> \"{fib}\"")));
+        validate_dot(&dot).unwrap();
+    }
+
+    #[test]
+    fn test_file_uri_project_root() {
+        assert_eq!(
+            normalize_project_root("file:///home/user/proj"),
+            "/home/user/proj"
+        );
+        assert_eq!(
+            normalize_project_root("file:///home/user/proj/"),
+            "/home/user/proj"
+        );
+        assert_eq!(normalize_project_root("file:///C:/proj"), "C:/proj");
+        assert_eq!(normalize_project_root("/plain/path"), "/plain/path");
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "fn alpha() -> u32 {\n    1\n}\n",
+        )
+        .unwrap();
+        let alpha = "rust-analyzer cargo crate 0.1.0 alpha().";
+        let root = format!("file://{}/", dir.path().display());
+        let call_graph = build_call_graph(&test_index(&root, "src/lib.rs", &[(alpha, 0)], &[]));
+
+        let node = call_graph.values().next().unwrap();
+        assert!(!node.file_path.starts_with("file:"));
+        assert_eq!(node.body.as_deref(), Some("fn alpha() -> u32 {\n    1\n}"));
+    }
+
+    #[test]
+    fn test_build_call_graph_reports_unreadable_files() {
+        let alpha = "rust-analyzer cargo crate 0.1.0 alpha().";
+        let index = test_index("/nonexistent/root", "src/lib.rs", &[(alpha, 0)], &[]);
+        let (call_graph, warnings) = build_call_graph_with_report(&index, DEFAULT_MATCH_TOLERANCE);
+
+        assert_eq!(call_graph.len(), 1);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("/nonexistent/root/src/lib.rs"));
+    }
+
+    #[test]
+    fn test_windows_relative_paths() {
+        let alpha = "rust-analyzer cargo crate 0.1.0 field/alpha().";
+        let index = test_index("file:///C:/proj", "src\\field\\mod.rs", &[(alpha, 0)], &[]);
+        let call_graph = build_call_graph(&index);
+        let node = call_graph.values().next().unwrap();
+        assert_eq!(node.relative_path, "src/field/mod.rs");
+        assert_eq!(node.file_path, "C:/proj/src/field/mod.rs");
+
+        let mut windows_node = node.clone();
+        windows_node.file_path = "C:\\proj\\src\\field\\mod.rs".to_string();
+        windows_node.relative_path = "src\\field\\mod.rs".to_string();
+        let atoms = call_graph_to_atoms(&HashMap::from([(alpha.to_string(), windows_node)]));
+        assert_eq!(atoms[0].full_path, "C:/proj/src/field/mod.rs");
+        assert_eq!(atoms[0].relative_path, "src/field/mod.rs");
+        assert_eq!(atoms[0].file_name, "mod.rs");
+        assert_eq!(atoms[0].parent_folder, "field");
+        assert_eq!(atoms[0].parent_dir, "src/field");
+    }
+
+    #[test]
+    fn test_relative_paths_normalized() {
+        let alpha = "rust-analyzer cargo crate 0.1.0 field/alpha().";
+        let index = test_index("file:///proj", "./src//field/./mod.rs", &[(alpha, 0)], &[]);
+        let atoms = call_graph_to_atoms(&build_call_graph_edges_only(&index));
+        assert_eq!(atoms[0].relative_path, "src/field/mod.rs");
+        assert_eq!(atoms[0].full_path, "/proj/src/field/mod.rs");
+        assert_eq!(atoms[0].file_name, "mod.rs");
+        assert_eq!(atoms[0].parent_folder, "field");
+    }
+
+    #[test]
+    fn test_atom_parent_dir() {
+        let alpha = "rust-analyzer cargo crate 0.1.0 alpha().";
+        for (relative_path, parent_folder, parent_dir) in [
+            ("lib.rs", "proj", ""),
+            ("src/lib.rs", "src", "src"),
+            ("a/b/c.rs", "b", "a/b"),
+        ] {
+            let index = test_index("file:///work/proj", relative_path, &[(alpha, 0)], &[]);
+            let atoms = call_graph_to_atoms(&build_call_graph_edges_only(&index));
+            assert_eq!(atoms[0].parent_folder, parent_folder, "{relative_path}");
+            assert_eq!(atoms[0].parent_dir, parent_dir, "{relative_path}");
+        }
+    }
+
+    #[test]
+    fn test_atom_deps_deduplicated_and_sorted() {
+        let caller = "rust-analyzer cargo geo 0.1.0 main().";
+        let zeta = "rust-analyzer cargo geo 0.1.0 zeta().";
+        // Both render as `probe:geo/0.1.0/util/f()`
+        let f_nested = "rust-analyzer cargo geo 0.1.0 util/f().";
+        let f_flat = "rust-analyzer cargo geo 0.1.0/util/f().";
+        let call_graph = test_graph(&[
+            (caller, &[zeta, f_nested, f_flat]),
+            (zeta, &[]),
+            (f_nested, &[]),
+            (f_flat, &[]),
+        ]);
+
+        let atoms = call_graph_to_atoms(&call_graph);
+        let main_atom = atoms.iter().find(|a| a.display_name == "main").unwrap();
+        assert_eq!(
+            main_atom.deps,
+            vec!["probe:geo/0.1.0/util/f()", "probe:geo/0.1.0/zeta()"]
+        );
+    }
+
+    #[test]
+    fn test_external_callees_in_deps() {
+        let run = "rust-analyzer cargo app 0.1.0 run().";
+        let push = "rust-analyzer cargo std 1.0.0 vec/Vec#push().";
+        let vec_type = "rust-analyzer cargo std 1.0.0 vec/Vec#";
+        let index = test_index(
+            "/app",
+            "src/lib.rs",
+            &[(run, 0)],
+            &[(push, 1), (vec_type, 2)],
+        );
+        let call_graph = build_call_graph(&index);
+        let node = call_graph.values().next().unwrap();
+        assert!(node.callees.is_empty());
+        assert_eq!(node.external_callees, HashSet::from([push.to_string()]));
+
+        assert!(call_graph_to_atoms(&call_graph)[0].deps.is_empty());
+        assert_eq!(
+            call_graph_to_atoms_with_external(&call_graph)[0].deps,
+            vec!["external:vec/Vec/push"]
+        );
+    }
+
+    #[test]
+    fn test_external_symbol_stubs() {
+        let run = "rust-analyzer cargo app 0.1.0 run().";
+        let push = "rust-analyzer cargo std 1.0.0 vec/Vec#push().";
+        let pop = "rust-analyzer cargo std 1.0.0 vec/Vec#pop().";
+        let mut index = test_index("/app", "src/lib.rs", &[(run, 0)], &[(push, 1)]);
+        let mut json = serde_json::to_value(&index).unwrap();
+        json.as_object_mut().unwrap().remove("external_symbols");
+        assert!(serde_json::from_value::<ScipIndex>(json)
+            .unwrap()
+            .external_symbols
+            .is_empty());
+
+        let external = test_index("/std", "src/vec.rs", &[(push, 0), (pop, 1)], &[]);
+        index.external_symbols = external.documents[0].symbols.clone();
+        let call_graph = build_call_graph(&index);
+        assert_eq!(call_graph.len(), 2);
+        let stub = call_graph.values().find(|n| n.symbol == push).unwrap();
+        assert!(stub.is_external);
+        assert!(stub.body.is_none());
+        assert_eq!(stub.callers.len(), 1);
+
+        let run_node = call_graph.values().find(|n| n.symbol == run).unwrap();
+        assert_eq!(run_node.callees, HashSet::from([push.to_string()]));
+        assert!(run_node.external_callees.is_empty());
+        let atoms = call_graph_to_atoms(&call_graph);
+        let run_atom = atoms.iter().find(|a| a.display_name == "run").unwrap();
+        assert_eq!(run_atom.deps.len(), 1);
+    }
+
+    #[test]
+    fn test_symbol_module_path() {
+        assert_eq!(
+            symbol_module_path(
+                "rust-analyzer cargo curve25519-dalek 4.1.3 backend/serial/u64/field/FieldElement51#add()."
+            ),
+            "curve25519_dalek::backend::serial::u64::field"
+        );
+        assert_eq!(
+            symbol_module_path("rust-analyzer cargo geo 0.1.0 scalar/montgomery/mul()."),
+            "geo::scalar::montgomery"
+        );
+        assert_eq!(
+            symbol_module_path("rust-analyzer cargo geo 0.1.0 `r#type`/inner/f()."),
+            "geo::r#type::inner"
+        );
+        assert_eq!(
+            symbol_module_path("rust-analyzer cargo geo 0.1.0 main()."),
+            "geo"
+        );
+        assert_eq!(symbol_module_path("local 12"), "");
+
+        let call_graph = test_graph(&[("rust-analyzer cargo geo 0.1.0 a/b/c().", &[])]);
+        assert_eq!(call_graph_to_atoms(&call_graph)[0].module_path, "geo::a::b");
+    }
+
+    #[test]
+    fn test_build_call_graph_edges_only() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "fn alpha() {\n    beta();\n}\nfn beta() {}\n",
+        )
+        .unwrap();
+        let alpha = "rust-analyzer cargo crate 0.1.0 alpha().";
+        let beta = "rust-analyzer cargo crate 0.1.0 beta().";
+        let root = dir.path().display().to_string();
+        let index = test_index(&root, "src/lib.rs", &[(alpha, 0), (beta, 3)], &[(beta, 1)]);
+
+        let full = build_call_graph(&index);
+        assert!(full.values().all(|n| n.body.is_some()));
+
+        let edges_only = build_call_graph_edges_only(&index);
+        assert!(edges_only.values().all(|n| n.body.is_none()));
+        for (key, node) in &edges_only {
+            assert_eq!(node.callees, full[key].callees);
+            assert_eq!(node.callers, full[key].callers);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_key_keeps_first_node() {
+        let alpha = "rust-analyzer cargo crate 0.1.0 alpha().";
+        let mut index = test_index("/nonexistent", "src/lib.rs", &[(alpha, 0)], &[]);
+        let mut second = index.documents[0].symbols[0].clone();
+        second.display_name = Some("second".to_string());
+        index.documents[0].symbols.push(second);
+
+        let options = BuildOptions {
+            extract_bodies: false,
+            ..BuildOptions::default()
+        };
+        let (call_graph, warnings) = build_call_graph_with_options(&index, &options);
+        assert_eq!(call_graph.len(), 1);
+        assert_eq!(call_graph.values().next().unwrap().display_name, "alpha");
+        assert!(warnings
+            .iter()
+            .any(|w| w.starts_with("1 symbol(s) had the same key")));
+    }
+
+    #[test]
+    fn test_build_item_macros() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "my_items! {\npub fn wrapped() {}\n}\n",
+        )
+        .unwrap();
+        let wrapped = "rust-analyzer cargo crate 0.1.0 wrapped().";
+        let root = dir.path().display().to_string();
+        let index = test_index(&root, "src/lib.rs", &[(wrapped, 1)], &[]);
+
+        // The visibility comes from the parsed span, so it shows whether the macro
+        // body was parsed
+        let visibility = |item_macros: &[&str]| {
+            let options = BuildOptions {
+                item_macros: item_macros.iter().map(|m| m.to_string()).collect(),
+                ..BuildOptions::default()
+            };
+            let (call_graph, _) = build_call_graph_with_options(&index, &options);
+            call_graph.values().next().unwrap().visibility
+        };
+        assert_eq!(visibility(&["verus"]), Visibility::Private);
+        assert_eq!(visibility(&["verus", "my_items"]), Visibility::Public);
+    }
+
+    #[test]
+    fn test_build_progress_callback() {
+        let alpha = "rust-analyzer cargo crate 0.1.0 alpha().";
+        let beta = "rust-analyzer cargo crate 0.1.0 beta().";
+        let index = test_index("/nonexistent", "src/lib.rs", &[(alpha, 0), (beta, 3)], &[]);
+        let mut events = Vec::new();
+        build_call_graph_with_progress(&index, &BuildOptions::default(), |phase, done, total| {
+            events.push((phase, done, total))
+        });
+
+        let finished: Vec<_> = events
+            .iter()
+            .filter(|(_, done, total)| done == total)
+            .collect();
+        assert_eq!(
+            finished,
+            vec![
+                &(BuildPhase::SymbolCollection, 1, 1),
+                &(BuildPhase::NodeCreation, 1, 1),
+                &(BuildPhase::EdgeBuilding, 1, 1),
+                &(BuildPhase::BodyExtraction, 2, 2),
+            ]
+        );
+        assert_eq!(events.first(), Some(&(BuildPhase::SymbolCollection, 0, 1)));
+    }
+
+    #[test]
+    fn test_local_symbols() {
+        assert_eq!(symbol_to_path("local 0", ""), "<local:0>");
+        assert_eq!(
+            symbol_to_path("local 12", "callback"),
+            "<local:callback:12>"
+        );
+
+        // The same local id in two documents must not become (colliding) nodes
+        let run = "rust-analyzer cargo app 0.1.0 run().";
+        let mut index = test_index("/app", "src/a.rs", &[(run, 0), ("local 0", 1)], &[]);
+        let other = test_index("/app", "src/b.rs", &[("local 0", 1)], &[]);
+        index.documents.extend(other.documents);
+        let call_graph = build_call_graph(&index);
+        assert_eq!(call_graph.len(), 1);
+        assert!(call_graph.values().all(|n| n.symbol == run));
+    }
+
+    #[test]
+    fn test_generate_per_module_dot() {
+        let mut call_graph = test_graph(&[
+            ("crate/a().", &["crate/b().", "crate/c()."]),
+            ("crate/b().", &[]),
+            ("crate/c().", &[]),
+        ]);
+        let node = call_graph.get_mut("crate/c().").unwrap();
+        node.file_path = "/proj/src/util/c.rs".to_string();
+        node.relative_path = "src/util/c.rs".to_string();
+        for symbol in ["crate/a().", "crate/b()."] {
+            let node = call_graph.get_mut(symbol).unwrap();
+            node.file_path = "/proj/src/lib.rs".to_string();
+            node.relative_path = "src/lib.rs".to_string();
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let written =
+            generate_per_module_dot(&call_graph, dir.path(), &DotOptions::default()).unwrap();
+        assert_eq!(
+            written,
+            vec![dir.path().join("src.dot"), dir.path().join("src_util.dot")]
+        );
+
+        let src = fs::read_to_string(&written[0]).unwrap();
+        assert!(src.contains("\"crate/a().\" -> \"crate/b().\""));
+        assert!(src.contains("\"crate/a().\" -> \"crate/c().\""));
+        assert!(
+            src.contains("\"crate/c().\" [label=\"c\", tooltip=\"/proj/src/util\", style=dashed")
+        );
+        let util = fs::read_to_string(&written[1]).unwrap();
+        assert!(util.contains("\"crate/c().\" [label="));
+        validate_dot(&src).unwrap();
+        validate_dot(&util).unwrap();
+        assert!(!util.contains("->"));
+
+        // Directories that flatten to the same name do not overwrite each other
+        let mut call_graph = test_graph(&[("crate/x().", &[]), ("crate/y().", &[])]);
+        for (symbol, dir) in [("crate/x().", "src/a_b"), ("crate/y().", "src/a/b")] {
+            let node = call_graph.get_mut(symbol).unwrap();
+            node.file_path = format!("/proj/{dir}/m.rs");
+            node.relative_path = format!("{dir}/m.rs");
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let written =
+            generate_per_module_dot(&call_graph, dir.path(), &DotOptions::default()).unwrap();
+        assert_eq!(
+            written,
+            vec![
+                dir.path().join("src_a_b.dot"),
+                dir.path().join("src_a_b_2.dot")
+            ]
+        );
+        let first = fs::read_to_string(&written[0]).unwrap();
+        let second = fs::read_to_string(&written[1]).unwrap();
+        assert!(first.contains("\"crate/y().\" [label="));
+        assert!(second.contains("\"crate/x().\" [label="));
+    }
+
+    #[test]
+    fn test_generate_per_crate_dot() {
+        let app = "rust-analyzer cargo my-app 0.1.0 main().";
+        let helper = "rust-analyzer cargo my-app 0.1.0 util/helper().";
+        let lib = "rust-analyzer cargo core-lib 0.2.0 api/compute().";
+        let call_graph = test_graph(&[(app, &[helper, lib]), (helper, &[]), (lib, &[])]);
+
+        let crates: BTreeMap<String, String> = call_graph_to_atoms(&call_graph)
+            .into_iter()
+            .map(|atom| (atom.display_name, atom.crate_name))
+            .collect();
+        assert_eq!(crates["main"], "my_app");
+        assert_eq!(crates["compute"], "core_lib");
+
+        let dir = tempfile::tempdir().unwrap();
+        let written =
+            generate_per_crate_dot(&call_graph, dir.path(), &DotOptions::default()).unwrap();
+        assert_eq!(
+            written,
+            vec![
+                dir.path().join("core_lib.dot"),
+                dir.path().join("my_app.dot")
+            ]
+        );
+
+        let app_dot = fs::read_to_string(&written[1]).unwrap();
+        assert!(app_dot.contains(&format!("\"{app}\" -> \"{helper}\"")));
+        assert!(app_dot.contains(&format!("\"{app}\" -> \"{lib}\"")));
+        assert!(app_dot.contains(&format!(
+            "\"{lib}\" [label=\"compute\\n(core_lib)\", style=dashed"
+        )));
+        let lib_dot = fs::read_to_string(&written[0]).unwrap();
+        assert!(lib_dot.contains(&format!("\"{lib}\" [label=")));
+        assert!(!lib_dot.contains("->"));
+        // The crate label and every module cluster use the configured font
+        assert!(app_dot.contains("  fontname=\"Helvetica\";\n"));
+        assert_eq!(
+            app_dot.matches("subgraph cluster_").count(),
+            app_dot.matches("    fontname=\"Helvetica\";\n").count()
+        );
+        validate_dot(&app_dot).unwrap();
+        validate_dot(&lib_dot).unwrap();
+    }
+
+    #[test]
+    fn test_public_api_subgraph() {
+        let mut call_graph = test_graph(&[
+            ("crate/api().", &["crate/helper().", "crate/other_api()."]),
+            ("crate/helper().", &["crate/other_api()."]),
+            ("crate/other_api().", &[]),
+            ("crate/internal().", &[]),
+        ]);
+        call_graph.get_mut("crate/helper().").unwrap().visibility = Visibility::Private;
+        call_graph.get_mut("crate/internal().").unwrap().visibility = Visibility::Crate;
+
+        let mut out = Vec::new();
+        let count =
+            generate_public_api_subgraph_dot_to(&call_graph, &mut out, &DotOptions::default())
+                .unwrap();
+        assert_eq!(count, 2);
+        let dot = String::from_utf8(out).unwrap();
+        assert!(dot.contains("\"crate/api().\" -> \"crate/other_api().\""));
+        assert!(!dot.contains("helper"));
+        assert!(!dot.contains("internal"));
+    }
+
+    #[test]
+    fn test_render_dot_rejects_unknown_format() {
+        let err = render_dot_to_image("graph.dot", "graph.gif", "gif").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("png, svg, pdf"));
+    }
+
+    #[test]
+    fn test_module_summary_dot() {
+        let mut call_graph = test_graph(&[
+            ("crate/a().", &["crate/b().", "crate/c().", "crate/d()."]),
+            ("crate/b().", &["crate/c()."]),
+            ("crate/c().", &["crate/d()."]),
+            ("crate/d().", &[]),
+        ]);
+        for (symbol, relative) in [
+            ("crate/a().", "src/lib.rs"),
+            ("crate/b().", "src/lib.rs"),
+            ("crate/c().", "src/util/c.rs"),
+            ("crate/d().", "src/util/d.rs"),
+        ] {
+            let node = call_graph.get_mut(symbol).unwrap();
+            node.file_path = format!("/proj/{relative}");
+            node.relative_path = relative.to_string();
+        }
+
+        let dot = module_summary_dot(&call_graph, &EdgeFilter::default(), &DotOptions::default());
+        assert!(dot.contains("\"/proj/src\" [label=\"src (2)\""));
+        assert!(dot.contains("\"/proj/src/util\" [label=\"src/util (2)\""));
+        // a -> c, a -> d and b -> c cross modules; c -> d stays inside src/util
+        assert!(dot.contains("\"/proj/src\" -> \"/proj/src/util\" [label=\"3\", weight=3]"));
+        assert_eq!(dot.matches("->").count(), 1);
+
+        let filter = EdgeFilter {
+            min_edge_weight: 4,
+            drop_isolated: false,
+        };
+        let dot = module_summary_dot(&call_graph, &filter, &DotOptions::default());
+        assert!(!dot.contains("->"));
+        assert!(dot.contains("src (2)"));
+
+        let filter = EdgeFilter {
+            min_edge_weight: 4,
+            drop_isolated: true,
+        };
+        assert!(
+            !module_summary_dot(&call_graph, &filter, &DotOptions::default()).contains("src (2)")
+        );
+    }
+
+    #[test]
+    fn test_dot_url_template() {
+        let mut call_graph = test_graph(&[("crate/a().", &["crate/b()."]), ("crate/b().", &[])]);
+        call_graph.get_mut("crate/a().").unwrap().range = vec![41, 3, 9];
+
+        let dot = call_graph_to_dot(&call_graph, false, &DotOptions::default());
+        assert!(!dot.contains("URL="));
+
+        let options = DotOptions {
+            url_template: Some("https://github.com/org/repo/blob/main/{path}#L{line}".to_string()),
+            ..Default::default()
+        };
+        let dot = call_graph_to_dot(&call_graph, false, &options);
+        assert!(dot.contains("URL=\"https://github.com/org/repo/blob/main/test.rs#L42\""));
+        assert!(dot.contains("URL=\"https://github.com/org/repo/blob/main/test.rs#L1\""));
+        validate_dot(&dot).unwrap();
+    }
+
+    #[test]
+    fn test_dot_to_writer() {
+        let call_graph = test_graph(&[("crate/a().", &["crate/b()."]), ("crate/b().", &[])]);
+        let options = DotOptions::default();
+        let tmp = NamedTempFile::new().unwrap();
+        let output = tmp.path().to_str().unwrap();
+
+        let mut written = Vec::new();
+        generate_call_graph_dot_to(&call_graph, &mut written, true, &options).unwrap();
+        generate_call_graph_dot(&call_graph, output, true, &options).unwrap();
+        assert_eq!(written, fs::read(output).unwrap());
+
+        let mut written = Vec::new();
+        generate_file_subgraph_dot_to(&call_graph, "test.rs", &mut written, 1, &options).unwrap();
+        generate_file_subgraph_dot(&call_graph, "test.rs", output, 1, &options).unwrap();
+        assert_eq!(written, fs::read(output).unwrap());
+
+        // Nothing is written when no function matches
+        let missing = vec!["nope".to_string()];
+        let mut written = Vec::new();
+        assert!(generate_function_subgraph_dot_to(
+            &call_graph,
+            &missing,
+            &mut written,
+            false,
+            true,
+            &options
+        )
+        .is_err());
+        assert!(written.is_empty());
+
+        // Progress goes to the log, so every writer only receives DOT
+        let files = vec!["test.rs".to_string()];
+        let names = vec!["a".to_string()];
+        let mut outputs = vec![Vec::new(), Vec::new(), Vec::new()];
+        generate_file_subgraph_dot_to(&call_graph, "test.rs", &mut outputs[0], 1, &options)
+            .unwrap();
+        generate_files_subgraph_dot_to(&call_graph, &files, &mut outputs[1], 1, &options).unwrap();
+        generate_function_subgraph_dot_to(
+            &call_graph,
+            &names,
+            &mut outputs[2],
+            true,
+            false,
+            &options,
+        )
+        .unwrap();
+        for output in outputs {
+            let dot = String::from_utf8(output).unwrap();
+            assert!(dot.starts_with("digraph "), "{dot}");
+            assert!(!dot.contains("Found "));
+            validate_dot(&dot).unwrap();
+        }
     }
 
-    // Build the transitive closure of dependencies
-    let mut included_symbols = matched_symbols.clone();
-    let mut queue = VecDeque::new();
-    for symbol in &matched_symbols {
-        queue.push_back(symbol.clone());
+    #[test]
+    fn test_dot_layout_options() {
+        let call_graph = test_graph(&[("crate/a().", &["crate/b()."]), ("crate/b().", &[])]);
+        let dot = call_graph_to_dot(&call_graph, false, &DotOptions::default());
+        assert!(dot.contains("  rankdir=LR;\n"));
+        assert!(dot.contains("fontname=\"Helvetica\""));
+
+        let options = DotOptions {
+            rankdir: "TB".to_string(),
+            fontname: "Courier".to_string(),
+            ..Default::default()
+        };
+        let tmp = NamedTempFile::new().unwrap();
+        let output = tmp.path().to_str().unwrap();
+        let names = vec!["crate/a().".to_string()];
+        generate_function_subgraph_dot(&call_graph, &names, output, false, true, &options).unwrap();
+        let dot = fs::read_to_string(output).unwrap();
+        assert!(dot.contains("  rankdir=TB;\n"));
+        assert!(dot.contains("    fontname=\"Courier\";\n"));
+        validate_dot(&dot).unwrap();
+        assert!(!dot.contains("Helvetica"));
+
+        let summary = module_summary_dot(&call_graph, &EdgeFilter::default(), &options);
+        assert!(summary.contains(&format!("  rankdir={};\n", options.rankdir)));
+        assert!(summary.contains(&format!("fontname=\"{}\"", options.fontname)));
+        validate_dot(&summary).unwrap();
+        assert!(!summary.contains("Helvetica"));
     }
 
-    // BFS to find all transitive dependencies
-    while let Some(symbol) = queue.pop_front() {
-        if let Some(node) = call_graph.get(&symbol) {
-            // Add callees (dependencies)
-            for callee in &node.callees {
-                if !included_symbols.contains(callee) {
-                    included_symbols.insert(callee.clone());
-                    queue.push_back(callee.clone());
-                }
-            }
+    #[test]
+    fn test_node_sizing() {
+        let mut call_graph = test_graph(&[("crate/a().", &["crate/b()."]), ("crate/b().", &[])]);
+        call_graph.get_mut("crate/a().").unwrap().body = Some("fn a() {\n    b()\n}".to_string());
+        let sizing = NodeSizing {
+            min: 1.0,
+            max: 3.0,
+            max_lines: 6,
+        };
+        assert_eq!(sizing.size(&call_graph["crate/a()."]), 2.0);
+        assert_eq!(sizing.size(&call_graph["crate/b()."]), 1.0);
 
-            // Optionally include callers as well
-            if include_callers {
-                for caller in &node.callers {
-                    if !included_symbols.contains(caller) {
-                        included_symbols.insert(caller.clone());
-                        queue.push_back(caller.clone());
-                    }
-                }
+        let options = DotOptions {
+            node_sizing: Some(sizing),
+            ..Default::default()
+        };
+        let dot = call_graph_to_dot(&call_graph, false, &options);
+        assert!(dot.contains("width=2.00]"));
+        assert!(dot.contains("width=1.00]"));
+
+        let tmp = NamedTempFile::new().unwrap();
+        generate_call_graph_svg(&call_graph, tmp.path().to_str().unwrap(), Some(&sizing)).unwrap();
+        let svg = fs::read_to_string(tmp.path()).unwrap();
+        assert!(svg.contains("r='2'"));
+        assert!(svg.contains("r='1'"));
+    }
+
+    #[test]
+    fn test_match_function_nodes_exact() {
+        let call_graph = test_graph(&[
+            ("rust-analyzer cargo demo 0.1.0 point/Point#new().", &[]),
+            ("rust-analyzer cargo demo 0.1.0 point/Point#renew().", &[]),
+            ("rust-analyzer cargo demo 0.1.0 line/Line#new().", &[]),
+        ]);
+        let names = |name: &str| vec![name.to_string()];
+
+        assert_eq!(
+            match_function_nodes(&call_graph, &names("new"), false).len(),
+            3
+        );
+        assert_eq!(
+            match_function_nodes(&call_graph, &names("new"), true).len(),
+            2
+        );
+
+        let matched = match_function_nodes(&call_graph, &names("point/Point/new"), true);
+        assert_eq!(matched.len(), 1);
+        assert_eq!(
+            matched[0].symbol,
+            "rust-analyzer cargo demo 0.1.0 point/Point#new()."
+        );
+        assert!(match_function_nodes(&call_graph, &names("Point/new"), true).is_empty());
+    }
+
+    #[test]
+    fn test_file_subgraph_neighbor_depth() {
+        let mut call_graph = test_graph(&[
+            ("crate/a().", &["crate/b()."]),
+            ("crate/b().", &["crate/c()."]),
+            ("crate/c().", &["crate/d()."]),
+            ("crate/d().", &[]),
+        ]);
+        call_graph.get_mut("crate/a().").unwrap().file_path = "/proj/src/seed.rs".to_string();
+        let tmp = NamedTempFile::new().unwrap();
+        let output = tmp.path().to_str().unwrap();
+
+        generate_file_subgraph_dot(&call_graph, "seed.rs", output, 1, &DotOptions::default())
+            .unwrap();
+        let dot = fs::read_to_string(output).unwrap();
+        assert!(dot.contains("\"crate/b().\" [label=\"b\", fillcolor=lightgray]"));
+        assert!(!dot.contains("\"crate/c().\""));
+        assert_eq!(dot.matches("->").count(), 1);
+
+        generate_files_subgraph_dot(
+            &call_graph,
+            &["seed.rs".to_string()],
+            output,
+            2,
+            &DotOptions::default(),
+        )
+        .unwrap();
+        let dot = fs::read_to_string(output).unwrap();
+        assert!(dot.contains("\"crate/c().\" [label=\"c\", fillcolor=gainsboro]"));
+        assert!(dot.contains("\"crate/b().\" -> \"crate/c().\""));
+        assert!(!dot.contains("\"crate/d().\""));
+        validate_dot(&dot).unwrap();
+    }
+
+    #[test]
+    fn test_scip_range() {
+        let single: Range = serde_json::from_str("[3, 4, 9]").unwrap();
+        assert_eq!(
+            single,
+            Range {
+                start_line: 3,
+                start_col: 4,
+                end_line: 3,
+                end_col: 9
             }
-        }
+        );
+        let multi: Range = serde_json::from_str("[3, 0, 5, 1]").unwrap();
+        assert_eq!((multi.end_line, multi.end_col), (5, 1));
+        assert_eq!(serde_json::to_string(&single).unwrap(), "[3,4,9]");
+        assert_eq!(serde_json::to_string(&multi).unwrap(), "[3,0,5,1]");
+        assert!(serde_json::from_str::<Range>("[1, 2]").is_err());
+
+        let content = "// header\nfn beta() {\n    2\n}\nfn gamma() {}\n";
+        let enclosing = Range::from_scip(&[1, 0, 3, 1]).unwrap();
+        assert_eq!(enclosing.extract(content), "fn beta() {\n    2\n}");
     }
 
-    // Group nodes by file path for visual organization
-    let mut file_groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
-    for symbol in &included_symbols {
-        if let Some(node) = call_graph.get(symbol) {
-            file_groups
-                .entry(node.file_path.clone())
-                .or_default()
-                .push(symbol.clone());
-        }
+    #[test]
+    fn test_save_and_load_call_graph() {
+        let mut call_graph = test_graph(&[("a", &["b", "c"]), ("b", &["c"]), ("c", &[])]);
+        let a = call_graph.get_mut("a").unwrap();
+        a.body = Some("fn a() { b(); c() }".to_string());
+        a.range = vec![1, 3, 4];
+        a.enclosing_range = Range::from_scip(&[1, 0, 3, 1]);
+
+        let tmp = NamedTempFile::new().unwrap();
+        save_call_graph(&call_graph, tmp.path()).unwrap();
+        let saved = fs::read_to_string(tmp.path()).unwrap();
+        assert!(saved.contains("\"callees\":[\"b\",\"c\"]"));
+
+        let reloaded = load_call_graph(tmp.path()).unwrap();
+        assert_eq!(reloaded, call_graph);
     }
 
-    // Create clusters for each file
-    for (cluster_id, (file_path, symbols)) in file_groups.iter().enumerate() {
-        let file_label = Path::new(file_path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown");
+    #[test]
+    fn test_build_call_graph_from_indexes() {
+        let helper = "rust-analyzer cargo crate_a 0.1.0 helper().";
+        let run = "rust-analyzer cargo crate_b 0.1.0 run().";
+        let index_a = test_index("file:///ws/crate_a", "src/lib.rs", &[(helper, 0)], &[]);
+        let index_b = test_index(
+            "file:///ws/crate_b",
+            "src/lib.rs",
+            &[(run, 0)],
+            &[(helper, 1)],
+        );
+        // crate_b's index without crate_a's symbol table cannot resolve the call
+        let index_b_alone = build_call_graph(&index_b);
+        assert!(index_b_alone.values().all(|n| n.callees.is_empty()));
+
+        let merged = merge_scip_indexes(&[index_a, index_b]).unwrap();
+        assert_eq!(merged.metadata.project_root, "file:///ws");
+        let paths: Vec<_> = merged
+            .documents
+            .iter()
+            .map(|d| d.relative_path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["crate_a/src/lib.rs", "crate_b/src/lib.rs"]);
+
+        let call_graph = build_call_graph(&merged);
+        let run_node = call_graph.values().find(|n| n.symbol == run).unwrap();
+        assert!(run_node.callees.contains(helper));
+        let helper_node = call_graph.values().find(|n| n.symbol == helper).unwrap();
+        assert_eq!(helper_node.relative_path, "crate_a/src/lib.rs");
+
+        // Roots given as a URI and as a plain path still rebase onto their common root
+        let index_a = test_index("file:///ws/crate_a/", "src/lib.rs", &[(helper, 0)], &[]);
+        let index_b = test_index("/ws/crate_b", "src/lib.rs", &[(run, 0)], &[]);
+        let merged = merge_scip_indexes(&[index_a, index_b]).unwrap();
+        assert_eq!(merged.metadata.project_root, "file:///ws");
+        let paths: Vec<_> = merged
+            .documents
+            .iter()
+            .map(|d| d.relative_path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["crate_a/src/lib.rs", "crate_b/src/lib.rs"]);
+    }
 
-        dot.push_str(&format!("  subgraph cluster_{} {{\n", cluster_id));
-        dot.push_str(&format!("    label = \"{}\";\n", file_label));
-        dot.push_str("    style=filled;\n");
-        dot.push_str("    color=lightgrey;\n");
-        dot.push_str("    fontname=Helvetica;\n");
+    #[test]
+    fn test_common_project_root() {
+        assert_eq!(
+            common_project_root(&["file:///ws/a", "/ws/b/"]),
+            "file:///ws"
+        );
+        assert_eq!(common_project_root(&["/ws/a", "file:///ws/a/b"]), "/ws/a");
+        // A shared string prefix is not a shared directory
+        assert_eq!(common_project_root(&["/a/foo", "/a/foobar"]), "/a");
+        assert_eq!(
+            common_project_root(&["file:///a/foo", "file:///a/foobar"]),
+            "file:///a"
+        );
+        assert_eq!(common_project_root(&["/ws"]), "/ws");
+        assert_eq!(common_project_root(&[]), "");
+    }
 
-        for symbol in symbols {
-            if let Some(node) = call_graph.get(symbol) {
-                let label = node.display_name.clone();
-                let tooltip = if let Some(body) = &node.body {
-                    let plain = body.replace(['\n', '\r'], " ").replace('"', "' ");
-                    if plain.len() > 200 {
-                        format!("{}...", &plain[..200])
-                    } else {
-                        plain
-                    }
-                } else {
-                    "".to_string()
-                };
+    #[test]
+    fn test_struct_and_enum_atoms() {
+        let point = "rust-analyzer cargo geo 0.1.0 Point#";
+        let shape = "rust-analyzer cargo geo 0.1.0 Shape#";
+        let origin = "rust-analyzer cargo geo 0.1.0 origin().";
+        let mut index = test_index(
+            "file:///geo",
+            "src/lib.rs",
+            &[(point, 0), (shape, 2), (origin, 5)],
+            &[(point, 6)],
+        );
+        index.documents[0].symbols[0].kind = i32::from(SymbolKind::Struct);
+        index.documents[0].symbols[1].kind = i32::from(SymbolKind::Enum);
 
-                // Color the initially matched nodes differently
-                let fillcolor = if matched_symbols.contains(symbol) {
-                    "lightblue"
-                } else {
-                    "white"
-                };
+        let call_graph = build_call_graph(&index);
+        assert_eq!(call_graph.len(), 3);
+        let origin_node = call_graph.values().find(|n| n.symbol == origin).unwrap();
+        assert!(origin_node.callees.contains(point));
 
-                dot.push_str(&format!(
-                    "    \"{}\" [label=\"{}\", tooltip=\"{}\", fillcolor={}]\n",
-                    node.symbol, label, tooltip, fillcolor
-                ));
-            }
-        }
+        let tmp = NamedTempFile::new().unwrap();
+        write_call_graph_as_atoms_json(&call_graph, tmp.path()).unwrap();
+        let atoms: Vec<Atom> =
+            serde_json::from_str(&fs::read_to_string(tmp.path()).unwrap()).unwrap();
+        let type_of = |name: &str| {
+            atoms
+                .iter()
+                .find(|a| a.display_name == name)
+                .map(|a| a.statement_type.clone())
+                .unwrap()
+        };
+        assert_eq!(type_of("Point"), "struct");
+        assert_eq!(type_of("Shape"), "enum");
+        assert_eq!(type_of("origin"), "function");
 
-        dot.push_str("  }\n");
+        let origin_atom = atoms.iter().find(|a| a.display_name == "origin").unwrap();
+        assert_eq!(origin_atom.deps.len(), 1);
     }
 
-    dot.push('\n');
+    #[test]
+    fn test_atoms_json_with_meta() {
+        let main = "rust-analyzer cargo app 0.1.0 main().";
+        let index = test_index("file:///app", "src/main.rs", &[(main, 0)], &[]);
+        let call_graph = build_call_graph(&index);
 
-    // Draw edges between all included nodes
-    for symbol in &included_symbols {
-        if let Some(node) = call_graph.get(symbol) {
-            for callee in &node.callees {
-                if included_symbols.contains(callee) {
-                    dot.push_str(&format!("  \"{}\" -> \"{}\"\n", node.symbol, callee));
-                }
-            }
-        }
+        let metadata = AtomsMetadata::new(index.metadata.tool_info.clone(), Some("abc123".into()));
+        let tmp = NamedTempFile::new().unwrap();
+        write_call_graph_as_atoms_json_with_meta(&call_graph, &metadata, tmp.path()).unwrap();
+        let written: AtomsWithMeta =
+            serde_json::from_str(&fs::read_to_string(tmp.path()).unwrap()).unwrap();
+
+        assert_eq!(written.atoms.len(), 1);
+        assert_eq!(written.metadata.atomizer_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(written.metadata.tool_info.name, "rust-analyzer");
+        assert_eq!(written.metadata.commit.as_deref(), Some("abc123"));
+        assert!(chrono::DateTime::parse_from_rfc3339(&written.metadata.generated_at).is_ok());
     }
 
-    dot.push_str("}\n");
-    std::fs::write(output_path, dot)
-}
+    #[test]
+    fn test_atoms_json_gz_round_trip() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let main = "rust-analyzer cargo app 0.1.0 main().";
+        let helper = "rust-analyzer cargo app 0.1.0 helper().";
+        let index = test_index(
+            "file:///app",
+            "src/main.rs",
+            &[(main, 0), (helper, 4)],
+            &[(helper, 1)],
+        );
+        let call_graph = build_call_graph(&index);
 
-pub fn generate_call_graph_svg(
-    call_graph: &HashMap<String, FunctionNode>,
-    output_path: &str,
-) -> std::io::Result<()> {
-    let node_radius = 40;
-    let width = 1200;
-    let height = 800;
-    let mut svg = format!(
-        r#"<svg xmlns='http://www.w3.org/2000/svg' width='{}' height='{}' style='background:#fff;font-family:sans-serif'>\n"#,
-        width, height
-    );
+        let tmp = NamedTempFile::new().unwrap();
+        write_call_graph_as_atoms_json_gz(&call_graph, tmp.path()).unwrap();
+        let mut json = String::new();
+        GzDecoder::new(fs::File::open(tmp.path()).unwrap())
+            .read_to_string(&mut json)
+            .unwrap();
+
+        let atoms: Vec<Atom> = serde_json::from_str(&json).unwrap();
+        assert_eq!(atoms.len(), 2);
+        let main_atom = atoms.iter().find(|a| a.display_name == "main").unwrap();
+        assert_eq!(main_atom.deps.len(), 1);
+        assert_eq!(
+            json,
+            serde_json::to_string_pretty(&call_graph_to_atoms(&call_graph)).unwrap()
+        );
+    }
 
-    // Use a force-directed layout instead of circular
-    // This is a simple implementation - for complex graphs, use a dot file with Graphviz
-    let nodes: Vec<_> = call_graph.values().collect();
-    let _n = nodes.len();
+    #[test]
+    fn test_missing_display_name_fallback() {
+        let ct_eq = "rust-analyzer cargo dalek 4.1.3 montgomery/MontgomeryPoint#ct_eq().";
+        let new = "rust-analyzer cargo dalek 4.1.3 montgomery/impl#[MontgomeryPoint]new().";
+        let unparsable = "rust-analyzer cargo dalek 4.1.3 ";
+        let mut index = test_index(
+            "file:///dalek",
+            "src/lib.rs",
+            &[(ct_eq, 0), (new, 4), (unparsable, 8)],
+            &[],
+        );
+        // An external stub nobody calls is dropped and must not be counted
+        let mut stub = index.documents[0].symbols[0].clone();
+        stub.symbol = "rust-analyzer cargo std 1.0.0 io/read().".to_string();
+        index.external_symbols.push(stub);
+        for symbol in index.documents[0]
+            .symbols
+            .iter_mut()
+            .chain(&mut index.external_symbols)
+        {
+            symbol.display_name = None;
+        }
+        let options = BuildOptions {
+            extract_bodies: false,
+            ..BuildOptions::default()
+        };
+        let (call_graph, warnings) = build_call_graph_with_options(&index, &options);
 
-    // Initial random positions
-    let mut positions = HashMap::new();
-    let mut rng = std::hash::DefaultHasher::new();
-    for node in &nodes {
-        use std::hash::{Hash, Hasher};
-        node.symbol.hash(&mut rng);
-        let x = (rng.finish() % 800) as f64 + 200.0;
-        rng.write_u8(1);
-        let y = (rng.finish() % 600) as f64 + 100.0;
-        positions.insert(&node.symbol, (x, y));
+        let mut names: Vec<&str> = call_graph
+            .values()
+            .map(|node| node.display_name.as_str())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["ct_eq", "new", "unknown"]);
+        assert!(warnings
+            .iter()
+            .any(|w| w.starts_with("2 symbol(s) had no display name; named after")));
+        assert!(warnings
+            .iter()
+            .any(|w| w.starts_with("1 symbol(s) had no display name and no parsable")));
+
+        assert_eq!(
+            symbol_item_name("rust-analyzer cargo a 0.1.0 m/`r#type`#(x)").as_deref(),
+            Some("r#type")
+        );
+        assert_eq!(
+            symbol_item_name("rust-analyzer cargo a 0.1.0 m/Point#[T]"),
+            Some("Point".to_string())
+        );
+        assert_eq!(fallback_display_name("local 7"), "unknown");
     }
 
-    // Arrows marker definition
-    svg.push_str("<defs><marker id='arrow' markerWidth='10' markerHeight='10' refX='10' refY='5' orient='auto' markerUnits='strokeWidth'><path d='M0,0 L10,5 L0,10 z' fill='#888'/></marker></defs>\n");
+    #[test]
+    fn test_trait_call_resolution() {
+        let main = "rust-analyzer cargo geo 0.1.0 main().";
+        let area = "rust-analyzer cargo geo 0.1.0 shape/Shape#area().";
+        let circle = "rust-analyzer cargo geo 0.1.0 shape/Circle#area().";
+        let square = "rust-analyzer cargo geo 0.1.0 shape/Square#area().";
+        let implements = |index: &mut ScipIndex, symbol: &str| {
+            let entry = index.documents[0]
+                .symbols
+                .iter_mut()
+                .find(|s| s.symbol == symbol)
+                .unwrap();
+            entry.relationships.push(Relationship {
+                symbol: area.to_string(),
+                is_implementation: true,
+                ..Relationship::default()
+            });
+        };
+        let callees = |call_graph: &HashMap<String, FunctionNode>, symbol: &str| {
+            let node = call_graph.values().find(|n| n.symbol == symbol).unwrap();
+            node.sorted_callees()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
 
-    // Draw edges
-    for node in call_graph.values() {
-        let (x1, y1) = positions[&node.symbol];
-        for callee in &node.callees {
-            if let Some(&(x2, y2)) = positions.get(callee) {
-                svg.push_str(&format!(
-                    "<line x1='{}' y1='{}' x2='{}' y2='{}' stroke='#888' stroke-width='2' marker-end='url(#arrow)'/>\n",
-                    x1, y1, x2, y2
-                ));
+        // A single implementation takes over the call
+        let mut index = test_index(
+            "file:///geo",
+            "src/main.rs",
+            &[(main, 0), (area, 4), (circle, 8)],
+            &[(area, 1)],
+        );
+        implements(&mut index, circle);
+        let call_graph = build_call_graph_edges_only(&index);
+        assert_eq!(callees(&call_graph, main), [circle]);
+        let circle_node = call_graph.values().find(|n| n.symbol == circle).unwrap();
+        assert_eq!(circle_node.callers.len(), 1);
+        let area_node = call_graph.values().find(|n| n.symbol == area).unwrap();
+        assert!(area_node.callers.is_empty());
+
+        // With several implementations the trait edge stays, optionally fanned out
+        let mut index = test_index(
+            "file:///geo",
+            "src/main.rs",
+            &[(main, 0), (area, 4), (circle, 8), (square, 12)],
+            &[(area, 1)],
+        );
+        implements(&mut index, circle);
+        implements(&mut index, square);
+        assert_eq!(callees(&build_call_graph_edges_only(&index), main), [area]);
+        let options = BuildOptions {
+            extract_bodies: false,
+            fan_out_trait_calls: true,
+            ..BuildOptions::default()
+        };
+        let (call_graph, _) = build_call_graph_with_options(&index, &options);
+        assert_eq!(callees(&call_graph, main), [circle, area, square]);
+
+        // A default body used by a type that does not override it stays a candidate
+        let mut index = test_index(
+            "file:///geo",
+            "src/main.rs",
+            &[(main, 0), (area, 4), (circle, 8)],
+            &[(area, 1)],
+        );
+        implements(&mut index, circle);
+        for ty in ["shape/Circle#", "shape/Square#"] {
+            index.documents[0].symbols.push(Symbol {
+                symbol: format!("rust-analyzer cargo geo 0.1.0 {ty}"),
+                kind: 49,
+                display_name: Some(ty.to_string()),
+                documentation: None,
+                signature_documentation: SignatureDocumentation {
+                    language: "rust".to_string(),
+                    text: format!("struct {ty}"),
+                    position_encoding: 1,
+                },
+                enclosing_symbol: None,
+                relationships: vec![Relationship {
+                    symbol: "rust-analyzer cargo geo 0.1.0 shape/Shape#".to_string(),
+                    is_implementation: true,
+                    ..Relationship::default()
+                }],
+            });
+        }
+        assert_eq!(callees(&build_call_graph_edges_only(&index), main), [area]);
+        let (call_graph, _) = build_call_graph_with_options(&index, &options);
+        assert_eq!(callees(&call_graph, main), [circle, area]);
+    }
+
+    #[test]
+    fn test_edge_kinds() {
+        let main = "rust-analyzer cargo geo 0.1.0 main().";
+        let helper = "rust-analyzer cargo geo 0.1.0 helper().";
+        let shape = "rust-analyzer cargo geo 0.1.0 shape/Shape#";
+        let area = "rust-analyzer cargo geo 0.1.0 shape/Shape#area().";
+        let perimeter = "rust-analyzer cargo geo 0.1.0 shape/Shape#perimeter().";
+        let circle = "rust-analyzer cargo geo 0.1.0 shape/Circle#area().";
+        let mut index = test_index(
+            "file:///geo",
+            "src/main.rs",
+            &[
+                (main, 0),
+                (helper, 5),
+                (area, 8),
+                (perimeter, 9),
+                (circle, 12),
+            ],
+            &[(helper, 1), (area, 2), (perimeter, 3)],
+        );
+        let symbols = &mut index.documents[0].symbols;
+        for symbol in symbols.iter_mut() {
+            if symbol.symbol == area || symbol.symbol == perimeter {
+                symbol.enclosing_symbol = Some(shape.to_string());
+            } else if symbol.symbol == circle {
+                symbol.relationships.push(Relationship {
+                    symbol: area.to_string(),
+                    is_implementation: true,
+                    ..Relationship::default()
+                });
             }
         }
+        let mut trait_symbol = symbols[0].clone();
+        trait_symbol.symbol = shape.to_string();
+        trait_symbol.kind = SymbolKind::Trait.into();
+        symbols.push(trait_symbol);
+
+        let call_graph = build_call_graph_edges_only(&index);
+        let main_node = call_graph.values().find(|n| n.symbol == main).unwrap();
+        assert_eq!(main_node.edge_kind(helper), EdgeKind::Direct);
+        // Resolved to the only impl
+        assert_eq!(main_node.edge_kind(circle), EdgeKind::TraitStatic);
+        // Declared in a trait without a project impl
+        assert_eq!(main_node.edge_kind(perimeter), EdgeKind::TraitDynamic);
+        assert!(!main_node.edge_kinds.contains_key(helper));
+
+        let dot = call_graph_to_dot(&call_graph, false, &DotOptions::default());
+        assert!(!dot.contains("style=dotted"));
+        let options = DotOptions {
+            edge_kind_styles: true,
+            ..Default::default()
+        };
+        let dot = call_graph_to_dot(&call_graph, false, &options);
+        assert!(dot.contains(&format!("\"{main}\" -> \"{circle}\" [style=dashed]")));
+        assert!(dot.contains(&format!("\"{main}\" -> \"{perimeter}\" [style=dotted]")));
+        assert!(dot.contains(&format!("\"{main}\" -> \"{helper}\"\n")));
+        validate_dot(&dot).unwrap();
     }
 
-    // Draw nodes
-    for node in call_graph.values() {
-        let (x, y) = positions[&node.symbol];
-        let body = node
-            .body
-            .as_ref()
-            .map(|b| html_escape::encode_safe(b))
-            .unwrap_or_default();
-        svg.push_str(&format!(
-            "<g>\
-                <circle cx='{}' cy='{}' r='{}' fill='#4a90e2' stroke='#222' stroke-width='2'/>\
-                <text x='{}' y='{}' text-anchor='middle' alignment-baseline='middle' fill='#fff' font-size='14'>{}</text>\
-                <title>{}</title>\
-            </g>\n",
-            x,
-            y,
-            node_radius,
-            x,
-            y,
-            html_escape::encode_safe(&node.display_name),
-            body
-        ));
+    #[test]
+    fn test_enclosing_symbol() {
+        let point = "rust-analyzer cargo geo 0.1.0 point/Point#";
+        let new = "rust-analyzer cargo geo 0.1.0 point/Point#new().";
+        let norm = "rust-analyzer cargo geo 0.1.0 point/Point#norm().";
+        let square = "rust-analyzer cargo geo 0.1.0 point/Point#norm().square().";
+        let origin = "rust-analyzer cargo geo 0.1.0 point/origin().";
+        let mut index = test_index(
+            "file:///geo",
+            "src/point.rs",
+            &[(new, 2), (norm, 6), (square, 7), (origin, 12)],
+            &[],
+        );
+        let symbols = &mut index.documents[0].symbols;
+        for symbol in symbols.iter_mut() {
+            symbol.display_name = symbol_item_name(&symbol.symbol);
+            symbol.enclosing_symbol = match symbol.symbol.as_str() {
+                s if s == square => Some(norm.to_string()),
+                s if s == origin => Some("rust-analyzer cargo geo 0.1.0 point/".to_string()),
+                _ => Some(point.to_string()),
+            };
+        }
+        let mut point_symbol = symbols[0].clone();
+        point_symbol.symbol = point.to_string();
+        point_symbol.display_name = Some("Point".to_string());
+        point_symbol.kind = SymbolKind::Struct.into();
+        point_symbol.enclosing_symbol = None;
+        symbols.push(point_symbol);
+
+        let call_graph = build_call_graph_edges_only(&index);
+        let enclosing = |display_name: &str| {
+            atoms(&call_graph)
+                .find(|atom| atom.display_name == display_name)
+                .unwrap()
+                .enclosing
+        };
+        let point_path = symbol_to_path(point, "");
+        assert_eq!(enclosing("new"), Some(point_path.clone()));
+        assert_eq!(enclosing("norm"), Some(point_path));
+        assert_eq!(enclosing("square"), Some(symbol_to_path(norm, "")));
+        // Modules are not reported
+        assert_eq!(enclosing("origin"), None);
     }
 
-    svg.push_str("</svg>\n");
-    fs::write(output_path, svg)
-}
+    #[test]
+    fn test_atoms_iterator_matches_vec() {
+        let main = "rust-analyzer cargo app 0.1.0 main().";
+        let helper = "rust-analyzer cargo app 0.1.0 helper().";
+        let index = test_index(
+            "file:///app",
+            "src/main.rs",
+            &[(main, 0), (helper, 4)],
+            &[(helper, 1), (main, 5)],
+        );
+        let call_graph = build_call_graph(&index);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
-    use std::fs;
-    use tempfile::NamedTempFile;
+        let lazy: Vec<Atom> = atoms(&call_graph).collect();
+        assert_eq!(
+            serde_json::to_string_pretty(&lazy).unwrap(),
+            serde_json::to_string_pretty(&call_graph_to_atoms(&call_graph)).unwrap()
+        );
+        assert!(lazy.iter().all(|atom| atom.scc_id == Some(0)));
+
+        let streamed = NamedTempFile::new().unwrap();
+        let materialized = NamedTempFile::new().unwrap();
+        write_call_graph_as_atoms_json(&call_graph, streamed.path()).unwrap();
+        write_atoms_json(&call_graph_to_atoms(&call_graph), materialized.path()).unwrap();
+        assert_eq!(
+            fs::read(streamed.path()).unwrap(),
+            fs::read(materialized.path()).unwrap()
+        );
+    }
 
     #[test]
-    fn test_function_body_extraction() {
-        let mut call_graph = HashMap::new();
-        call_graph.insert(
-            "f1".to_string(),
-            FunctionNode {
-                symbol: "f1".to_string(),
-                display_name: "foo".to_string(),
-                signature_text: "fn foo()".to_string(),
-                file_path: "/tmp/foo.rs".to_string(),
-                relative_path: "tmp/foo.rs".to_string(),
-                callers: HashSet::new(),
-                callees: HashSet::new(),
-                range: vec![],
-                body: Some("fn foo() { println!(\"Hello\"); }".to_string()),
-            },
+    fn test_const_and_static_atoms() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = "const N: usize = 8;\n\nstatic NAME: &str = \"buf\";\n\nfn buffer() -> [u8; N] {\n    [0; N]\n}\n";
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), source).unwrap();
+
+        let n = "rust-analyzer cargo buf 0.1.0 N.";
+        let name = "rust-analyzer cargo buf 0.1.0 NAME.";
+        let buffer = "rust-analyzer cargo buf 0.1.0 buffer().";
+        let root = format!("file://{}", dir.path().display());
+        let mut index = test_index(
+            &root,
+            "src/lib.rs",
+            &[(n, 0), (name, 2), (buffer, 4)],
+            &[(n, 5)],
         );
-        let tmp = NamedTempFile::new().unwrap();
-        generate_call_graph_dot(&call_graph, tmp.path().to_str().unwrap()).unwrap();
-        let dot = fs::read_to_string(tmp.path()).unwrap();
-        // Note: quotes are replaced with "' " in the tooltip for DOT format safety
-        assert!(dot.contains("tooltip=\"fn foo() { println!(' Hello' ); }\""));
+        for (symbol, kind, display_name) in [
+            (0, i32::from(SymbolKind::Constant), "N"),
+            (1, i32::from(SymbolKind::StaticVariable), "NAME"),
+        ] {
+            index.documents[0].symbols[symbol].kind = kind;
+            index.documents[0].symbols[symbol].display_name = Some(display_name.to_string());
+        }
+
+        let atoms = call_graph_to_atoms(&build_call_graph(&index));
+        let atom = |name: &str| atoms.iter().find(|a| a.display_name == name).unwrap();
+        assert_eq!(atom("N").statement_type, "const");
+        assert_eq!(atom("N").body, "const N: usize = 8;");
+        assert_eq!(atom("NAME").statement_type, "static");
+        assert_eq!(atom("buffer").deps, vec![atom("N").identifier.clone()]);
     }
 
     #[test]
-    fn test_symbol_to_code_name() {
-        // Test basic conversion
-        let symbol =
-            "rust-analyzer cargo curve25519-dalek 4.1.3 montgomery/MontgomeryPoint#ct_eq().";
-        let code_name = symbol_to_code_name(symbol, "ct_eq", None);
+    fn test_exclude_tests() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = "fn run() {\n    helper()\n}\n\nfn helper() {}\n\n#[cfg(test)]\nmod tests {\n    fn fixture() {}\n\n    #[test]\n    fn test_run() {\n        super::run();\n        fixture()\n    }\n}\n";
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), source).unwrap();
+
+        let run = "rust-analyzer cargo app 0.1.0 run().";
+        let helper = "rust-analyzer cargo app 0.1.0 helper().";
+        let fixture = "rust-analyzer cargo app 0.1.0 tests/fixture().";
+        let test_run = "rust-analyzer cargo app 0.1.0 tests/test_run().";
+        let root = format!("file://{}", dir.path().display());
+        let index = test_index(
+            &root,
+            "src/lib.rs",
+            &[(run, 0), (helper, 4), (fixture, 8), (test_run, 11)],
+            &[(helper, 1), (run, 12), (fixture, 13)],
+        );
+
+        let options = BuildOptions {
+            exclude_tests: true,
+            ..BuildOptions::default()
+        };
+        let (call_graph, _) = build_call_graph_with_options(&index, &options);
+        let mut symbols: Vec<&str> = call_graph.values().map(|n| n.symbol.as_str()).collect();
+        symbols.sort();
+        assert_eq!(symbols, vec![helper, run]);
+        let run_node = call_graph.values().find(|n| n.symbol == run).unwrap();
+        assert!(run_node.callers.is_empty());
+        assert_eq!(run_node.callees.len(), 1);
+
+        assert_eq!(build_call_graph(&index).len(), 4);
+    }
+
+    #[test]
+    fn test_update_call_graph() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/main.rs"),
+            "fn main() {\n    helper()\n}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("src/util.rs"), "fn helper() {}\n").unwrap();
+
+        let main = "rust-analyzer cargo app 0.1.0 main().";
+        let helper = "rust-analyzer cargo app 0.1.0 util/helper().";
+        let extra = "rust-analyzer cargo app 0.1.0 util/extra().";
+        let root = format!("file://{}", dir.path().display());
+        let index_with = |util: ScipIndex| {
+            let mut index = test_index(&root, "src/main.rs", &[(main, 0)], &[(helper, 1)]);
+            index.documents.extend(util.documents);
+            index
+        };
+        let index = index_with(test_index(&root, "src/util.rs", &[(helper, 0)], &[]));
+        let options = BuildOptions::default();
+        let (mut call_graph, _) = build_call_graph_with_options(&index, &options);
+        let old_main = call_graph
+            .values()
+            .find(|n| n.symbol == main)
+            .unwrap()
+            .clone();
+
+        // Edit util.rs so that helper calls a new function. main.rs also changes on
+        // disk, but is not reported as changed, so its body must not be re-read.
+        fs::write(
+            dir.path().join("src/util.rs"),
+            "fn helper() {\n    extra()\n}\n\nfn extra() {}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        let index = index_with(test_index(
+            &root,
+            "src/util.rs",
+            &[(helper, 0), (extra, 4)],
+            &[(extra, 1)],
+        ));
+        update_call_graph(
+            &mut call_graph,
+            &index,
+            &["src/util.rs".to_string()],
+            &options,
+        );
+
+        assert_eq!(call_graph.len(), 3);
+        let node = |symbol: &str| call_graph.values().find(|n| n.symbol == symbol).unwrap();
+        assert_eq!(node(main), &old_main);
         assert_eq!(
-            code_name,
-            "probe:curve25519-dalek/4.1.3/montgomery/MontgomeryPoint#ct_eq()"
+            node(helper).body.as_deref(),
+            Some("fn helper() {\n    extra()\n}")
         );
+        assert_eq!(node(extra).body.as_deref(), Some("fn extra() {}"));
+        let main_key = call_graph.iter().find(|(_, n)| n.symbol == main).unwrap().0;
+        assert!(node(helper).callers.contains(main_key));
+        assert_eq!(node(extra).callers.len(), 1);
+
+        // Edges match a full rebuild of the new index
+        let rebuilt = build_call_graph_edges_only(&index);
+        for (key, node) in &call_graph {
+            assert_eq!(node.callers, rebuilt[key].callers);
+            assert_eq!(node.callees, rebuilt[key].callees);
+        }
+    }
 
-        // Test with trait method and type disambiguation
-        let symbol = "rust-analyzer cargo curve25519-dalek 4.1.3 montgomery/Mul#mul().";
-        let signature = "fn mul(self, scalar: &Scalar) -> MontgomeryPoint";
-        let code_name = symbol_to_code_name(symbol, "mul", Some(signature));
+    #[test]
+    fn test_symbol_kind_conversions() {
+        assert_eq!(SymbolKind::try_from(17), Ok(SymbolKind::Function));
+        assert_eq!(SymbolKind::try_from(53), Ok(SymbolKind::Trait));
+        assert_eq!(i32::from(SymbolKind::StaticMethod), 80);
+        assert!(SymbolKind::try_from(-1).is_err());
+
+        assert!(is_function_like(i32::from(SymbolKind::Method)));
+        assert!(!is_function_like(i32::from(SymbolKind::Trait)));
+        assert!(!is_function_like(-1));
         assert_eq!(
-            code_name,
-            "probe:curve25519-dalek/4.1.3/montgomery/Mul<Scalar>#mul()"
+            statement_type_for_kind(i32::from(SymbolKind::StaticMethod)),
+            "method"
+        );
+        assert_eq!(
+            test_node("f", &[]).symbol_kind(),
+            Some(SymbolKind::Function)
         );
+    }
 
-        // Test simple function (no trait)
-        let symbol = "rust-analyzer cargo mylib 0.1.0 utils/helper().";
-        let code_name = symbol_to_code_name(symbol, "helper", None);
-        assert_eq!(code_name, "probe:mylib/0.1.0/utils/helper()");
+    #[test]
+    fn test_statement_type_per_kind() {
+        let new = "rust-analyzer cargo geo 0.1.0 Point#new().";
+        let norm = "rust-analyzer cargo geo 0.1.0 Point#norm().";
+        let square = "rust-analyzer cargo geo 0.1.0 square!";
+        let origin = "rust-analyzer cargo geo 0.1.0 origin().";
+        let mut index = test_index(
+            "file:///geo",
+            "src/lib.rs",
+            &[(new, 0), (norm, 2), (square, 4), (origin, 6)],
+            &[(square, 7)],
+        );
+        index.documents[0].symbols[0].kind = i32::from(SymbolKind::Constructor);
+        index.documents[0].symbols[1].kind = i32::from(SymbolKind::Method);
+        index.documents[0].symbols[2].kind = i32::from(SymbolKind::Macro);
+
+        let call_graph = build_call_graph(&index);
+        assert_eq!(call_graph.len(), 4);
+        let square_node = call_graph.values().find(|n| n.symbol == square).unwrap();
+        assert_eq!(square_node.kind, i32::from(SymbolKind::Macro));
+
+        let atoms = call_graph_to_atoms(&call_graph);
+        let type_of = |name: &str| {
+            atoms
+                .iter()
+                .find(|a| a.display_name == name)
+                .map(|a| a.statement_type.clone())
+                .unwrap()
+        };
+        assert_eq!(type_of("new"), "constructor");
+        assert_eq!(type_of("norm"), "method");
+        assert_eq!(type_of("square!"), "macro");
+        assert_eq!(type_of("origin"), "function");
+    }
+
+    #[test]
+    fn test_symbol_interner() {
+        let mut interner = SymbolInterner::default();
+        let a = interner.intern("rust-analyzer cargo demo 0.1.0 a().");
+        let b = interner.intern("rust-analyzer cargo demo 0.1.0 b().");
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("rust-analyzer cargo demo 0.1.0 a()."), a);
+        assert_eq!(interner.get("rust-analyzer cargo demo 0.1.0 b()."), Some(b));
+        assert_eq!(interner.get("missing"), None);
+        assert_eq!(interner.resolve(b), "rust-analyzer cargo demo 0.1.0 b().");
     }
 }
@@ -0,0 +1,78 @@
+//! Preflight checks for the external programs the pipeline shells out to.
+//!
+//! A missing analyzer otherwise only surfaces as an opaque "No such file or
+//! directory" once indexing starts; checking up front lets us say what to install.
+
+use crate::error::AtomizerError;
+use std::process::Command;
+
+/// An external program, with a hint on how to install it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalTool {
+    /// Executable name looked up on `PATH`
+    pub binary: &'static str,
+    pub install_hint: &'static str,
+}
+
+pub const RUST_ANALYZER: ExternalTool = ExternalTool {
+    binary: "rust-analyzer",
+    install_hint: "install it with `rustup component add rust-analyzer`",
+};
+
+pub const VERUS_ANALYZER: ExternalTool = ExternalTool {
+    binary: "verus-analyzer",
+    install_hint: "build it from https://github.com/verus-lang/verus-analyzer",
+};
+
+pub const SCIP: ExternalTool = ExternalTool {
+    binary: "scip",
+    install_hint: "download a release from https://github.com/sourcegraph/scip/releases",
+};
+
+pub const GIT: ExternalTool = ExternalTool {
+    binary: "git",
+    install_hint: "install git from https://git-scm.com/downloads",
+};
+
+pub const GRAPHVIZ: ExternalTool = ExternalTool {
+    binary: "dot",
+    install_hint: "install Graphviz, e.g. `apt install graphviz` or `brew install graphviz`",
+};
+
+/// Run `<binary> --version` and return the first line it printed
+pub fn check_tool(tool: &ExternalTool) -> Result<String, AtomizerError> {
+    let output = Command::new(tool.binary)
+        .arg("--version")
+        .output()
+        .map_err(|e| AtomizerError::MissingTool {
+            tool: tool.binary.to_string(),
+            install_hint: tool.install_hint.to_string(),
+            reason: e.to_string(),
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().next().unwrap_or_default().trim().to_string())
+}
+
+/// Check every tool in `tools` (see [`check_tool`]) and fail on the first one missing,
+/// returning the version line of each otherwise
+pub fn check_tools(tools: &[ExternalTool]) -> Result<Vec<String>, AtomizerError> {
+    tools.iter().map(check_tool).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_missing_tool() {
+        let missing = ExternalTool {
+            binary: "atomizer-no-such-tool",
+            install_hint: "install it from the moon",
+        };
+        let err = check_tools(&[missing]).unwrap_err();
+        assert!(matches!(err, AtomizerError::MissingTool { .. }));
+        let message = err.to_string();
+        assert!(message.contains("atomizer-no-such-tool"));
+        assert!(message.contains("install it from the moon"));
+    }
+}
@@ -7,84 +7,343 @@
 //! - `forall`, `exists` quantifiers
 //! - `==>` implications, `&&&`, `|||` operators
 //!
-//! Uses the visitor pattern for proper AST traversal.
+//! Uses the visitor pattern for proper AST traversal. Files that `verus_syn` cannot parse
+//! fall back to the standard `syn` parser, which yields plain function and type spans.
 
 use log::debug;
+use quote::ToTokens;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use verus_syn::spanned::Spanned;
 use verus_syn::visit::Visit;
-use verus_syn::{ImplItemFn, Item, ItemFn, ItemMacro, TraitItemFn};
+use verus_syn::{
+    Attribute, FnMode, ImplItemFn, Item, ItemConst, ItemEnum, ItemFn, ItemMacro, ItemStatic,
+    ItemStruct, Signature, Specification, TraitItemFn,
+};
 
-/// Represents a function's location in a source file
-#[derive(Debug, Clone)]
+/// Verus function mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FunctionMode {
+    Spec,
+    Proof,
+    #[default]
+    Exec,
+}
+
+/// Kind of item a span belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ItemKind {
+    #[default]
+    Function,
+    Struct,
+    Enum,
+    Const,
+    Static,
+}
+
+/// Declared visibility of an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// `pub`
+    Public,
+    /// `pub(crate)`
+    Crate,
+    /// `pub(super)`, `pub(self)` or `pub(in path)`
+    Restricted,
+    /// No visibility modifier
+    #[default]
+    Private,
+}
+
+impl Visibility {
+    /// Classify a `syn` or `verus_syn` visibility by its source text
+    fn of<V: ToTokens>(vis: &V) -> Self {
+        let text: String = vis
+            .to_token_stream()
+            .to_string()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        match text.as_str() {
+            "" => Visibility::Private,
+            "pub" => Visibility::Public,
+            "pub(crate)" => Visibility::Crate,
+            _ => Visibility::Restricted,
+        }
+    }
+}
+
+/// Represents a function's (or type definition's) location in a source file
+#[derive(Debug, Clone, Default)]
 pub struct FunctionSpan {
     pub name: String,
-    pub start_line: usize, // 1-indexed
-    pub end_line: usize,   // 1-indexed (inclusive)
+    /// `name` prefixed with the functions it is nested in, e.g. `outer::helper`
+    pub qualified_name: String,
+    pub kind: ItemKind,
+    pub mode: FunctionMode,
+    pub start_line: usize,         // 1-indexed
+    pub end_line: usize,           // 1-indexed (inclusive)
+    pub start_col: usize,          // 0-indexed, in characters
+    pub end_col: usize,            // 0-indexed, in characters (exclusive)
+    pub requires: Vec<String>,     // `requires` clauses, one entry per expression
+    pub ensures: Vec<String>,      // `ensures` clauses, one entry per expression
+    pub decreases: Option<String>, // `decreases` measure, if any
+    pub is_test: bool,             // `#[test]`-like attribute, or inside a `#[cfg(test)]` module
+    pub visibility: Visibility,    // Trait methods are recorded as `Public`
+    pub is_async: bool,
+    pub is_unsafe: bool,
+    pub is_const: bool,
+    /// Function header, generics, inputs, output and where-clause as token text,
+    /// e.g. `fn f (x : u32) -> bool`; empty for types, consts and statics
+    pub signature: String,
+}
+
+/// Attribute contents without the surrounding `#[...]` and whitespace, e.g. `tokio::test`
+fn attribute_text<A: ToTokens>(attr: &A) -> String {
+    let text: String = attr
+        .to_token_stream()
+        .to_string()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    text.strip_prefix("#[")
+        .and_then(|t| t.strip_suffix(']'))
+        .unwrap_or(&text)
+        .to_string()
+}
+
+/// Whether the attributes mark a test function: `#[test]` or any path ending in
+/// `test`, such as `#[tokio::test]`
+fn has_test_attr<A: ToTokens>(attrs: &[A]) -> bool {
+    attrs
+        .iter()
+        .any(|attr| attribute_text(attr).rsplit("::").next() == Some("test"))
+}
+
+/// Whether the attributes contain `#[cfg(test)]`
+fn has_cfg_test<A: ToTokens>(attrs: &[A]) -> bool {
+    attrs.iter().any(|attr| attribute_text(attr) == "cfg(test)")
+}
+
+/// Qualify `name` with the functions it is nested in, e.g. `outer::helper`
+fn qualified_name(enclosing_functions: &[String], name: &str) -> String {
+    enclosing_functions
+        .iter()
+        .map(String::as_str)
+        .chain([name])
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Render a signature without its Verus mode and specification clauses
+fn signature_text(sig: &Signature) -> String {
+    let Signature {
+        constness,
+        asyncness,
+        unsafety,
+        abi,
+        fn_token,
+        ident,
+        generics,
+        inputs,
+        output,
+        ..
+    } = sig;
+    let where_clause = &generics.where_clause;
+    quote::quote!(#constness #asyncness #unsafety #abi #fn_token #ident #generics (#inputs) #output #where_clause)
+        .to_string()
+}
+
+/// Like [`signature_text`], for a plain `syn` signature
+fn syn_signature_text(sig: &syn::Signature) -> String {
+    let syn::Signature {
+        constness,
+        asyncness,
+        unsafety,
+        abi,
+        fn_token,
+        ident,
+        generics,
+        inputs,
+        output,
+        ..
+    } = sig;
+    let where_clause = &generics.where_clause;
+    quote::quote!(#constness #asyncness #unsafety #abi #fn_token #ident #generics (#inputs) #output #where_clause)
+        .to_string()
+}
+
+/// Render each expression of a Verus specification clause as source text
+fn spec_exprs(spec: &Specification) -> Vec<String> {
+    spec.exprs
+        .iter()
+        .map(|expr| expr.to_token_stream().to_string())
+        .collect()
+}
+
+/// Determine the Verus mode of a function. Functions without a mode keyword are `exec`.
+fn signature_mode(sig: &Signature) -> FunctionMode {
+    match &sig.mode {
+        FnMode::Spec(_) | FnMode::SpecChecked(_) => FunctionMode::Spec,
+        FnMode::Exec(_) | FnMode::Default => FunctionMode::Exec,
+        // `proof fn` and its variants (e.g. `axiom fn`)
+        _ => FunctionMode::Proof,
+    }
+}
+
+/// Extract the `requires`, `ensures` and `decreases` clauses from a function signature
+fn signature_contract(sig: &Signature) -> (Vec<String>, Vec<String>, Option<String>) {
+    let requires = sig
+        .spec
+        .requires
+        .as_ref()
+        .map(|r| spec_exprs(&r.exprs))
+        .unwrap_or_default();
+    let ensures = sig
+        .spec
+        .ensures
+        .as_ref()
+        .map(|e| spec_exprs(&e.exprs))
+        .unwrap_or_default();
+    let decreases = sig
+        .spec
+        .decreases
+        .as_ref()
+        .map(|d| spec_exprs(&d.decreases.exprs).join(", "));
+    (requires, ensures, decreases)
 }
 
 /// Visitor that collects function spans from an AST
 struct FunctionSpanVisitor {
     functions: Vec<FunctionSpan>,
+    /// Number of enclosing `#[cfg(test)]` modules
+    test_module_depth: usize,
+    /// Names of the functions whose bodies are being visited, outermost first
+    enclosing_functions: Vec<String>,
+    /// Macros whose bodies are parsed as items, see [`DEFAULT_ITEM_MACROS`]
+    item_macros: Vec<String>,
 }
 
 impl FunctionSpanVisitor {
-    fn new() -> Self {
+    fn new(item_macros: &[String]) -> Self {
         Self {
             functions: Vec::new(),
+            test_module_depth: 0,
+            enclosing_functions: Vec::new(),
+            item_macros: item_macros.to_vec(),
         }
     }
-}
 
-impl<'ast> Visit<'ast> for FunctionSpanVisitor {
-    fn visit_item_fn(&mut self, node: &'ast ItemFn) {
-        let name = node.sig.ident.to_string();
+    /// Record a type, const or static definition given its name and the syntax node spanning it
+    fn record_item<T: Spanned>(
+        &mut self,
+        name: String,
+        kind: ItemKind,
+        visibility: Visibility,
+        node: &T,
+    ) {
         let span = node.span();
-        let start_line = span.start().line;
-        let end_line = span.end().line;
-
         self.functions.push(FunctionSpan {
+            qualified_name: qualified_name(&self.enclosing_functions, &name),
             name,
-            start_line,
-            end_line,
+            kind,
+            visibility,
+            start_line: span.start().line,
+            end_line: span.end().line,
+            start_col: span.start().column,
+            end_col: span.end().column,
+            ..Default::default()
         });
-
-        // Continue visiting nested items
-        verus_syn::visit::visit_item_fn(self, node);
     }
 
-    fn visit_impl_item_fn(&mut self, node: &'ast ImplItemFn) {
-        let name = node.sig.ident.to_string();
+    /// Record a function given its signature, attributes and the syntax node spanning it
+    fn record_function<T: Spanned>(
+        &mut self,
+        sig: &Signature,
+        attrs: &[Attribute],
+        visibility: Visibility,
+        node: &T,
+    ) {
         let span = node.span();
-        let start_line = span.start().line;
-        let end_line = span.end().line;
+        let (requires, ensures, decreases) = signature_contract(sig);
 
         self.functions.push(FunctionSpan {
-            name,
-            start_line,
-            end_line,
+            name: sig.ident.to_string(),
+            qualified_name: qualified_name(&self.enclosing_functions, &sig.ident.to_string()),
+            kind: ItemKind::Function,
+            mode: signature_mode(sig),
+            start_line: span.start().line,
+            end_line: span.end().line,
+            start_col: span.start().column,
+            end_col: span.end().column,
+            requires,
+            ensures,
+            decreases,
+            is_test: self.test_module_depth > 0 || has_test_attr(attrs),
+            visibility,
+            is_async: sig.asyncness.is_some(),
+            is_unsafe: sig.unsafety.is_some(),
+            is_const: sig.constness.is_some(),
+            signature: signature_text(sig),
         });
+    }
+}
+
+impl<'ast> Visit<'ast> for FunctionSpanVisitor {
+    fn visit_item_fn(&mut self, node: &'ast ItemFn) {
+        self.record_function(&node.sig, &node.attrs, Visibility::of(&node.vis), node);
+
+        // Continue visiting nested items, qualified by this function's name
+        self.enclosing_functions.push(node.sig.ident.to_string());
+        verus_syn::visit::visit_item_fn(self, node);
+        self.enclosing_functions.pop();
+    }
 
-        // Continue visiting nested items
+    fn visit_impl_item_fn(&mut self, node: &'ast ImplItemFn) {
+        self.record_function(&node.sig, &node.attrs, Visibility::of(&node.vis), node);
+
+        // Continue visiting nested items, qualified by this function's name
+        self.enclosing_functions.push(node.sig.ident.to_string());
         verus_syn::visit::visit_impl_item_fn(self, node);
+        self.enclosing_functions.pop();
     }
 
     fn visit_trait_item_fn(&mut self, node: &'ast TraitItemFn) {
-        let name = node.sig.ident.to_string();
-        let span = node.span();
-        let start_line = span.start().line;
-        let end_line = span.end().line;
+        self.record_function(&node.sig, &node.attrs, Visibility::Public, node);
 
-        self.functions.push(FunctionSpan {
-            name,
-            start_line,
-            end_line,
-        });
-
-        // Continue visiting nested items
+        // Continue visiting nested items, qualified by this function's name
+        self.enclosing_functions.push(node.sig.ident.to_string());
         verus_syn::visit::visit_trait_item_fn(self, node);
+        self.enclosing_functions.pop();
+    }
+
+    fn visit_item_struct(&mut self, node: &'ast ItemStruct) {
+        let visibility = Visibility::of(&node.vis);
+        self.record_item(node.ident.to_string(), ItemKind::Struct, visibility, node);
+        verus_syn::visit::visit_item_struct(self, node);
+    }
+
+    fn visit_item_enum(&mut self, node: &'ast ItemEnum) {
+        let visibility = Visibility::of(&node.vis);
+        self.record_item(node.ident.to_string(), ItemKind::Enum, visibility, node);
+        verus_syn::visit::visit_item_enum(self, node);
+    }
+
+    fn visit_item_const(&mut self, node: &'ast ItemConst) {
+        let visibility = Visibility::of(&node.vis);
+        self.record_item(node.ident.to_string(), ItemKind::Const, visibility, node);
+        verus_syn::visit::visit_item_const(self, node);
+    }
+
+    fn visit_item_static(&mut self, node: &'ast ItemStatic) {
+        let visibility = Visibility::of(&node.vis);
+        self.record_item(node.ident.to_string(), ItemKind::Static, visibility, node);
+        verus_syn::visit::visit_item_static(self, node);
     }
 
     // Ensure we traverse into impl blocks
@@ -99,19 +358,26 @@ impl<'ast> Visit<'ast> for FunctionSpanVisitor {
 
     // Ensure we traverse into modules
     fn visit_item_mod(&mut self, node: &'ast verus_syn::ItemMod) {
+        let is_test_module = has_cfg_test(&node.attrs);
+        self.test_module_depth += usize::from(is_test_module);
         verus_syn::visit::visit_item_mod(self, node);
+        self.test_module_depth -= usize::from(is_test_module);
     }
 
-    // Handle verus! macro blocks by parsing their contents
+    // Handle verus! (and other item) macro blocks by parsing their contents
     fn visit_item_macro(&mut self, node: &'ast ItemMacro) {
-        // Check if this is a verus! macro
-        if let Some(ident) = &node.mac.path.get_ident() {
-            if *ident == "verus" {
-                // Try to parse the macro body as items
-                if let Ok(items) = verus_syn::parse2::<VerusMacroBody>(node.mac.tokens.clone()) {
-                    for item in items.items {
-                        self.visit_item(&item);
-                    }
+        // Check if this is one of the item macros, by the last segment of its path
+        let is_item_macro = node
+            .mac
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| self.item_macros.iter().any(|m| segment.ident == m));
+        if is_item_macro {
+            // Try to parse the macro body as items
+            if let Ok(items) = verus_syn::parse2::<VerusMacroBody>(node.mac.tokens.clone()) {
+                for item in items.items {
+                    self.visit_item(&item);
                 }
             }
         }
@@ -120,6 +386,14 @@ impl<'ast> Visit<'ast> for FunctionSpanVisitor {
     }
 }
 
+/// Macros whose bodies are parsed as items by default
+pub const DEFAULT_ITEM_MACROS: &[&str] = &["verus"];
+
+/// [`DEFAULT_ITEM_MACROS`] as owned strings
+fn default_item_macros() -> Vec<String> {
+    DEFAULT_ITEM_MACROS.iter().map(|m| m.to_string()).collect()
+}
+
 /// Helper struct to parse verus! macro body as a list of items
 struct VerusMacroBody {
     items: Vec<Item>,
@@ -137,71 +411,337 @@ impl verus_syn::parse::Parse for VerusMacroBody {
 
 /// Parse a Verus/Rust source file and extract all function spans
 pub fn extract_function_spans(file_path: &str) -> Result<Vec<FunctionSpan>, String> {
+    extract_function_spans_with_macros(file_path, &default_item_macros())
+}
+
+/// Like [`extract_function_spans`], parsing the bodies of `item_macros` as items
+pub fn extract_function_spans_with_macros(
+    file_path: &str,
+    item_macros: &[String],
+) -> Result<Vec<FunctionSpan>, String> {
     let path = Path::new(file_path);
     let content = fs::read_to_string(path)
         .map_err(|e| format!("Failed to read file {}: {}", file_path, e))?;
 
-    extract_function_spans_from_content(&content)
+    extract_function_spans_from_content_with_macros(&content, item_macros)
 }
 
-/// Parse content string and extract all function spans
+/// Parse content string and extract all function spans.
+///
+/// Tries `verus_syn` first and falls back to plain `syn` for Rust files using syntax
+/// that `verus_syn` rejects. Spans found by the fallback carry no Verus contract or mode.
 pub fn extract_function_spans_from_content(content: &str) -> Result<Vec<FunctionSpan>, String> {
+    extract_function_spans_from_content_with_macros(content, &default_item_macros())
+}
+
+/// Like [`extract_function_spans_from_content`], also looking inside invocations of
+/// `item_macros` (e.g. `["verus", "my_items"]`).
+///
+/// Macros are never expanded: a recognized macro's token stream is only re-parsed as a
+/// list of items, so this works for macros that wrap item-shaped code (like `verus!`),
+/// not for macros that generate functions from other input.
+pub fn extract_function_spans_from_content_with_macros(
+    content: &str,
+    item_macros: &[String],
+) -> Result<Vec<FunctionSpan>, String> {
+    let verus_error = match verus_syn::parse_file(content) {
+        Ok(syntax_tree) => {
+            debug!("Parsed file with verus_syn");
+            let mut visitor = FunctionSpanVisitor::new(item_macros);
+            visitor.visit_file(&syntax_tree);
+            return Ok(visitor.functions);
+        }
+        Err(e) => e,
+    };
+
+    debug!(
+        "verus_syn failed to parse file ({}), retrying with syn",
+        verus_error
+    );
+    let spans = extract_function_spans_with_syn(content).map_err(|syn_error| {
+        format!(
+            "Failed to parse file: {} (syn fallback: {})",
+            verus_error, syn_error
+        )
+    })?;
+    debug!("Parsed file with syn fallback");
+    Ok(spans)
+}
+
+/// Visitor collecting function and type spans from a plain `syn` AST
+struct SynFunctionSpanVisitor {
+    functions: Vec<FunctionSpan>,
+    /// Number of enclosing `#[cfg(test)]` modules
+    test_module_depth: usize,
+    /// Names of the functions whose bodies are being visited, outermost first
+    enclosing_functions: Vec<String>,
+}
+
+impl SynFunctionSpanVisitor {
+    /// Record a type, const or static definition given its name and the syntax node spanning it
+    fn record_item<T: syn::spanned::Spanned>(
+        &mut self,
+        name: String,
+        kind: ItemKind,
+        visibility: Visibility,
+        node: &T,
+    ) {
+        let span = node.span();
+        self.functions.push(FunctionSpan {
+            qualified_name: qualified_name(&self.enclosing_functions, &name),
+            name,
+            kind,
+            visibility,
+            start_line: span.start().line,
+            end_line: span.end().line,
+            start_col: span.start().column,
+            end_col: span.end().column,
+            ..Default::default()
+        });
+    }
+
+    /// Record a function given its signature, attributes and the syntax node spanning it
+    fn record_function<T: syn::spanned::Spanned>(
+        &mut self,
+        sig: &syn::Signature,
+        attrs: &[syn::Attribute],
+        visibility: Visibility,
+        node: &T,
+    ) {
+        let span = node.span();
+        self.functions.push(FunctionSpan {
+            name: sig.ident.to_string(),
+            qualified_name: qualified_name(&self.enclosing_functions, &sig.ident.to_string()),
+            kind: ItemKind::Function,
+            start_line: span.start().line,
+            end_line: span.end().line,
+            start_col: span.start().column,
+            end_col: span.end().column,
+            is_test: self.test_module_depth > 0 || has_test_attr(attrs),
+            visibility,
+            is_async: sig.asyncness.is_some(),
+            is_unsafe: sig.unsafety.is_some(),
+            is_const: sig.constness.is_some(),
+            signature: syn_signature_text(sig),
+            ..Default::default()
+        });
+    }
+}
+
+impl<'ast> syn::visit::Visit<'ast> for SynFunctionSpanVisitor {
+    fn visit_item_fn(&mut self, node: &'ast syn::ItemFn) {
+        self.record_function(&node.sig, &node.attrs, Visibility::of(&node.vis), node);
+        self.enclosing_functions.push(node.sig.ident.to_string());
+        syn::visit::visit_item_fn(self, node);
+        self.enclosing_functions.pop();
+    }
+
+    fn visit_impl_item_fn(&mut self, node: &'ast syn::ImplItemFn) {
+        self.record_function(&node.sig, &node.attrs, Visibility::of(&node.vis), node);
+        self.enclosing_functions.push(node.sig.ident.to_string());
+        syn::visit::visit_impl_item_fn(self, node);
+        self.enclosing_functions.pop();
+    }
+
+    fn visit_trait_item_fn(&mut self, node: &'ast syn::TraitItemFn) {
+        self.record_function(&node.sig, &node.attrs, Visibility::Public, node);
+        self.enclosing_functions.push(node.sig.ident.to_string());
+        syn::visit::visit_trait_item_fn(self, node);
+        self.enclosing_functions.pop();
+    }
+
+    fn visit_item_struct(&mut self, node: &'ast syn::ItemStruct) {
+        let visibility = Visibility::of(&node.vis);
+        self.record_item(node.ident.to_string(), ItemKind::Struct, visibility, node);
+        syn::visit::visit_item_struct(self, node);
+    }
+
+    fn visit_item_enum(&mut self, node: &'ast syn::ItemEnum) {
+        let visibility = Visibility::of(&node.vis);
+        self.record_item(node.ident.to_string(), ItemKind::Enum, visibility, node);
+        syn::visit::visit_item_enum(self, node);
+    }
+
+    fn visit_item_const(&mut self, node: &'ast syn::ItemConst) {
+        let visibility = Visibility::of(&node.vis);
+        self.record_item(node.ident.to_string(), ItemKind::Const, visibility, node);
+        syn::visit::visit_item_const(self, node);
+    }
+
+    fn visit_item_static(&mut self, node: &'ast syn::ItemStatic) {
+        let visibility = Visibility::of(&node.vis);
+        self.record_item(node.ident.to_string(), ItemKind::Static, visibility, node);
+        syn::visit::visit_item_static(self, node);
+    }
+
+    fn visit_item_mod(&mut self, node: &'ast syn::ItemMod) {
+        let is_test_module = has_cfg_test(&node.attrs);
+        self.test_module_depth += usize::from(is_test_module);
+        syn::visit::visit_item_mod(self, node);
+        self.test_module_depth -= usize::from(is_test_module);
+    }
+}
+
+/// Extract function spans using the standard `syn` parser (no Verus syntax support)
+pub fn extract_function_spans_with_syn(content: &str) -> Result<Vec<FunctionSpan>, String> {
     let syntax_tree =
-        verus_syn::parse_file(content).map_err(|e| format!("Failed to parse file: {}", e))?;
+        syn::parse_file(content).map_err(|e| format!("Failed to parse file: {}", e))?;
 
-    let mut visitor = FunctionSpanVisitor::new();
-    visitor.visit_file(&syntax_tree);
+    let mut visitor = SynFunctionSpanVisitor {
+        functions: Vec::new(),
+        test_module_depth: 0,
+        enclosing_functions: Vec::new(),
+    };
+    syn::visit::Visit::visit_file(&mut visitor, &syntax_tree);
 
     Ok(visitor.functions)
 }
 
+/// Default line tolerance for [`find_best_match`] - accounts for doc comments
+pub const DEFAULT_MATCH_TOLERANCE: usize = 15;
+
 /// Find the best matching function span for a given function name and approximate line number.
 ///
-/// Uses fuzzy matching with tolerance to account for doc comments which are included
-/// in the function's span by the parser, but SCIP points to the signature line.
+/// Uses fuzzy matching with [`DEFAULT_MATCH_TOLERANCE`] to account for doc comments which are
+/// included in the function's span by the parser, but SCIP points to the signature line.
 pub fn find_best_match<'a>(
     spans: &'a [FunctionSpan],
     name: &str,
     approx_line: usize,
 ) -> Option<&'a FunctionSpan> {
-    // Tolerance for fuzzy matching - accounts for doc comments
-    const TOLERANCE: usize = 15;
+    find_best_match_with_tolerance(spans, name, approx_line, DEFAULT_MATCH_TOLERANCE)
+}
 
-    // First try exact name match
-    let matching: Vec<_> = spans.iter().filter(|s| s.name == name).collect();
+/// Like [`find_best_match`], but with a configurable line tolerance.
+/// Files with very long doc comments may need a larger tolerance.
+///
+/// A qualified `name` such as `outer::helper` only matches that nested function. A plain
+/// name matches nested functions too; of two spans equally close to `approx_line`, the
+/// top-level one wins.
+pub fn find_best_match_with_tolerance<'a>(
+    spans: &'a [FunctionSpan],
+    name: &str,
+    approx_line: usize,
+    tolerance: usize,
+) -> Option<&'a FunctionSpan> {
+    find_best_match_with_quality(spans, name, approx_line, tolerance).map(|(span, _)| span)
+}
 
-    if matching.is_empty() {
-        return None;
-    }
+/// Strip generic arguments and call parentheses from a (display) name, e.g.
+/// `foo<T>` and `foo::<u8>()` become `foo`
+fn base_identifier(name: &str) -> &str {
+    let end = name.find(['<', '(']).unwrap_or(name.len());
+    name[..end].trim().trim_end_matches("::")
+}
 
-    if matching.len() == 1 {
-        return Some(matching[0]);
+/// Spans that are candidates for `name`, in source order: those whose qualified name
+/// is `name` if it contains `::`, otherwise those with that plain name. A name that
+/// matches nothing is retried without its generic arguments (see [`base_identifier`]).
+/// Shared by [`find_all_matches`] and [`find_best_match_with_quality`].
+fn candidate_spans<'a>(spans: &'a [FunctionSpan], name: &str) -> Vec<&'a FunctionSpan> {
+    let matching: Vec<_> = if name.contains("::") {
+        spans.iter().filter(|s| s.qualified_name == name).collect()
+    } else {
+        spans.iter().filter(|s| s.name == name).collect()
+    };
+    let base = base_identifier(name);
+    if matching.is_empty() && !base.is_empty() && base != name {
+        // The name may carry generic arguments the source identifier lacks
+        debug!("No span named {}, retrying as {}", name, base);
+        return candidate_spans(spans, base);
     }
+    matching
+}
 
-    // Multiple matches - find the one closest to the approximate line
-    // First try exact match
-    for span in &matching {
-        if span.start_line == approx_line {
-            return Some(span);
-        }
+/// How [`find_best_match_with_quality`] picked its span
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchQuality {
+    /// The only span with that name
+    Unique,
+    /// Starts exactly at the approximate line
+    ExactLine,
+    /// Closest span within the tolerance of the approximate line, preferring spans
+    /// that start before it
+    WithinTolerance,
+    /// No span was within the tolerance; the closest one was used
+    Closest,
+}
+
+/// Every span named `name` (or qualified as `name`, if it contains `::`), sorted by line,
+/// including functions nested in other bodies. These are the spans
+/// [`find_best_match_with_quality`] chooses from.
+pub fn find_all_matches<'a>(spans: &'a [FunctionSpan], name: &str) -> Vec<&'a FunctionSpan> {
+    let mut matching = candidate_spans(spans, name);
+    matching.sort_by_key(|s| (s.start_line, s.start_col));
+    matching
+}
+
+/// Like [`find_best_match_with_tolerance`], also telling how the span was chosen.
+/// A [`MatchQuality::Closest`] result is the usual suspect when a wrong body is extracted.
+pub fn find_best_match_with_quality<'a>(
+    spans: &'a [FunctionSpan],
+    name: &str,
+    approx_line: usize,
+    tolerance: usize,
+) -> Option<(&'a FunctionSpan, MatchQuality)> {
+    let matching = candidate_spans(spans, name);
+    match matching.as_slice() {
+        [] => return None,
+        [span] => return Some((span, MatchQuality::Unique)),
+        _ => {}
     }
 
-    // Then try within tolerance
-    for span in &matching {
-        let diff = span.start_line.abs_diff(approx_line);
+    // Multiple matches - rank by distance to the approximate line. A nested function
+    // shares its plain name with a top-level one, so nesting only breaks ties (in favor
+    // of the top-level span).
+    let rank = |s: &&&FunctionSpan| {
+        (
+            s.start_line.abs_diff(approx_line),
+            s.qualified_name != s.name,
+            s.start_line,
+        )
+    };
+    if let Some(span) = matching
+        .iter()
+        .filter(|s| s.start_line == approx_line)
+        .min_by_key(rank)
+    {
+        return Some((span, MatchQuality::ExactLine));
+    }
 
-        if diff <= TOLERANCE {
-            return Some(span);
-        }
+    // Then try within tolerance. Doc comments are part of the span but come before the
+    // SCIP signature line, so spans starting before the line are preferred.
+    if let Some(span) = matching
+        .iter()
+        .filter(|s| s.start_line.abs_diff(approx_line) <= tolerance)
+        .min_by_key(|s| (s.start_line > approx_line, rank(s)))
+    {
+        return Some((span, MatchQuality::WithinTolerance));
     }
 
     // Fallback: return the closest one
+    debug!(
+        "No span of {} within {} lines of line {}, using the closest",
+        name, tolerance, approx_line
+    );
     matching
-        .into_iter()
-        .min_by_key(|s| (s.start_line as i64 - approx_line as i64).abs())
+        .iter()
+        .min_by_key(rank)
+        .map(|span| (*span, MatchQuality::Closest))
 }
 
-/// Extract a function body given the file content and span
+/// Line ending used by `content`: `"\r\n"` if it has any CRLF line breaks, otherwise `"\n"`.
+/// Extracted bodies are re-joined with it so they match the source byte-for-byte.
+pub fn line_ending(content: &str) -> &'static str {
+    if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Extract a function body given the file content and span.
+/// The original line ending is preserved (see [`line_ending`]).
 pub fn extract_body_from_span(content: &str, span: &FunctionSpan) -> String {
     let lines: Vec<&str> = content.lines().collect();
 
@@ -217,57 +757,269 @@ pub fn extract_body_from_span(content: &str, span: &FunctionSpan) -> String {
         return String::new();
     }
 
-    lines[start_idx..end_idx].join("\n")
+    lines[start_idx..end_idx].join(line_ending(content))
+}
+
+/// Extract exactly the text covered by a span, honoring its start and end columns.
+/// Unlike [`extract_body_from_span`], this works for items that start or end mid-line.
+pub fn extract_exact_text_from_span(content: &str, span: &FunctionSpan) -> String {
+    if span.start_line == 0 || span.end_line < span.start_line {
+        return String::new();
+    }
+
+    let lines: Vec<&str> = content.lines().collect();
+    let start_idx = span.start_line - 1;
+    let end_idx = span.end_line - 1;
+    if end_idx >= lines.len() {
+        return String::new();
+    }
+
+    let mut text = String::new();
+    for (idx, line) in lines.iter().enumerate().take(end_idx + 1).skip(start_idx) {
+        let from = if idx == start_idx { span.start_col } else { 0 };
+        let to = if idx == end_idx {
+            span.end_col
+        } else {
+            line.chars().count()
+        };
+        if idx > start_idx {
+            text.push_str(line_ending(content));
+        }
+        text.extend(line.chars().skip(from).take(to.saturating_sub(from)));
+    }
+    text
 }
 
-/// Cache for parsed files to avoid re-parsing
+/// Modification time and length of a file, used to detect changes on disk
+#[derive(Debug, Clone, PartialEq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    fn of(file_path: &str) -> Result<Self, String> {
+        let metadata = fs::metadata(file_path)
+            .map_err(|e| format!("Failed to read metadata for {}: {}", file_path, e))?;
+        Ok(FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Cache for parsed files to avoid re-parsing.
+/// Entries are re-parsed when the file's modification time or length changes.
 pub struct FileSpanCache {
-    cache: HashMap<String, Vec<FunctionSpan>>,
+    cache: HashMap<String, (FileStamp, Vec<FunctionSpan>)>,
+    item_macros: Vec<String>,
+    /// Memory-mapped sources, if enabled with [`FileSpanCache::with_mmap`]
+    #[cfg(feature = "mmap")]
+    mapped: Option<HashMap<String, (FileStamp, memmap2::Mmap)>>,
 }
 
 impl FileSpanCache {
     pub fn new() -> Self {
+        Self::with_item_macros(default_item_macros())
+    }
+
+    /// Cache that parses the bodies of `item_macros` as items, see
+    /// [`extract_function_spans_from_content_with_macros`]
+    pub fn with_item_macros(item_macros: Vec<String>) -> Self {
         Self {
             cache: HashMap::new(),
+            item_macros,
+            #[cfg(feature = "mmap")]
+            mapped: None,
         }
     }
 
-    /// Get function spans for a file, parsing it if not already cached
+    /// Read bodies through memory maps of the source files, kept for the lifetime of
+    /// the cache, instead of reading each file again for every body.
+    ///
+    /// A file is re-mapped when its modification time or length changes. As with any
+    /// mmap, truncating a file while it is mapped can crash the process.
+    #[cfg(feature = "mmap")]
+    pub fn with_mmap(mut self) -> Self {
+        self.mapped = Some(HashMap::new());
+        self
+    }
+
+    /// Extract the text of `span` from `file_path`. After [`FileSpanCache::with_mmap`]
+    /// the file is memory-mapped on first use and the map is reused by later calls;
+    /// otherwise the file is read from disk on every call.
+    pub fn read_body(&mut self, file_path: &str, span: &FunctionSpan) -> Result<String, String> {
+        #[cfg(feature = "mmap")]
+        if let Some(mapped) = &mut self.mapped {
+            let stamp = FileStamp::of(file_path)?;
+            let is_fresh = matches!(mapped.get(file_path), Some((cached, _)) if *cached == stamp);
+            if !is_fresh {
+                let file = fs::File::open(file_path)
+                    .map_err(|e| format!("Failed to open file {}: {}", file_path, e))?;
+                // SAFETY: the map is only read, but it is only sound while nobody
+                // truncates or rewrites the file: reading a truncated mapping faults
+                // (SIGBUS) and a concurrent write changes the bytes under the `&str`
+                // below. The sources are treated as read-only for the duration of a
+                // run, which is what the atomizer is built around (it indexes a
+                // checkout nothing else writes to); the stamp check only re-maps files
+                // changed between calls, it cannot guard against changes during one.
+                let mmap = unsafe { memmap2::Mmap::map(&file) }
+                    .map_err(|e| format!("Failed to map file {}: {}", file_path, e))?;
+                mapped.insert(file_path.to_string(), (stamp, mmap));
+            }
+            let content = std::str::from_utf8(&mapped[file_path].1)
+                .map_err(|e| format!("File {} is not valid UTF-8: {}", file_path, e))?;
+            return Ok(extract_body_from_span(content, span));
+        }
+        read_body(file_path, span)
+    }
+
+    /// Get function spans for a file, parsing it if not cached or changed on disk
     pub fn get_spans(&mut self, file_path: &str) -> Result<&Vec<FunctionSpan>, String> {
-        if !self.cache.contains_key(file_path) {
+        let stamp = FileStamp::of(file_path)?;
+        let is_fresh = matches!(self.cache.get(file_path), Some((cached, _)) if *cached == stamp);
+        if !is_fresh {
             debug!("Parsing file with verus_syn: {}", file_path);
-            let spans = extract_function_spans(file_path)?;
+            let spans = extract_function_spans_with_macros(file_path, &self.item_macros)?;
             debug!("Found {} functions in {}", spans.len(), file_path);
-            self.cache.insert(file_path.to_string(), spans);
+            self.cache.insert(file_path.to_string(), (stamp, spans));
         }
-        Ok(self.cache.get(file_path).unwrap())
+        Ok(&self.cache.get(file_path).unwrap().1)
     }
 
-    /// Find a function body given file path, function name, and approximate line
-    pub fn get_function_body(
+    /// Drop the cached spans for a file so the next lookup re-parses it
+    pub fn invalidate(&mut self, file_path: &str) {
+        self.cache.remove(file_path);
+        #[cfg(feature = "mmap")]
+        if let Some(mapped) = &mut self.mapped {
+            mapped.remove(file_path);
+        }
+    }
+
+    /// Find the span of a function given file path, function name, approximate line
+    /// and line tolerance (see [`find_best_match_with_tolerance`])
+    pub fn find_function(
         &mut self,
         file_path: &str,
         function_name: &str,
         approx_line: usize,
-    ) -> Result<Option<String>, String> {
+        tolerance: usize,
+    ) -> Result<Option<FunctionSpan>, String> {
         let spans = self.get_spans(file_path)?;
+        Ok(find_best_match_with_tolerance(spans, function_name, approx_line, tolerance).cloned())
+    }
 
-        if let Some(span) = find_best_match(spans, function_name, approx_line) {
-            let content = fs::read_to_string(file_path)
-                .map_err(|e| format!("Failed to read file {}: {}", file_path, e))?;
-            Ok(Some(extract_body_from_span(&content, span)))
-        } else {
-            Ok(None)
+    /// Find a function body given file path, function name, approximate line and
+    /// line tolerance (see [`find_best_match_with_tolerance`])
+    pub fn get_function_body(
+        &mut self,
+        file_path: &str,
+        function_name: &str,
+        approx_line: usize,
+        tolerance: usize,
+    ) -> Result<Option<String>, String> {
+        match self.find_function(file_path, function_name, approx_line, tolerance)? {
+            Some(span) => self.read_body(file_path, &span).map(Some),
+            None => Ok(None),
         }
     }
 }
 
+/// Read `file_path` and extract the text of `span` (see [`extract_body_from_span`])
+pub fn read_body(file_path: &str, span: &FunctionSpan) -> Result<String, String> {
+    let content = fs::read_to_string(file_path)
+        .map_err(|e| format!("Failed to read file {}: {}", file_path, e))?;
+    Ok(extract_body_from_span(&content, span))
+}
+
 impl Default for FileSpanCache {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Cached spans of a single file, locked while the file is being parsed
+type SpanSlot = Arc<Mutex<Option<(FileStamp, Arc<Vec<FunctionSpan>>)>>>;
+
+impl Default for SharedFileSpanCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Thread-safe variant of [`FileSpanCache`] for parallel body extraction.
+///
+/// Cloning is cheap and clones share the same cache. Each file has its own lock, held while
+/// parsing, so concurrent lookups of the same uncached file parse it only once.
+#[derive(Clone)]
+pub struct SharedFileSpanCache {
+    slots: Arc<Mutex<HashMap<String, SpanSlot>>>,
+    item_macros: Arc<[String]>,
+}
+
+impl SharedFileSpanCache {
+    pub fn new() -> Self {
+        Self::with_item_macros(default_item_macros())
+    }
+
+    /// Cache that parses the bodies of `item_macros` as items, as
+    /// [`FileSpanCache::with_item_macros`]
+    pub fn with_item_macros(item_macros: Vec<String>) -> Self {
+        Self {
+            slots: Arc::default(),
+            item_macros: item_macros.into(),
+        }
+    }
+
+    fn slot(&self, file_path: &str) -> SpanSlot {
+        let mut slots = self.slots.lock().unwrap();
+        slots.entry(file_path.to_string()).or_default().clone()
+    }
+
+    /// Get function spans for a file, parsing it if not cached or changed on disk
+    pub fn get_spans(&self, file_path: &str) -> Result<Arc<Vec<FunctionSpan>>, String> {
+        let stamp = FileStamp::of(file_path)?;
+        let slot = self.slot(file_path);
+        let mut entry = slot.lock().unwrap();
+        if let Some((cached, spans)) = entry.as_ref() {
+            if *cached == stamp {
+                return Ok(spans.clone());
+            }
+        }
+
+        debug!("Parsing file with verus_syn: {}", file_path);
+        let spans = Arc::new(extract_function_spans_with_macros(
+            file_path,
+            &self.item_macros,
+        )?);
+        debug!("Found {} functions in {}", spans.len(), file_path);
+        *entry = Some((stamp, spans.clone()));
+        Ok(spans)
+    }
+
+    /// Drop the cached spans for a file so the next lookup re-parses it
+    pub fn invalidate(&self, file_path: &str) {
+        self.slots.lock().unwrap().remove(file_path);
+    }
+
+    /// Find a function body given file path, function name, approximate line and
+    /// line tolerance (see [`find_best_match_with_tolerance`])
+    pub fn get_function_body(
+        &self,
+        file_path: &str,
+        function_name: &str,
+        approx_line: usize,
+        tolerance: usize,
+    ) -> Result<Option<String>, String> {
+        let spans = self.get_spans(file_path)?;
+
+        match find_best_match_with_tolerance(&spans, function_name, approx_line, tolerance) {
+            Some(span) => read_body(file_path, span).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,9 +1054,73 @@ impl Foo {
 }
 "#;
         let spans = extract_function_spans_from_content(code).unwrap();
-        assert_eq!(spans.len(), 2);
-        assert_eq!(spans[0].name, "bar");
-        assert_eq!(spans[1].name, "baz");
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].name, "Foo");
+        assert_eq!(spans[0].kind, ItemKind::Struct);
+        assert_eq!(spans[1].name, "bar");
+        assert_eq!(spans[2].name, "baz");
+    }
+
+    #[test]
+    fn test_test_function_detection() {
+        let code = r#"
+fn production() {}
+
+#[test]
+fn unit() {}
+
+#[tokio::test]
+async fn async_unit() {}
+
+#[cfg(test)]
+mod tests {
+    fn helper() {}
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        let is_test = |name: &str| spans.iter().find(|s| s.name == name).unwrap().is_test;
+        assert!(!is_test("production"));
+        assert!(is_test("unit"));
+        assert!(is_test("async_unit"));
+        assert!(is_test("helper"));
+    }
+
+    #[test]
+    fn test_visibility() {
+        let code = r#"
+pub fn public() {}
+pub(crate) fn crate_visible() {}
+pub(super) fn parent_visible() {}
+pub(in crate::a) fn path_visible() {}
+fn private() {}
+pub(crate) struct Point;
+trait Shape {
+    fn area(&self) -> u32;
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        let visibility = |name: &str| spans.iter().find(|s| s.name == name).unwrap().visibility;
+        assert_eq!(visibility("public"), Visibility::Public);
+        assert_eq!(visibility("crate_visible"), Visibility::Crate);
+        assert_eq!(visibility("parent_visible"), Visibility::Restricted);
+        assert_eq!(visibility("path_visible"), Visibility::Restricted);
+        assert_eq!(visibility("private"), Visibility::Private);
+        assert_eq!(visibility("Point"), Visibility::Crate);
+        assert_eq!(visibility("area"), Visibility::Public);
+    }
+
+    #[test]
+    fn test_function_qualifiers() {
+        let code = r#"
+async unsafe fn fetch() {}
+const fn zero() -> u32 { 0 }
+fn plain() {}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        let span = |name: &str| spans.iter().find(|s| s.name == name).unwrap();
+        assert!(span("fetch").is_async && span("fetch").is_unsafe && !span("fetch").is_const);
+        assert!(span("zero").is_const && !span("zero").is_async && !span("zero").is_unsafe);
+        assert!(!span("plain").is_async && !span("plain").is_unsafe && !span("plain").is_const);
     }
 
     #[test]
@@ -314,16 +1130,19 @@ impl Foo {
                 name: "foo".to_string(),
                 start_line: 10,
                 end_line: 20,
+                ..Default::default()
             },
             FunctionSpan {
                 name: "foo".to_string(),
                 start_line: 100,
                 end_line: 110,
+                ..Default::default()
             },
             FunctionSpan {
                 name: "bar".to_string(),
                 start_line: 50,
                 end_line: 60,
+                ..Default::default()
             },
         ];
 
@@ -346,4 +1165,456 @@ impl Foo {
         let result = find_best_match(&spans, "nonexistent", 50);
         assert!(result.is_none());
     }
+
+    #[test]
+    fn test_verus_contract_clauses() {
+        let code = r#"
+verus! {
+fn countdown(n: u64) -> (r: u64)
+    requires
+        n > 0,
+        n < 100,
+    ensures
+        r == 0,
+    decreases n,
+{
+    0
+}
+
+fn plain() {}
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].name, "countdown");
+        assert_eq!(spans[0].requires, vec!["n > 0", "n < 100"]);
+        assert_eq!(spans[0].ensures, vec!["r == 0"]);
+        assert_eq!(spans[0].decreases.as_deref(), Some("n"));
+
+        assert_eq!(spans[1].name, "plain");
+        assert!(spans[1].requires.is_empty());
+        assert!(spans[1].ensures.is_empty());
+        assert!(spans[1].decreases.is_none());
+    }
+
+    #[test]
+    fn test_verus_function_modes() {
+        let code = r#"
+verus! {
+spec fn double(x: int) -> int {
+    x * 2
+}
+
+proof fn lemma_double(x: int)
+    ensures double(x) == x + x,
+{
+}
+
+exec fn run() {}
+
+fn plain() {}
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        let modes: Vec<_> = spans.iter().map(|s| (s.name.as_str(), s.mode)).collect();
+        assert_eq!(
+            modes,
+            vec![
+                ("double", FunctionMode::Spec),
+                ("lemma_double", FunctionMode::Proof),
+                ("run", FunctionMode::Exec),
+                ("plain", FunctionMode::Exec),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_best_match_tolerance() {
+        // The correct overload has 20 lines of doc comments, so its span starts
+        // 20 lines before the SCIP signature line; another overload is 18 lines after.
+        let spans = vec![
+            FunctionSpan {
+                name: "foo".to_string(),
+                start_line: 10,
+                end_line: 35,
+                ..Default::default()
+            },
+            FunctionSpan {
+                name: "foo".to_string(),
+                start_line: 48,
+                end_line: 55,
+                ..Default::default()
+            },
+        ];
+
+        let result = find_best_match(&spans, "foo", 30).unwrap();
+        assert_eq!(result.start_line, 48);
+
+        let result = find_best_match_with_tolerance(&spans, "foo", 30, 25).unwrap();
+        assert_eq!(result.start_line, 10);
+    }
+
+    #[test]
+    fn test_find_all_matches_and_quality() {
+        let span = |name: &str, start_line: usize| FunctionSpan {
+            name: name.to_string(),
+            start_line,
+            end_line: start_line + 5,
+            ..Default::default()
+        };
+        let spans = vec![span("foo", 100), span("bar", 50), span("foo", 10)];
+
+        let lines: Vec<_> = find_all_matches(&spans, "foo")
+            .iter()
+            .map(|s| s.start_line)
+            .collect();
+        assert_eq!(lines, vec![10, 100]);
+        assert!(find_all_matches(&spans, "baz").is_empty());
+        // Both paths see the same candidates, generic arguments included
+        assert_eq!(find_all_matches(&spans, "foo::<u8>").len(), 2);
+
+        let quality = |name: &str, line: usize| {
+            find_best_match_with_quality(&spans, name, line, DEFAULT_MATCH_TOLERANCE)
+                .map(|(s, q)| (s.start_line, q))
+        };
+        assert_eq!(quality("bar", 1), Some((50, MatchQuality::Unique)));
+        assert_eq!(quality("foo", 100), Some((100, MatchQuality::ExactLine)));
+        assert_eq!(
+            quality("foo", 20),
+            Some((10, MatchQuality::WithinTolerance))
+        );
+        assert_eq!(quality("foo", 70), Some((100, MatchQuality::Closest)));
+        assert_eq!(quality("baz", 70), None);
+        assert_eq!(
+            quality("foo::<u8>", 100),
+            Some((100, MatchQuality::ExactLine))
+        );
+    }
+
+    #[test]
+    fn test_signature_text() {
+        let code = r#"
+/// Doc comment
+pub async unsafe fn pick<T: Clone, const N: usize>(items: &[T; N], index: usize) -> Option<T>
+where
+    T: Default,
+{
+    items.get(index).cloned()
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        assert_eq!(
+            spans[0].signature,
+            "async unsafe fn pick < T : Clone , const N : usize > (items : & [T ; N] , index : usize) -> Option < T > where T : Default ,"
+        );
+    }
+
+    #[test]
+    fn test_verus_signature_text() {
+        let code = r#"
+verus! {
+fn f(x: u32) -> bool
+    requires
+        x > 0,
+{
+    true
+}
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        assert_eq!(spans[0].signature, "fn f (x : u32) -> bool");
+        assert_eq!(spans[0].requires, vec!["x > 0"]);
+    }
+
+    #[test]
+    fn test_generic_name_matching() {
+        let code = r#"
+struct Wrapper<T>(T);
+
+impl<T: Clone> Wrapper<T> {
+    fn foo(&self) -> T {
+        self.0.clone()
+    }
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        for name in ["foo", "foo<T>", "foo::<u8>", "foo<T>()"] {
+            let span = find_best_match(&spans, name, 5).unwrap();
+            assert_eq!((span.name.as_str(), span.start_line), ("foo", 5), "{name}");
+        }
+        assert!(find_best_match(&spans, "<T>", 5).is_none());
+        assert!(find_best_match(&spans, "bar<T>", 5).is_none());
+    }
+
+    #[test]
+    fn test_span_columns() {
+        let code = "struct S; impl S { fn inline(&self) -> u8 { 1 } }\n";
+        let spans = extract_function_spans_from_content(code).unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].start_col, spans[0].end_col), (0, 9));
+        assert_eq!(spans[1].start_line, 1);
+        assert_eq!(spans[1].start_col, 19);
+        assert_eq!(spans[1].end_col, 47);
+        assert_eq!(
+            extract_exact_text_from_span(code, &spans[1]),
+            "fn inline(&self) -> u8 { 1 }"
+        );
+    }
+
+    #[test]
+    fn test_cache_reparses_changed_file() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        fs::write(path, "fn first() {}\n").unwrap();
+
+        let mut cache = FileSpanCache::new();
+        let names: Vec<_> = cache
+            .get_spans(path)
+            .unwrap()
+            .iter()
+            .map(|s| s.name.clone())
+            .collect();
+        assert_eq!(names, vec!["first"]);
+
+        fs::write(path, "fn first() {}\n\nfn second() {}\n").unwrap();
+        let names: Vec<_> = cache
+            .get_spans(path)
+            .unwrap()
+            .iter()
+            .map(|s| s.name.clone())
+            .collect();
+        assert_eq!(names, vec!["first", "second"]);
+
+        cache.invalidate(path);
+        assert_eq!(cache.get_spans(path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_shared_cache_parses_once() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        fs::write(path, "fn a() {}\n\nfn b() {\n    a();\n}\n").unwrap();
+
+        let cache = SharedFileSpanCache::new();
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    let cache = cache.clone();
+                    scope.spawn(move || cache.get_spans(path).unwrap())
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(results.iter().all(|spans| Arc::ptr_eq(spans, &results[0])));
+        assert_eq!(results[0].len(), 2);
+
+        let body = cache
+            .get_function_body(path, "b", 3, DEFAULT_MATCH_TOLERANCE)
+            .unwrap();
+        assert_eq!(body.as_deref(), Some("fn b() {\n    a();\n}"));
+    }
+
+    #[test]
+    fn test_shared_cache_item_macros() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        fs::write(path, "my_items! {\nfn wrapped() {}\n}\n").unwrap();
+
+        assert!(SharedFileSpanCache::new()
+            .get_spans(path)
+            .unwrap()
+            .is_empty());
+        let cache = SharedFileSpanCache::with_item_macros(vec!["my_items".to_string()]);
+        let body = cache
+            .get_function_body(path, "wrapped", 2, DEFAULT_MATCH_TOLERANCE)
+            .unwrap();
+        assert_eq!(body.as_deref(), Some("fn wrapped() {}"));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap_bodies() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        fs::write(path, "fn a() {\n    1\n}\n").unwrap();
+
+        let mut cache = FileSpanCache::new().with_mmap();
+        let body = cache
+            .get_function_body(path, "a", 1, DEFAULT_MATCH_TOLERANCE)
+            .unwrap();
+        assert_eq!(body.as_deref(), Some("fn a() {\n    1\n}"));
+
+        // A changed file is re-mapped
+        fs::write(path, "fn a() { 22 }\n").unwrap();
+        let body = cache
+            .get_function_body(path, "a", 1, DEFAULT_MATCH_TOLERANCE)
+            .unwrap();
+        assert_eq!(body.as_deref(), Some("fn a() { 22 }"));
+    }
+
+    #[test]
+    fn test_struct_and_enum_spans() {
+        let code = r#"
+#[derive(Clone)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+enum Shape {
+    Dot(Point),
+    Line(Point, Point),
+}
+
+fn origin() -> Point {
+    Point { x: 0, y: 0 }
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        let items: Vec<_> = spans
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind, s.start_line, s.end_line))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                ("Point", ItemKind::Struct, 2, 6),
+                ("Shape", ItemKind::Enum, 8, 11),
+                ("origin", ItemKind::Function, 13, 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_item_macros() {
+        let code = r#"
+my_items! {
+    fn wrapped() {}
+}
+
+verus! {
+    fn verified() {}
+}
+"#;
+        let names = |spans: Vec<FunctionSpan>| -> Vec<String> {
+            spans.into_iter().map(|s| s.name).collect()
+        };
+        let spans = extract_function_spans_from_content(code).unwrap();
+        assert_eq!(names(spans), vec!["verified"]);
+
+        let item_macros = vec!["verus".to_string(), "my_items".to_string()];
+        let spans = extract_function_spans_from_content_with_macros(code, &item_macros).unwrap();
+        assert_eq!(names(spans), vec!["wrapped", "verified"]);
+    }
+
+    #[test]
+    fn test_nested_functions() {
+        let code = r#"
+fn outer() -> u32 {
+    fn helper() -> u32 {
+        1
+    }
+    helper()
+}
+
+/// Top-level helper, documented
+fn helper() -> u32 {
+    2
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        let names: Vec<_> = spans.iter().map(|s| s.qualified_name.as_str()).collect();
+        assert_eq!(names, vec!["outer", "outer::helper", "helper"]);
+
+        // A plain name resolves to whichever span is closest to the SCIP line
+        let top_level = find_best_match(&spans, "helper", 10).unwrap();
+        assert_eq!(
+            (top_level.qualified_name.as_str(), top_level.start_line),
+            ("helper", 9)
+        );
+        let nested = find_best_match(&spans, "helper", 3).unwrap();
+        assert_eq!(
+            (nested.qualified_name.as_str(), nested.start_line),
+            ("outer::helper", 3)
+        );
+
+        let nested = find_best_match(&spans, "outer::helper", 10).unwrap();
+        assert_eq!(nested.start_line, 3);
+        assert_eq!(nested.name, "helper");
+    }
+
+    #[test]
+    fn test_const_and_static_spans() {
+        let code = r#"
+const N: usize = 8;
+
+pub static GREETING: &str = "hello";
+
+fn buffer() -> [u8; N] {
+    [0; N]
+}
+"#;
+        let spans = extract_function_spans_from_content(code).unwrap();
+        let items: Vec<_> = spans
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind, s.start_line, s.end_line))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                ("N", ItemKind::Const, 2, 2),
+                ("GREETING", ItemKind::Static, 4, 4),
+                ("buffer", ItemKind::Function, 6, 8),
+            ]
+        );
+        assert_eq!(spans[1].visibility, Visibility::Public);
+        let n = find_best_match(&spans, "N", 2).unwrap();
+        assert_eq!(extract_body_from_span(code, n), "const N: usize = 8;");
+    }
+
+    #[test]
+    fn test_crlf_line_endings() {
+        let content = "fn first() {\r\n    1\r\n}\r\n\r\nfn second() {}\r\n";
+        let spans = extract_function_spans_from_content(content).unwrap();
+        let first = find_best_match(&spans, "first", 1).unwrap();
+        assert_eq!(
+            extract_body_from_span(content, first),
+            "fn first() {\r\n    1\r\n}"
+        );
+        assert_eq!(
+            extract_exact_text_from_span(content, first),
+            "fn first() {\r\n    1\r\n}"
+        );
+        let second = find_best_match(&spans, "second", 5).unwrap();
+        assert_eq!(extract_body_from_span(content, second), "fn second() {}");
+    }
+
+    #[test]
+    fn test_syn_fallback() {
+        // `assert` is a keyword for verus_syn, but a plain identifier in Rust
+        let code = r#"
+fn assert(condition: bool, message: &str) {
+    if !condition {
+        panic!("{}", message);
+    }
+}
+
+fn check() {
+    assert(1 + 1 == 2, "math");
+}
+"#;
+        let spans = extract_function_spans_with_syn(code).unwrap();
+        let names: Vec<_> = spans
+            .iter()
+            .map(|s| (s.name.as_str(), s.start_line))
+            .collect();
+        assert_eq!(names, vec![("assert", 2), ("check", 8)]);
+
+        let spans = extract_function_spans_from_content(code).unwrap();
+        let names: Vec<_> = spans
+            .iter()
+            .map(|s| (s.name.as_str(), s.start_line))
+            .collect();
+        assert_eq!(names, vec![("assert", 2), ("check", 8)]);
+    }
 }